@@ -0,0 +1,185 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use thiserror::Error;
+
+#[cfg(feature = "program")]
+pub mod processor;
+
+#[cfg(feature = "program")]
+pub use processor::process_instruction;
+
+solana_program::declare_id!("EYWdWGpF7NaPQnqbVe3sqL1vMwhGNcbvujhhcSEYafmW");
+
+#[cfg(all(feature = "program", not(feature = "no-entrypoint")))]
+solana_program::entrypoint!(process_instruction);
+
+pub const ESCROW_SEED: &[u8] = b"escrow";
+
+/// Errors of the escrow program, returned as `ProgramError::Custom(code)`
+#[derive(Clone, Copy, Debug, Error, PartialEq, Eq)]
+pub enum EscrowError {
+    #[error("escrow account is already initialized")]
+    AlreadyInitialized = 0,
+    #[error("escrow account is not initialized")]
+    Uninitialized = 1,
+    #[error("escrow account doesn't derive from the maker, id and bump")]
+    InvalidEscrowAddress = 2,
+    #[error("bump is not the canonical bump of the escrow")]
+    NonCanonicalBump = 3,
+    #[error("signer is not the maker of this escrow")]
+    InvalidMaker = 4,
+    #[error("escrow is already funded")]
+    AlreadyFunded = 5,
+    #[error("escrow is not funded yet")]
+    NotFunded = 6,
+    #[error("vault is not the escrow's associated token account")]
+    InvalidVault = 7,
+    #[error("mint doesn't match the escrow")]
+    InvalidMint = 8,
+    #[error("token account doesn't belong to the maker")]
+    InvalidTokenAccount = 9,
+    #[error("amounts must be greater than zero")]
+    ZeroAmount = 10,
+}
+
+impl EscrowError {
+    /// The error behind a `ProgramError::Custom` code, e.g. from a failed transaction
+    pub fn from_code(code: u32) -> Option<Self> {
+        Some(match code {
+            0 => EscrowError::AlreadyInitialized,
+            1 => EscrowError::Uninitialized,
+            2 => EscrowError::InvalidEscrowAddress,
+            3 => EscrowError::NonCanonicalBump,
+            4 => EscrowError::InvalidMaker,
+            5 => EscrowError::AlreadyFunded,
+            6 => EscrowError::NotFunded,
+            7 => EscrowError::InvalidVault,
+            8 => EscrowError::InvalidMint,
+            9 => EscrowError::InvalidTokenAccount,
+            10 => EscrowError::ZeroAmount,
+            _ => return None,
+        })
+    }
+}
+
+impl From<EscrowError> for ProgramError {
+    fn from(error: EscrowError) -> Self {
+        ProgramError::Custom(error as u32)
+    }
+}
+
+/// What one side of the escrow puts in
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Asset {
+    Sol { lamports: u64 },
+    Token { mint: Pubkey, amount: u64 },
+}
+
+impl Asset {
+    /// Largest borsh encoding, of `Asset::Token`
+    pub const LEN: usize = 1 + 32 + 8;
+
+    /// Lamports or token base units, by the kind of asset
+    pub fn amount(&self) -> u64 {
+        match self {
+            Asset::Sol { lamports } => *lamports,
+            Asset::Token { amount, .. } => *amount,
+        }
+    }
+}
+
+/// Escrow state, stored in the PDA of `[ESCROW_SEED, maker, id]`
+///
+/// SOL deposits sit in the escrow account itself, token deposits in the escrow's
+/// associated token account, the vault.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Escrow {
+    pub is_initialized: bool,
+    pub maker: Pubkey,
+    pub id: u64,
+    pub bump: u8,
+    pub funded: bool,
+    /// Offered by the maker
+    pub deposit: Asset,
+    /// Asked from the taker
+    pub receive: Asset,
+}
+
+impl Escrow {
+    pub const LEN: usize = 1 + 32 + 8 + 1 + 1 + Asset::LEN + Asset::LEN;
+}
+
+/// Instructions of the escrow program, borsh encoded
+///
+/// Token assets take four extra accounts each, in the order the instructions list them:
+/// the token account the tokens leave, the one they go to, the mint and its token program.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub enum EscrowInstruction {
+    /// Create the escrow account, `bump` must be the canonical bump
+    ///
+    /// Accounts required
+    /// 1. [signer, writable] Maker
+    /// 2. [writable] Escrow PDA
+    /// 3. [] System program
+    Initialize {
+        id: u64,
+        bump: u8,
+        deposit: Asset,
+        receive: Asset,
+    },
+    /// Move the maker's deposit into the escrow
+    ///
+    /// Accounts required
+    /// 1. [signer, writable] Maker
+    /// 2. [writable] Escrow PDA
+    /// 3. [] System program
+    /// 4. [writable] Maker token account, for token deposits
+    /// 5. [writable] Vault
+    /// 6. [] Deposit mint
+    /// 7. [] Deposit token program
+    Deposit,
+    /// Pay the maker and take the deposit, closes the escrow
+    ///
+    /// A token deposit is paid out with whatever the vault holds, which a transfer fee
+    /// or tokens sent to the vault make differ from the deposited amount.
+    ///
+    /// Accounts required
+    /// 1. [signer, writable] Taker
+    /// 2. [writable] Maker
+    /// 3. [writable] Escrow PDA
+    /// 4. [] System program
+    /// 5. [writable] Vault, for token deposits
+    /// 6. [writable] Taker token account receiving the deposit
+    /// 7. [writable] Deposit mint, takes the vault's withheld transfer fees
+    /// 8. [] Deposit token program
+    /// 9. [writable] Taker token account paying the maker, for token payments
+    /// 10. [writable] Maker token account receiving the payment
+    /// 11. [] Receive mint
+    /// 12. [] Receive token program
+    Exchange,
+    /// Refund the deposit to the maker, closes the escrow
+    ///
+    /// Accounts required
+    /// 1. [signer, writable] Maker
+    /// 2. [writable] Escrow PDA
+    /// 3. [writable] Vault, for token deposits
+    /// 4. [writable] Maker token account
+    /// 5. [writable] Deposit mint, takes the vault's withheld transfer fees
+    /// 6. [] Deposit token program
+    Cancel,
+}
+
+pub fn escrow_seeds<'a>(maker: &'a Pubkey, id: &'a [u8; 8]) -> [&'a [u8]; 3] {
+    [ESCROW_SEED, maker.as_ref(), id]
+}
+
+pub fn find_escrow_address(maker: &Pubkey, id: u64, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&escrow_seeds(maker, &id.to_le_bytes()), program_id)
+}
+
+/// The token account holding a token deposit
+pub fn vault_address(escrow: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    get_associated_token_address_with_program_id(escrow, mint, token_program)
+}
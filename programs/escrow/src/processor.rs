@@ -0,0 +1,829 @@
+use crate::{
+    Asset, ESCROW_SEED, Escrow, EscrowError, EscrowInstruction, escrow_seeds, vault_address,
+};
+use borsh::{BorshDeserialize, BorshSerialize};
+use program_utils::create_pda_account;
+use solana_program::account_info::{AccountInfo, next_account_info};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::msg;
+use solana_program::program::{invoke, invoke_signed};
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::system_instruction;
+use solana_program::system_program;
+use spl_token_2022::extension::transfer_fee::TransferFeeAmount;
+use spl_token_2022::extension::transfer_fee::instruction::harvest_withheld_tokens_to_mint;
+use spl_token_2022::extension::{BaseStateWithExtensions, StateWithExtensions};
+use spl_token_2022::instruction::{close_account, transfer_checked};
+use spl_token_2022::state::{Account as TokenAccount, Mint};
+
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let instruction = EscrowInstruction::try_from_slice(instruction_data)
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+    match instruction {
+        EscrowInstruction::Initialize {
+            id,
+            bump,
+            deposit,
+            receive,
+        } => {
+            msg!("Instruction: Initialize");
+            process_initialize(program_id, accounts, id, bump, deposit, receive)
+        }
+        EscrowInstruction::Deposit => {
+            msg!("Instruction: Deposit");
+            process_deposit(program_id, accounts)
+        }
+        EscrowInstruction::Exchange => {
+            msg!("Instruction: Exchange");
+            process_exchange(program_id, accounts)
+        }
+        EscrowInstruction::Cancel => {
+            msg!("Instruction: Cancel");
+            process_cancel(program_id, accounts)
+        }
+    }
+}
+
+fn process_initialize(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    id: u64,
+    bump: u8,
+    deposit: Asset,
+    receive: Asset,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let maker = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+
+    if !maker.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *system_program_account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if deposit.amount() == 0 || receive.amount() == 0 {
+        return Err(EscrowError::ZeroAmount.into());
+    }
+
+    // the client passes the bump, check it before signing with it
+    let id_bytes = id.to_le_bytes();
+    let seeds = escrow_seeds(maker.key, &id_bytes);
+    let expected = Pubkey::create_program_address(&[&seeds[..], &[&[bump]]].concat(), program_id)
+        .map_err(|_| EscrowError::InvalidEscrowAddress)?;
+    if *escrow_account.key != expected {
+        return Err(EscrowError::InvalidEscrowAddress.into());
+    }
+    let (_, canonical) = Pubkey::find_program_address(&seeds, program_id);
+    if bump != canonical {
+        return Err(EscrowError::NonCanonicalBump.into());
+    }
+    if escrow_account.owner == program_id {
+        return Err(EscrowError::AlreadyInitialized.into());
+    }
+
+    create_pda_account(
+        maker,
+        escrow_account,
+        system_program_account,
+        Escrow::LEN,
+        0,
+        program_id,
+        &[ESCROW_SEED, maker.key.as_ref(), &id_bytes, &[bump]],
+    )?;
+
+    let escrow = Escrow {
+        is_initialized: true,
+        maker: *maker.key,
+        id,
+        bump,
+        funded: false,
+        deposit,
+        receive,
+    };
+    escrow.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
+    Ok(())
+}
+
+fn process_deposit(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let maker = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+
+    let mut escrow = load_escrow(program_id, escrow_account)?;
+    check_maker(&escrow, maker)?;
+    if escrow.funded {
+        return Err(EscrowError::AlreadyFunded.into());
+    }
+
+    match escrow.deposit {
+        Asset::Sol { lamports } => invoke(
+            &system_instruction::transfer(maker.key, escrow_account.key, lamports),
+            &[
+                maker.clone(),
+                escrow_account.clone(),
+                system_program_account.clone(),
+            ],
+        )?,
+        Asset::Token { mint, amount } => {
+            let maker_token = next_account_info(account_info_iter)?;
+            let vault = next_account_info(account_info_iter)?;
+            let mint_account = next_account_info(account_info_iter)?;
+            let token_program = next_account_info(account_info_iter)?;
+            let decimals = check_mint(&mint, mint_account, token_program)?;
+            check_vault(escrow_account, vault, mint_account, token_program)?;
+
+            invoke(
+                &transfer_checked(
+                    token_program.key,
+                    maker_token.key,
+                    mint_account.key,
+                    vault.key,
+                    maker.key,
+                    &[],
+                    amount,
+                    decimals,
+                )?,
+                &[
+                    maker_token.clone(),
+                    mint_account.clone(),
+                    vault.clone(),
+                    maker.clone(),
+                ],
+            )?;
+        }
+    }
+
+    escrow.funded = true;
+    escrow.serialize(&mut &mut escrow_account.data.borrow_mut()[..])?;
+    Ok(())
+}
+
+fn process_exchange(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let taker = next_account_info(account_info_iter)?;
+    let maker = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+
+    if !taker.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let escrow = load_escrow(program_id, escrow_account)?;
+    if *maker.key != escrow.maker {
+        return Err(EscrowError::InvalidMaker.into());
+    }
+    if !escrow.funded {
+        return Err(EscrowError::NotFunded.into());
+    }
+
+    // the deposit goes to the taker
+    match escrow.deposit {
+        Asset::Sol { lamports } => transfer_lamports(escrow_account, taker, lamports)?,
+        Asset::Token { mint, .. } => {
+            let vault = next_account_info(account_info_iter)?;
+            let taker_token = next_account_info(account_info_iter)?;
+            let mint_account = next_account_info(account_info_iter)?;
+            let token_program = next_account_info(account_info_iter)?;
+            let decimals = check_mint(&mint, mint_account, token_program)?;
+            check_vault(escrow_account, vault, mint_account, token_program)?;
+            release_vault(
+                &escrow,
+                escrow_account,
+                vault,
+                taker_token,
+                mint_account,
+                token_program,
+                maker,
+                decimals,
+            )?;
+        }
+    }
+
+    // the payment goes to the maker
+    match escrow.receive {
+        Asset::Sol { lamports } => invoke(
+            &system_instruction::transfer(taker.key, maker.key, lamports),
+            &[taker.clone(), maker.clone(), system_program_account.clone()],
+        )?,
+        Asset::Token { mint, amount } => {
+            let taker_token = next_account_info(account_info_iter)?;
+            let maker_token = next_account_info(account_info_iter)?;
+            let mint_account = next_account_info(account_info_iter)?;
+            let token_program = next_account_info(account_info_iter)?;
+            let decimals = check_mint(&mint, mint_account, token_program)?;
+            // otherwise the taker could pay into an account of their own
+            let destination =
+                StateWithExtensions::<TokenAccount>::unpack(&maker_token.data.borrow())?.base;
+            if destination.owner != escrow.maker || destination.mint != mint {
+                return Err(EscrowError::InvalidTokenAccount.into());
+            }
+
+            invoke(
+                &transfer_checked(
+                    token_program.key,
+                    taker_token.key,
+                    mint_account.key,
+                    maker_token.key,
+                    taker.key,
+                    &[],
+                    amount,
+                    decimals,
+                )?,
+                &[
+                    taker_token.clone(),
+                    mint_account.clone(),
+                    maker_token.clone(),
+                    taker.clone(),
+                ],
+            )?;
+        }
+    }
+
+    close_escrow(escrow_account, maker)
+}
+
+fn process_cancel(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let maker = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+
+    let escrow = load_escrow(program_id, escrow_account)?;
+    check_maker(&escrow, maker)?;
+
+    // a SOL deposit is refunded by closing the escrow
+    if let (true, Asset::Token { mint, .. }) = (escrow.funded, escrow.deposit) {
+        let vault = next_account_info(account_info_iter)?;
+        let maker_token = next_account_info(account_info_iter)?;
+        let mint_account = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let decimals = check_mint(&mint, mint_account, token_program)?;
+        check_vault(escrow_account, vault, mint_account, token_program)?;
+        release_vault(
+            &escrow,
+            escrow_account,
+            vault,
+            maker_token,
+            mint_account,
+            token_program,
+            maker,
+            decimals,
+        )?;
+    }
+
+    close_escrow(escrow_account, maker)
+}
+
+/// Deserialize the escrow after checking it is an initialized account of this program
+fn load_escrow(program_id: &Pubkey, escrow_account: &AccountInfo) -> Result<Escrow, ProgramError> {
+    if escrow_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // `Asset::Sol` encodes shorter than the space reserved for it
+    let escrow = Escrow::deserialize(&mut &escrow_account.data.borrow()[..])?;
+    if !escrow.is_initialized {
+        return Err(EscrowError::Uninitialized.into());
+    }
+    Ok(escrow)
+}
+
+fn check_maker(escrow: &Escrow, maker: &AccountInfo) -> ProgramResult {
+    if !maker.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *maker.key != escrow.maker {
+        return Err(EscrowError::InvalidMaker.into());
+    }
+    Ok(())
+}
+
+/// Check the mint against the escrow, returns its decimals
+fn check_mint(
+    mint: &Pubkey,
+    mint_account: &AccountInfo,
+    token_program: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    if *mint_account.key != *mint {
+        return Err(EscrowError::InvalidMint.into());
+    }
+    if *token_program.key != spl_token::id() && *token_program.key != spl_token_2022::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if mint_account.owner != token_program.key {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(
+        StateWithExtensions::<Mint>::unpack(&mint_account.data.borrow())?
+            .base
+            .decimals,
+    )
+}
+
+fn check_vault(
+    escrow_account: &AccountInfo,
+    vault: &AccountInfo,
+    mint_account: &AccountInfo,
+    token_program: &AccountInfo,
+) -> ProgramResult {
+    if *vault.key != vault_address(escrow_account.key, mint_account.key, token_program.key) {
+        return Err(EscrowError::InvalidVault.into());
+    }
+    Ok(())
+}
+
+/// Pay everything in the vault out to `destination` and close it, its rent goes to the maker
+///
+/// The balance can differ from the deposit: anyone can send tokens to the vault, and a
+/// transfer fee leaves it short. Token-2022 only closes accounts without withheld fees,
+/// so those are harvested to the mint first.
+#[allow(clippy::too_many_arguments)]
+fn release_vault<'a>(
+    escrow: &Escrow,
+    escrow_account: &AccountInfo<'a>,
+    vault: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    mint_account: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    maker: &AccountInfo<'a>,
+    decimals: u8,
+) -> ProgramResult {
+    let (balance, withheld) = {
+        let data = vault.data.borrow();
+        let account = StateWithExtensions::<TokenAccount>::unpack(&data)?;
+        let withheld = account
+            .get_extension::<TransferFeeAmount>()
+            .map(|fee| u64::from(fee.withheld_amount))
+            .unwrap_or(0);
+        (account.base.amount, withheld)
+    };
+    let id_bytes = escrow.id.to_le_bytes();
+    let signer_seeds: &[&[u8]] = &[
+        ESCROW_SEED,
+        escrow.maker.as_ref(),
+        &id_bytes,
+        &[escrow.bump],
+    ];
+
+    if balance > 0 {
+        invoke_signed(
+            &transfer_checked(
+                token_program.key,
+                vault.key,
+                mint_account.key,
+                destination.key,
+                escrow_account.key,
+                &[],
+                balance,
+                decimals,
+            )?,
+            &[
+                vault.clone(),
+                mint_account.clone(),
+                destination.clone(),
+                escrow_account.clone(),
+            ],
+            &[signer_seeds],
+        )?;
+    }
+    if withheld > 0 {
+        invoke(
+            &harvest_withheld_tokens_to_mint(token_program.key, mint_account.key, &[vault.key])?,
+            &[mint_account.clone(), vault.clone()],
+        )?;
+    }
+    invoke_signed(
+        &close_account(
+            token_program.key,
+            vault.key,
+            maker.key,
+            escrow_account.key,
+            &[],
+        )?,
+        &[vault.clone(), maker.clone(), escrow_account.clone()],
+        &[signer_seeds],
+    )
+}
+
+/// The program owns the escrow account, so it moves lamports without the system program
+fn transfer_lamports(from: &AccountInfo, to: &AccountInfo, lamports: u64) -> ProgramResult {
+    if **from.try_borrow_lamports()? < lamports {
+        return Err(ProgramError::InsufficientFunds);
+    }
+    **from.try_borrow_mut_lamports()? -= lamports;
+    **to.try_borrow_mut_lamports()? += lamports;
+    Ok(())
+}
+
+/// Hand every lamport left to the maker, the runtime then removes the account
+fn close_escrow(escrow_account: &AccountInfo, maker: &AccountInfo) -> ProgramResult {
+    transfer_lamports(escrow_account, maker, escrow_account.lamports())?;
+    escrow_account.data.borrow_mut().fill(0);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{find_escrow_address, id};
+    use program_utils::test_utils::{self, TestAccount};
+    use spl_token_2022::extension::transfer_fee::{TransferFee, TransferFeeConfig};
+    use spl_token_2022::extension::{
+        BaseStateWithExtensionsMut, ExtensionType, StateWithExtensionsMut,
+    };
+    use spl_token_2022::processor::Processor;
+    use spl_token_2022::state::AccountState;
+
+    const DECIMALS: u8 = 2;
+
+    fn stub_syscalls() {
+        test_utils::stub_syscalls(id(), Some(Processor::process));
+    }
+
+    /// A Token-2022 mint, with a transfer fee of `fee_basis_points` if given
+    fn mint(fee_basis_points: Option<u16>) -> TestAccount {
+        let extensions: &[ExtensionType] = match fee_basis_points {
+            Some(_) => &[ExtensionType::TransferFeeConfig],
+            None => &[],
+        };
+        let mut data =
+            vec![0; ExtensionType::try_calculate_account_len::<Mint>(extensions).unwrap()];
+        let mut state = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+        if let Some(basis_points) = fee_basis_points {
+            let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+            let fee = TransferFee {
+                epoch: 0.into(),
+                maximum_fee: u64::MAX.into(),
+                transfer_fee_basis_points: basis_points.into(),
+            };
+            config.older_transfer_fee = fee;
+            config.newer_transfer_fee = fee;
+        }
+        state.base = Mint {
+            supply: 1_000_000,
+            decimals: DECIMALS,
+            is_initialized: true,
+            ..Mint::default()
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        TestAccount::new(Pubkey::new_unique(), spl_token_2022::id(), 1_000, data)
+    }
+
+    /// A Token-2022 account, withholding transfer fees if `withheld` is given
+    fn token_account(
+        key: Pubkey,
+        mint: &Pubkey,
+        owner: &Pubkey,
+        amount: u64,
+        withheld: Option<u64>,
+    ) -> TestAccount {
+        let extensions: &[ExtensionType] = match withheld {
+            Some(_) => &[ExtensionType::TransferFeeAmount],
+            None => &[],
+        };
+        let mut data =
+            vec![0; ExtensionType::try_calculate_account_len::<TokenAccount>(extensions).unwrap()];
+        let mut state =
+            StateWithExtensionsMut::<TokenAccount>::unpack_uninitialized(&mut data).unwrap();
+        if let Some(withheld) = withheld {
+            state
+                .init_extension::<TransferFeeAmount>(true)
+                .unwrap()
+                .withheld_amount = withheld.into();
+        }
+        state.base = TokenAccount {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        TestAccount::new(key, spl_token_2022::id(), 10, data)
+    }
+
+    fn token_amount(account: &TestAccount) -> u64 {
+        StateWithExtensions::<TokenAccount>::unpack(&account.data)
+            .unwrap()
+            .base
+            .amount
+    }
+
+    fn withheld_amount(account: &TestAccount) -> u64 {
+        let account = StateWithExtensions::<TokenAccount>::unpack(&account.data).unwrap();
+        u64::from(
+            account
+                .get_extension::<TransferFeeAmount>()
+                .unwrap()
+                .withheld_amount,
+        )
+    }
+
+    /// A funded escrow of `deposit` for `receive`, holding `lamports`
+    fn funded_escrow(maker: &Pubkey, deposit: Asset, receive: Asset, lamports: u64) -> TestAccount {
+        let (key, bump) = find_escrow_address(maker, 1, &id());
+        let state = Escrow {
+            is_initialized: true,
+            maker: *maker,
+            id: 1,
+            bump,
+            funded: true,
+            deposit,
+            receive,
+        };
+        let mut data = vec![0; Escrow::LEN];
+        state.serialize(&mut &mut data[..]).unwrap();
+        TestAccount::new(key, id(), lamports, data)
+    }
+
+    fn program(key: Pubkey) -> TestAccount {
+        TestAccount::new(key, Pubkey::default(), 1, vec![])
+    }
+
+    fn initialize(id: u64, bump: u8) -> Vec<u8> {
+        borsh::to_vec(&EscrowInstruction::Initialize {
+            id,
+            bump,
+            deposit: Asset::Sol { lamports: 100 },
+            receive: Asset::Sol { lamports: 200 },
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_initialize_rejects_wrong_seeds_and_bumps() {
+        let maker_key = Pubkey::new_unique();
+        let (escrow_key, bump) = find_escrow_address(&maker_key, 1, &id());
+        let mut maker = TestAccount::new(maker_key, system_program::id(), 1_000, vec![]);
+        let mut escrow = TestAccount::new(escrow_key, system_program::id(), 0, vec![]);
+        let mut system = TestAccount::new(system_program::id(), Pubkey::default(), 0, vec![]);
+
+        // the escrow of another id
+        let accounts = [maker.info(true), escrow.info(false), system.info(false)];
+        assert_eq!(
+            process_instruction(&id(), &accounts, &initialize(2, bump)),
+            Err(EscrowError::InvalidEscrowAddress.into())
+        );
+
+        // a bump that is valid for the seeds but not canonical derives another address
+        let id_bytes = 1u64.to_le_bytes();
+        let seeds = escrow_seeds(&maker_key, &id_bytes);
+        let (other_bump, other_key) = (0..bump)
+            .rev()
+            .find_map(|bump| {
+                let address =
+                    Pubkey::create_program_address(&[&seeds[..], &[&[bump]]].concat(), &id());
+                Some((bump, address.ok()?))
+            })
+            .unwrap();
+        assert_eq!(
+            process_instruction(&id(), &accounts, &initialize(1, other_bump)),
+            Err(EscrowError::InvalidEscrowAddress.into())
+        );
+        drop(accounts);
+        let mut other = TestAccount::new(other_key, system_program::id(), 0, vec![]);
+        let accounts = [maker.info(true), other.info(false), system.info(false)];
+        assert_eq!(
+            process_instruction(&id(), &accounts, &initialize(1, other_bump)),
+            Err(EscrowError::NonCanonicalBump.into())
+        );
+
+        let accounts = [maker.info(false), escrow.info(false), system.info(false)];
+        assert_eq!(
+            process_instruction(&id(), &accounts, &initialize(1, bump)),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+    }
+
+    #[test]
+    fn test_cancel_sol_escrow() {
+        let maker_key = Pubkey::new_unique();
+        let (escrow_key, bump) = find_escrow_address(&maker_key, 7, &id());
+        let state = Escrow {
+            is_initialized: true,
+            maker: maker_key,
+            id: 7,
+            bump,
+            funded: true,
+            deposit: Asset::Sol { lamports: 100 },
+            receive: Asset::Sol { lamports: 200 },
+        };
+        let mut data = vec![0; Escrow::LEN];
+        state.serialize(&mut &mut data[..]).unwrap();
+        let mut maker = TestAccount::new(maker_key, system_program::id(), 1_000, vec![]);
+        let mut escrow = TestAccount::new(escrow_key, id(), 150, data);
+        let cancel = borsh::to_vec(&EscrowInstruction::Cancel).unwrap();
+
+        let mut stranger = TestAccount::new(Pubkey::new_unique(), system_program::id(), 0, vec![]);
+        let accounts = [stranger.info(true), escrow.info(false)];
+        assert_eq!(
+            process_instruction(&id(), &accounts, &cancel),
+            Err(EscrowError::InvalidMaker.into())
+        );
+
+        let accounts = [maker.info(true), escrow.info(false)];
+        process_instruction(&id(), &accounts, &cancel).unwrap();
+        drop(accounts);
+        assert_eq!(maker.lamports, 1_150);
+        assert_eq!(escrow.lamports, 0);
+        assert!(escrow.data.iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn test_exchange_sol_for_sol() {
+        stub_syscalls();
+        let mut maker = TestAccount::new(Pubkey::new_unique(), system_program::id(), 1_000, vec![]);
+        let mut taker = TestAccount::new(Pubkey::new_unique(), system_program::id(), 1_000, vec![]);
+        let deposit = Asset::Sol { lamports: 100 };
+        let receive = Asset::Sol { lamports: 200 };
+        let mut escrow = funded_escrow(&maker.key, deposit, receive, 150);
+        let mut system = program(system_program::id());
+        let exchange = borsh::to_vec(&EscrowInstruction::Exchange).unwrap();
+
+        let accounts = [
+            taker.info(true),
+            maker.info(false),
+            escrow.info(false),
+            system.info(false),
+        ];
+        process_instruction(&id(), &accounts, &exchange).unwrap();
+        drop(accounts);
+        // the taker pays 200 for 100, the maker gets the escrow's rent back too
+        assert_eq!(taker.lamports, 900);
+        assert_eq!(maker.lamports, 1_250);
+        assert_eq!(escrow.lamports, 0);
+        assert!(escrow.data.iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn test_exchange_tokens() {
+        stub_syscalls();
+        let mut maker = TestAccount::new(Pubkey::new_unique(), system_program::id(), 1_000, vec![]);
+        let mut taker = TestAccount::new(Pubkey::new_unique(), system_program::id(), 1_000, vec![]);
+        let mut deposit_mint = mint(None);
+        let mut receive_mint = mint(None);
+        let deposit = Asset::Token {
+            mint: deposit_mint.key,
+            amount: 1_000,
+        };
+        let receive = Asset::Token {
+            mint: receive_mint.key,
+            amount: 500,
+        };
+        let mut escrow = funded_escrow(&maker.key, deposit, receive, 50);
+        let vault_key = vault_address(&escrow.key, &deposit_mint.key, &spl_token_2022::id());
+        let mut vault = token_account(vault_key, &deposit_mint.key, &escrow.key, 1_000, None);
+        let mut taker_deposit =
+            token_account(Pubkey::new_unique(), &deposit_mint.key, &taker.key, 0, None);
+        let mut taker_payment = token_account(
+            Pubkey::new_unique(),
+            &receive_mint.key,
+            &taker.key,
+            800,
+            None,
+        );
+        let mut maker_payment =
+            token_account(Pubkey::new_unique(), &receive_mint.key, &maker.key, 0, None);
+        let mut system = program(system_program::id());
+        let mut token_program = program(spl_token_2022::id());
+        let mut receive_program = program(spl_token_2022::id());
+        let exchange = borsh::to_vec(&EscrowInstruction::Exchange).unwrap();
+
+        let accounts = [
+            taker.info(true),
+            maker.info(false),
+            escrow.info(false),
+            system.info(false),
+            vault.info(false),
+            taker_deposit.info(false),
+            deposit_mint.info(false),
+            token_program.info(false),
+            taker_payment.info(false),
+            maker_payment.info(false),
+            receive_mint.info(false),
+            receive_program.info(false),
+        ];
+        process_instruction(&id(), &accounts, &exchange).unwrap();
+        drop(accounts);
+        assert_eq!(token_amount(&taker_deposit), 1_000);
+        assert_eq!(token_amount(&taker_payment), 300);
+        assert_eq!(token_amount(&maker_payment), 500);
+        // both the vault and the escrow are closed to the maker
+        assert_eq!((vault.lamports, vault.owner), (0, system_program::id()));
+        assert_eq!(escrow.lamports, 0);
+        assert_eq!(maker.lamports, 1_060);
+    }
+
+    #[test]
+    fn test_cancel_pays_out_extra_tokens() {
+        stub_syscalls();
+        let mut maker = TestAccount::new(Pubkey::new_unique(), system_program::id(), 1_000, vec![]);
+        let mut deposit_mint = mint(None);
+        let deposit = Asset::Token {
+            mint: deposit_mint.key,
+            amount: 1_000,
+        };
+        let mut escrow = funded_escrow(&maker.key, deposit, Asset::Sol { lamports: 1 }, 50);
+        let vault_key = vault_address(&escrow.key, &deposit_mint.key, &spl_token_2022::id());
+        // someone sent 5 tokens more, paying out only the deposit would leave the vault
+        // unclosable
+        let mut vault = token_account(vault_key, &deposit_mint.key, &escrow.key, 1_005, None);
+        let mut maker_token =
+            token_account(Pubkey::new_unique(), &deposit_mint.key, &maker.key, 0, None);
+        let mut token_program = program(spl_token_2022::id());
+        let cancel = borsh::to_vec(&EscrowInstruction::Cancel).unwrap();
+
+        let accounts = [
+            maker.info(true),
+            escrow.info(false),
+            vault.info(false),
+            maker_token.info(false),
+            deposit_mint.info(false),
+            token_program.info(false),
+        ];
+        process_instruction(&id(), &accounts, &cancel).unwrap();
+        drop(accounts);
+        assert_eq!(token_amount(&maker_token), 1_005);
+        assert_eq!((vault.lamports, vault.owner), (0, system_program::id()));
+        assert_eq!(maker.lamports, 1_060);
+    }
+
+    #[test]
+    fn test_exchange_transfer_fee_vault() {
+        stub_syscalls();
+        let mut maker = TestAccount::new(Pubkey::new_unique(), system_program::id(), 1_000, vec![]);
+        let mut taker = TestAccount::new(Pubkey::new_unique(), system_program::id(), 1_000, vec![]);
+        // 1% fee, depositing 1_000 left 990 in the vault and withheld 10 there
+        let mut deposit_mint = mint(Some(100));
+        let deposit = Asset::Token {
+            mint: deposit_mint.key,
+            amount: 1_000,
+        };
+        let mut escrow = funded_escrow(&maker.key, deposit, Asset::Sol { lamports: 200 }, 50);
+        let vault_key = vault_address(&escrow.key, &deposit_mint.key, &spl_token_2022::id());
+        let mut vault = token_account(vault_key, &deposit_mint.key, &escrow.key, 990, Some(10));
+        let mut taker_token = token_account(
+            Pubkey::new_unique(),
+            &deposit_mint.key,
+            &taker.key,
+            0,
+            Some(0),
+        );
+        let mut system = program(system_program::id());
+        let mut token_program = program(spl_token_2022::id());
+        let exchange = borsh::to_vec(&EscrowInstruction::Exchange).unwrap();
+
+        let accounts = [
+            taker.info(true),
+            maker.info(false),
+            escrow.info(false),
+            system.info(false),
+            vault.info(false),
+            taker_token.info(false),
+            deposit_mint.info(false),
+            token_program.info(false),
+        ];
+        process_instruction(&id(), &accounts, &exchange).unwrap();
+        drop(accounts);
+        // the payout is charged the fee again, the vault's withheld fees go to the mint
+        assert_eq!(token_amount(&taker_token), 980);
+        assert_eq!(withheld_amount(&taker_token), 10);
+        let mint_state = StateWithExtensions::<Mint>::unpack(&deposit_mint.data).unwrap();
+        let config = mint_state.get_extension::<TransferFeeConfig>().unwrap();
+        assert_eq!(u64::from(config.withheld_amount), 10);
+        assert_eq!((vault.lamports, vault.owner), (0, system_program::id()));
+        assert_eq!((taker.lamports, maker.lamports), (800, 1_260));
+    }
+
+    #[test]
+    fn test_escrow_len() {
+        let escrow = Escrow {
+            is_initialized: true,
+            maker: Pubkey::new_unique(),
+            id: 0,
+            bump: 0,
+            funded: false,
+            deposit: Asset::Token {
+                mint: Pubkey::new_unique(),
+                amount: 1,
+            },
+            receive: Asset::Token {
+                mint: Pubkey::new_unique(),
+                amount: 1,
+            },
+        };
+        assert_eq!(borsh::to_vec(&escrow).unwrap().len(), Escrow::LEN);
+        assert_eq!(
+            EscrowError::from_code(3),
+            Some(EscrowError::NonCanonicalBump)
+        );
+    }
+}
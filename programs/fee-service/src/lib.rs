@@ -0,0 +1,118 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use thiserror::Error;
+
+#[cfg(feature = "program")]
+pub mod processor;
+
+#[cfg(feature = "program")]
+pub use processor::process_instruction;
+
+solana_program::declare_id!("3eKFG32skQzimU1hA9bnyzepAEfLUH85o1bRZms446pL");
+
+#[cfg(all(feature = "program", not(feature = "no-entrypoint")))]
+solana_program::entrypoint!(process_instruction);
+
+pub const CONFIG_SEED: &[u8] = b"config";
+pub const TREASURY_SEED: &[u8] = b"treasury";
+
+/// Errors of the fee service program, returned as `ProgramError::Custom(code)`
+#[derive(Clone, Copy, Debug, Error, PartialEq, Eq)]
+pub enum ServiceError {
+    #[error("config account is already initialized")]
+    AlreadyInitialized = 0,
+    #[error("config account is not initialized")]
+    Uninitialized = 1,
+    #[error("signer is not the admin of this config")]
+    InvalidAdmin = 2,
+    #[error("config account is not the PDA of the admin")]
+    InvalidConfigAddress = 3,
+    #[error("treasury account doesn't belong to this config")]
+    InvalidTreasuryAddress = 4,
+    #[error("withdrawal would leave the treasury below rent exemption")]
+    TreasuryNotRentExempt = 5,
+    #[error("execution counter overflowed")]
+    Overflow = 6,
+}
+
+impl ServiceError {
+    /// The error behind a `ProgramError::Custom` code, e.g. from a failed transaction
+    pub fn from_code(code: u32) -> Option<Self> {
+        Some(match code {
+            0 => ServiceError::AlreadyInitialized,
+            1 => ServiceError::Uninitialized,
+            2 => ServiceError::InvalidAdmin,
+            3 => ServiceError::InvalidConfigAddress,
+            4 => ServiceError::InvalidTreasuryAddress,
+            5 => ServiceError::TreasuryNotRentExempt,
+            6 => ServiceError::Overflow,
+            _ => return None,
+        })
+    }
+}
+
+impl From<ServiceError> for ProgramError {
+    fn from(error: ServiceError) -> Self {
+        ProgramError::Custom(error as u32)
+    }
+}
+
+/// Fee configuration, stored in the PDA of `[CONFIG_SEED, admin]`
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ServiceConfig {
+    pub is_initialized: bool,
+    pub admin: Pubkey,
+    /// PDA of `[TREASURY_SEED, config]` collecting the fees, owned by the program
+    pub treasury: Pubkey,
+    /// Lamports charged per `Execute`
+    pub fee: u64,
+    pub executions: u64,
+    pub bump: u8,
+    pub treasury_bump: u8,
+}
+
+impl ServiceConfig {
+    pub const LEN: usize = 1 + 32 + 32 + 8 + 8 + 1 + 1;
+}
+
+/// Instructions of the fee service program, borsh encoded
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ServiceInstruction {
+    /// Create the config and treasury PDAs of the admin
+    ///
+    /// Accounts required
+    /// 1. [signer, writable] Admin, pays the rent
+    /// 2. [writable] Config PDA
+    /// 3. [writable] Treasury PDA
+    /// 4. [] System program
+    Initialize { fee: u64 },
+    /// Pay the configured fee into the treasury and perform the service
+    ///
+    /// Accounts required
+    /// 1. [signer, writable] User
+    /// 2. [writable] Config PDA
+    /// 3. [writable] Treasury PDA
+    /// 4. [] System program
+    Execute,
+    /// Accounts required
+    /// 1. [signer] Admin
+    /// 2. [writable] Config PDA
+    UpdateFee { fee: u64 },
+    /// Move collected fees out, the treasury keeps its rent exempt minimum
+    ///
+    /// Accounts required
+    /// 1. [signer] Admin
+    /// 2. [] Config PDA
+    /// 3. [writable] Treasury PDA
+    /// 4. [writable] Destination
+    WithdrawTreasury { amount: u64 },
+}
+
+pub fn find_config_address(admin: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CONFIG_SEED, admin.as_ref()], program_id)
+}
+
+pub fn find_treasury_address(config: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TREASURY_SEED, config.as_ref()], program_id)
+}
@@ -0,0 +1,467 @@
+use crate::{
+    CONFIG_SEED, ServiceConfig, ServiceError, ServiceInstruction, TREASURY_SEED,
+    find_config_address, find_treasury_address,
+};
+use borsh::{BorshDeserialize, BorshSerialize};
+use program_utils::create_pda_account;
+use solana_program::account_info::{AccountInfo, next_account_info};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::msg;
+use solana_program::program::invoke;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::rent::Rent;
+use solana_program::system_instruction;
+use solana_program::system_program;
+use solana_program::sysvar::Sysvar;
+
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let instruction = ServiceInstruction::try_from_slice(instruction_data)
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+    match instruction {
+        ServiceInstruction::Initialize { fee } => {
+            msg!("Instruction: Initialize");
+            process_initialize(program_id, accounts, fee)
+        }
+        ServiceInstruction::Execute => {
+            msg!("Instruction: Execute");
+            process_execute(program_id, accounts)
+        }
+        ServiceInstruction::UpdateFee { fee } => {
+            msg!("Instruction: UpdateFee");
+            process_update_fee(program_id, accounts, fee)
+        }
+        ServiceInstruction::WithdrawTreasury { amount } => {
+            msg!("Instruction: WithdrawTreasury");
+            process_withdraw_treasury(program_id, accounts, amount)
+        }
+    }
+}
+
+fn process_initialize(program_id: &Pubkey, accounts: &[AccountInfo], fee: u64) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let admin = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let treasury = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+
+    if !admin.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *system_program_account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // the canonical bumps, so every admin has exactly one config
+    let (config_address, bump) = find_config_address(admin.key, program_id);
+    if *config_account.key != config_address {
+        return Err(ServiceError::InvalidConfigAddress.into());
+    }
+    let (treasury_address, treasury_bump) = find_treasury_address(config_account.key, program_id);
+    if *treasury.key != treasury_address {
+        return Err(ServiceError::InvalidTreasuryAddress.into());
+    }
+    if config_account.owner == program_id {
+        return Err(ServiceError::AlreadyInitialized.into());
+    }
+
+    create_pda_account(
+        admin,
+        config_account,
+        system_program_account,
+        ServiceConfig::LEN,
+        0,
+        program_id,
+        &[CONFIG_SEED, admin.key.as_ref(), &[bump]],
+    )?;
+    // an empty account owned by the program, so the program can debit it
+    create_pda_account(
+        admin,
+        treasury,
+        system_program_account,
+        0,
+        0,
+        program_id,
+        &[TREASURY_SEED, config_account.key.as_ref(), &[treasury_bump]],
+    )?;
+
+    let config = ServiceConfig {
+        is_initialized: true,
+        admin: *admin.key,
+        treasury: *treasury.key,
+        fee,
+        executions: 0,
+        bump,
+        treasury_bump,
+    };
+    config.serialize(&mut &mut config_account.data.borrow_mut()[..])?;
+    msg!("Fee: {} lamports", fee);
+    Ok(())
+}
+
+fn process_execute(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let user = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let treasury = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+
+    if !user.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let mut config = load_config(program_id, config_account)?;
+    if *treasury.key != config.treasury {
+        return Err(ServiceError::InvalidTreasuryAddress.into());
+    }
+
+    // the user's account belongs to the system program, only it can debit the user
+    invoke(
+        &system_instruction::transfer(user.key, treasury.key, config.fee),
+        &[
+            user.clone(),
+            treasury.clone(),
+            system_program_account.clone(),
+        ],
+    )?;
+
+    // Perform the primary instruction
+    config.executions = config
+        .executions
+        .checked_add(1)
+        .ok_or(ServiceError::Overflow)?;
+    config.serialize(&mut &mut config_account.data.borrow_mut()[..])?;
+    msg!(
+        "Execution {} paid {} lamports",
+        config.executions,
+        config.fee
+    );
+    Ok(())
+}
+
+fn process_update_fee(program_id: &Pubkey, accounts: &[AccountInfo], fee: u64) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let admin = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+
+    let mut config = load_config(program_id, config_account)?;
+    check_admin(&config, admin)?;
+
+    msg!("Fee: {} -> {} lamports", config.fee, fee);
+    config.fee = fee;
+    config.serialize(&mut &mut config_account.data.borrow_mut()[..])?;
+    Ok(())
+}
+
+fn process_withdraw_treasury(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let admin = next_account_info(account_info_iter)?;
+    let config_account = next_account_info(account_info_iter)?;
+    let treasury = next_account_info(account_info_iter)?;
+    let destination = next_account_info(account_info_iter)?;
+
+    let config = load_config(program_id, config_account)?;
+    check_admin(&config, admin)?;
+    if *treasury.key != config.treasury {
+        return Err(ServiceError::InvalidTreasuryAddress.into());
+    }
+    if treasury.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let minimum = Rent::get()?.minimum_balance(treasury.data_len());
+    let available = treasury.lamports().saturating_sub(minimum);
+    if amount > available {
+        return Err(ServiceError::TreasuryNotRentExempt.into());
+    }
+    transfer_service_fee_lamports(treasury, destination, amount)
+}
+
+/// Deserialize the config after checking it is an initialized account of this program
+fn load_config(
+    program_id: &Pubkey,
+    config_account: &AccountInfo,
+) -> Result<ServiceConfig, ProgramError> {
+    if config_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let config = ServiceConfig::try_from_slice(&config_account.data.borrow())?;
+    if !config.is_initialized {
+        return Err(ServiceError::Uninitialized.into());
+    }
+    Ok(config)
+}
+
+fn check_admin(config: &ServiceConfig, admin: &AccountInfo) -> ProgramResult {
+    if !admin.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *admin.key != config.admin {
+        return Err(ServiceError::InvalidAdmin.into());
+    }
+    Ok(())
+}
+
+///Your Solana Program can transfer lamports from one account to another without 'invoking' the System program.
+/// The fundamental rule is that your program can transfer lamports from any account owned by your program to
+/// any account at all.
+/// The recipient account does not have to be an account owned by your program.
+/// Transfers lamports from one account (must be program owned)
+/// to another account. The recipient can be any account
+fn transfer_service_fee_lamports(
+    from_account: &AccountInfo,
+    to_account: &AccountInfo,
+    amount_of_lamports: u64,
+) -> ProgramResult {
+    // Does the from account have enough lamports to transfer?
+    if **from_account.try_borrow_lamports()? < amount_of_lamports {
+        return Err(ProgramError::InsufficientFunds);
+    }
+    // Debit from_account and credit to_account
+    **from_account.try_borrow_mut_lamports()? -= amount_of_lamports;
+    **to_account.try_borrow_mut_lamports()? += amount_of_lamports;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{find_config_address, find_treasury_address, id};
+    use program_utils::test_utils::{TestAccount, stub_syscalls, take_invoked};
+    use solana_program::system_instruction::SystemInstruction;
+
+    /// Install the stubs and take the system instructions invoked so far
+    fn invoked() -> Vec<SystemInstruction> {
+        stub_syscalls(id(), None);
+        take_invoked()
+    }
+
+    fn config_account(admin: &Pubkey) -> TestAccount {
+        let config = ServiceConfig {
+            is_initialized: true,
+            admin: *admin,
+            treasury: Pubkey::new_unique(),
+            fee: 5,
+            ..ServiceConfig::default()
+        };
+        TestAccount::new(
+            Pubkey::new_unique(),
+            id(),
+            1,
+            borsh::to_vec(&config).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_instruction_encoding() {
+        let instruction = ServiceInstruction::WithdrawTreasury { amount: 42 };
+        let data = borsh::to_vec(&instruction).unwrap();
+        assert_eq!(data, [3, 42, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            ServiceInstruction::try_from_slice(&data).unwrap(),
+            instruction
+        );
+        assert_eq!(
+            process_instruction(&id(), &[], &[9]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(
+            ServiceConfig::LEN,
+            borsh::to_vec(&ServiceConfig::default()).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_update_fee_checks() {
+        let mut admin = TestAccount::new(Pubkey::new_unique(), system_program::id(), 1, vec![]);
+        let mut config = config_account(&admin.key);
+        let data = borsh::to_vec(&ServiceInstruction::UpdateFee { fee: 10 }).unwrap();
+
+        // the admin has to sign
+        let accounts = [admin.info(false), config.info(false)];
+        assert_eq!(
+            process_instruction(&id(), &accounts, &data),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+
+        let mut other = TestAccount::new(Pubkey::new_unique(), system_program::id(), 1, vec![]);
+        let accounts = [other.info(true), config.info(false)];
+        assert_eq!(
+            process_instruction(&id(), &accounts, &data),
+            Err(ServiceError::InvalidAdmin.into())
+        );
+
+        // a config of another program is rejected
+        let mut foreign = config_account(&admin.key);
+        foreign.owner = Pubkey::new_unique();
+        let accounts = [admin.info(true), foreign.info(false)];
+        assert_eq!(
+            process_instruction(&id(), &accounts, &data),
+            Err(ProgramError::IncorrectProgramId)
+        );
+
+        let accounts = [admin.info(true), config.info(false)];
+        process_instruction(&id(), &accounts, &data).unwrap();
+        drop(accounts);
+        let updated = ServiceConfig::try_from_slice(&config.data).unwrap();
+        assert_eq!(updated.fee, 10);
+
+        assert_eq!(
+            ServiceError::from_code(5),
+            Some(ServiceError::TreasuryNotRentExempt)
+        );
+        assert_eq!(ServiceError::from_code(7), None);
+    }
+
+    #[test]
+    fn test_initialize_funded_config() {
+        invoked();
+        let mut admin = TestAccount::new(
+            Pubkey::new_unique(),
+            system_program::id(),
+            1_000_000_000,
+            vec![],
+        );
+        let (config_address, _) = find_config_address(&admin.key, &id());
+        // someone sent lamports ahead, `create_account` would fail; the system program
+        // isn't run here, so the data is sized already
+        let mut config = TestAccount {
+            key: config_address,
+            owner: system_program::id(),
+            lamports: 1_000,
+            data: vec![0; ServiceConfig::LEN],
+        };
+        let (treasury_address, _) = find_treasury_address(&config_address, &id());
+        let mut treasury = TestAccount {
+            key: treasury_address,
+            owner: system_program::id(),
+            lamports: 0,
+            data: vec![],
+        };
+        let mut system = TestAccount::new(Pubkey::new_unique(), Pubkey::default(), 1, vec![]);
+        system.key = system_program::id();
+
+        let data = borsh::to_vec(&ServiceInstruction::Initialize { fee: 5 }).unwrap();
+        let accounts = [
+            admin.info(true),
+            config.info(false),
+            treasury.info(false),
+            system.info(false),
+        ];
+        process_instruction(&id(), &accounts, &data).unwrap();
+        drop(accounts);
+
+        let rent = Rent::default();
+        assert_eq!(
+            invoked(),
+            [
+                SystemInstruction::Transfer {
+                    lamports: rent.minimum_balance(ServiceConfig::LEN) - 1_000
+                },
+                SystemInstruction::Allocate {
+                    space: ServiceConfig::LEN as u64
+                },
+                SystemInstruction::Assign { owner: id() },
+                SystemInstruction::CreateAccount {
+                    lamports: rent.minimum_balance(0),
+                    space: 0,
+                    owner: id(),
+                },
+            ]
+        );
+        assert_eq!(config.lamports, rent.minimum_balance(ServiceConfig::LEN));
+        let initialized = ServiceConfig::try_from_slice(&config.data).unwrap();
+        assert_eq!((initialized.admin, initialized.fee), (admin.key, 5));
+    }
+
+    #[test]
+    fn test_execute() {
+        invoked();
+        let mut user = TestAccount::new(Pubkey::new_unique(), system_program::id(), 100, vec![]);
+        let mut config = config_account(&Pubkey::new_unique());
+        let mut treasury = TestAccount::new(Pubkey::new_unique(), id(), 0, vec![]);
+        let mut system = TestAccount::new(Pubkey::new_unique(), Pubkey::default(), 1, vec![]);
+        system.key = system_program::id();
+        let mut state = ServiceConfig::try_from_slice(&config.data).unwrap();
+        state.treasury = treasury.key;
+        config.data = borsh::to_vec(&state).unwrap();
+        let data = borsh::to_vec(&ServiceInstruction::Execute).unwrap();
+
+        // the user pays the fee, so has to sign
+        let accounts = [
+            user.info(false),
+            config.info(false),
+            treasury.info(false),
+            system.info(false),
+        ];
+        assert_eq!(
+            process_instruction(&id(), &accounts, &data),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+
+        // the fee only goes to the config's treasury
+        let mut other = TestAccount::new(Pubkey::new_unique(), id(), 0, vec![]);
+        let accounts = [
+            user.info(true),
+            config.info(false),
+            other.info(false),
+            system.info(false),
+        ];
+        assert_eq!(
+            process_instruction(&id(), &accounts, &data),
+            Err(ServiceError::InvalidTreasuryAddress.into())
+        );
+
+        for _ in 0..2 {
+            let accounts = [
+                user.info(true),
+                config.info(false),
+                treasury.info(false),
+                system.info(false),
+            ];
+            process_instruction(&id(), &accounts, &data).unwrap();
+        }
+        assert_eq!((user.lamports, treasury.lamports), (90, 10));
+        let executed = ServiceConfig::try_from_slice(&config.data).unwrap();
+        assert_eq!(executed.executions, 2);
+    }
+
+    #[test]
+    fn test_withdraw_treasury_keeps_rent() {
+        invoked();
+        let mut admin = TestAccount::new(Pubkey::new_unique(), system_program::id(), 1, vec![]);
+        let mut config = config_account(&admin.key);
+        let minimum = Rent::default().minimum_balance(0);
+        let mut treasury = TestAccount::new(Pubkey::new_unique(), id(), minimum + 100, vec![]);
+        let mut destination =
+            TestAccount::new(Pubkey::new_unique(), system_program::id(), 0, vec![]);
+        let mut state = ServiceConfig::try_from_slice(&config.data).unwrap();
+        state.treasury = treasury.key;
+        config.data = borsh::to_vec(&state).unwrap();
+
+        let mut withdraw = |amount| {
+            let data = borsh::to_vec(&ServiceInstruction::WithdrawTreasury { amount }).unwrap();
+            let accounts = [
+                admin.info(true),
+                config.info(false),
+                treasury.info(false),
+                destination.info(false),
+            ];
+            process_instruction(&id(), &accounts, &data)
+        };
+        // only what exceeds the rent exemption can go
+        assert_eq!(
+            withdraw(101),
+            Err(ServiceError::TreasuryNotRentExempt.into())
+        );
+        withdraw(100).unwrap();
+        assert_eq!(withdraw(1), Err(ServiceError::TreasuryNotRentExempt.into()));
+        assert_eq!((treasury.lamports, destination.lamports), (minimum, 100));
+    }
+}
@@ -0,0 +1,39 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::instruction::{AccountMeta, Instruction};
+use solana_program::pubkey::Pubkey;
+use solana_program::sysvar::clock::ID as SYSVAR_CLOCK_ID;
+
+#[cfg(feature = "program")]
+pub mod processor;
+
+#[cfg(feature = "program")]
+pub use processor::process_instruction;
+
+solana_program::declare_id!("77ezihTV6mTh2Uf3ggwbYF2NyGJJ5HHah1GrdowWJVD3");
+
+#[cfg(all(feature = "program", not(feature = "no-entrypoint")))]
+solana_program::entrypoint!(process_instruction);
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct HelloState {
+    pub is_initialized: bool,
+}
+
+impl HelloState {
+    pub const LEN: usize = 1; // because there exists just one boolean variable
+}
+
+/// Initialize a hello state account
+///
+/// With `pass_clock` the program reads the clock from the sysvar account,
+/// otherwise it gets it directly.
+pub fn hello(payer: &Pubkey, hello_account: &Pubkey, pass_clock: bool) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(*hello_account, false),
+    ];
+    if pass_clock {
+        accounts.push(AccountMeta::new_readonly(SYSVAR_CLOCK_ID, false));
+    }
+    Instruction::new_with_bytes(id(), &[], accounts)
+}
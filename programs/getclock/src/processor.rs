@@ -0,0 +1,93 @@
+use crate::HelloState;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::account_info::{AccountInfo, next_account_info};
+use solana_program::clock::Clock;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::msg;
+use solana_program::pubkey::Pubkey;
+use solana_program::sysvar::Sysvar;
+
+///How to get clock in a program
+// Getting a clock (ie, the current time) can be done in two ways:
+// 1. Passing SYSVAR_CLOCK_PUBKEY into an instruction
+// 2. Accessing Clock directly inside an instruction.
+//
+// It is nice to know both the methods, because some legacy programs still expect
+// the SYSVAR_CLOCK_PUBKEY as an account. The program takes the first way when the
+// clock sysvar is passed, the second one otherwise.
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    if accounts.len() > 2 {
+        process_with_clock_sysvar(program_id, accounts, instruction_data)
+    } else {
+        process_with_clock_syscall(program_id, accounts, instruction_data)
+    }
+}
+
+/// 1. Passing SYSVAR_CLOCK_PUBKEY into an instruction
+///
+// Accounts required
+/// 1. [signer, writable] Payer
+/// 2. [writable] Hello state account
+/// 3. [] Clock sys var
+pub fn process_with_clock_sysvar(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    _instruction_data: &[u8],
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    // Payer account
+    let _payer_account = next_account_info(accounts_iter)?;
+    // Hello state account
+    let hello_state_account = next_account_info(accounts_iter)?;
+    // Clock sysvar
+    let sysvar_clock_pubkey = next_account_info(accounts_iter)?;
+
+    let mut hello_state = HelloState::try_from_slice(&hello_state_account.data.borrow())?;
+    hello_state.is_initialized = true;
+    hello_state.serialize(&mut &mut hello_state_account.data.borrow_mut()[..])?;
+    msg!("Account initialized :)");
+
+    // Type casting [AccountInfo] to [Clock]
+    let clock = Clock::from_account_info(sysvar_clock_pubkey)?;
+    // Getting timestamp
+    let current_timestamp = clock.unix_timestamp;
+    msg!("Current Timestamp: {}", current_timestamp);
+
+    Ok(())
+}
+
+///Accessing Clock directly inside an instruction
+// Creating the same instruction, but without expecting the SYSVAR_CLOCK_PUBKEY from the client side.
+//
+// Accounts required
+/// 1. [signer, writable] Payer
+/// 2. [writable] Hello state account
+pub fn process_with_clock_syscall(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    _instruction_data: &[u8],
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    // Payer account
+    let _payer_account = next_account_info(accounts_iter)?;
+    // Hello state account
+    let hello_state_account = next_account_info(accounts_iter)?;
+
+    // Getting clock directly
+    let clock = Clock::get()?;
+
+    let mut hello_state = HelloState::try_from_slice(&hello_state_account.data.borrow())?;
+    hello_state.is_initialized = true;
+    hello_state.serialize(&mut &mut hello_state_account.data.borrow_mut()[..])?;
+    msg!("Account initialized :)");
+
+    // Getting timestamp
+    let current_timestamp = clock.unix_timestamp;
+    msg!("Current Timestamp: {}", current_timestamp);
+
+    Ok(())
+}
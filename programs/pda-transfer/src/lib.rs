@@ -0,0 +1,39 @@
+use solana_program::instruction::{AccountMeta, Instruction};
+use solana_program::pubkey::Pubkey;
+use solana_program::system_program;
+
+#[cfg(feature = "program")]
+pub mod processor;
+
+#[cfg(feature = "program")]
+pub use processor::process_instruction;
+
+solana_program::declare_id!("CbrkungZsPirCxh6X9XWvmapSUjJiHBeu4WSVKN1aX96");
+
+#[cfg(all(feature = "program", not(feature = "no-entrypoint")))]
+solana_program::entrypoint!(process_instruction);
+
+pub const PDA_SEED: &[u8] = b"escrow";
+
+/// Amount moved out of the PDA per instruction
+pub const TRANSFER_LAMPORTS: u64 = 100_000_000; // 0.1 SOL
+
+/// The PDA of `authority`, only they can move lamports out of it
+pub fn find_pda_address(authority: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PDA_SEED, authority.as_ref()], program_id)
+}
+
+/// Move `TRANSFER_LAMPORTS` from the PDA of `authority` to `to`
+pub fn transfer(program_id: &Pubkey, authority: &Pubkey, to: &Pubkey) -> Instruction {
+    let (pda, bump) = find_pda_address(authority, program_id);
+    Instruction::new_with_bytes(
+        *program_id,
+        &[bump], // pass bump seed for saving compute budget
+        vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(pda, false),
+            AccountMeta::new(*to, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
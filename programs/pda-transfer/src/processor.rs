@@ -0,0 +1,135 @@
+use crate::{PDA_SEED, TRANSFER_LAMPORTS};
+use solana_program::account_info::{AccountInfo, next_account_info};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program::invoke_signed;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::system_instruction;
+
+// Sign with a PDA's Account
+//program transfer tokens from one account to another
+// the PDA derives from the authority's key, so checking the authority signed and the PDA
+// matches its seeds keeps anyone else from draining it
+//
+// Accounts required
+/// 1. [signer] Authority
+/// 2. [writable] PDA of the authority
+/// 3. [writable] Recipient
+/// 4. [] System program
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let authority_account_info = next_account_info(account_info_iter)?;
+    let pda_account_info = next_account_info(account_info_iter)?;
+    let to_account_info = next_account_info(account_info_iter)?;
+    let system_program_account_info = next_account_info(account_info_iter)?;
+
+    if !authority_account_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // pass bump seed for saving compute budget
+    let bump_seed = *instruction_data
+        .first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    let seeds: &[&[u8]] = &[PDA_SEED, authority_account_info.key.as_ref(), &[bump_seed]];
+    let expected = Pubkey::create_program_address(seeds, program_id)
+        .map_err(|_| ProgramError::InvalidSeeds)?;
+    if *pda_account_info.key != expected {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    invoke_signed(
+        &system_instruction::transfer(pda_account_info.key, to_account_info.key, TRANSFER_LAMPORTS),
+        &[
+            pda_account_info.clone(),
+            to_account_info.clone(),
+            system_program_account_info.clone(),
+        ],
+        &[seeds],
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{find_pda_address, id, transfer};
+    use solana_program::clock::Epoch;
+    use solana_program::system_program;
+
+    struct TestAccount {
+        key: Pubkey,
+        owner: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+    }
+
+    impl TestAccount {
+        fn new(key: Pubkey, lamports: u64) -> Self {
+            Self {
+                key,
+                owner: system_program::id(),
+                lamports,
+                data: vec![],
+            }
+        }
+
+        fn info(&mut self, is_signer: bool) -> AccountInfo<'_> {
+            AccountInfo::new(
+                &self.key,
+                is_signer,
+                true,
+                &mut self.lamports,
+                &mut self.data,
+                &self.owner,
+                false,
+                Epoch::default(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_only_the_authority_transfers() {
+        let authority_key = Pubkey::new_unique();
+        let (pda_key, bump) = find_pda_address(&authority_key, &id());
+        let mut authority = TestAccount::new(authority_key, 0);
+        let mut pda = TestAccount::new(pda_key, TRANSFER_LAMPORTS);
+        let mut to = TestAccount::new(Pubkey::new_unique(), 0);
+        let mut system = TestAccount::new(system_program::id(), 0);
+
+        let instruction = transfer(&id(), &authority_key, &to.key);
+        assert_eq!(instruction.data, [bump]);
+        assert_eq!(instruction.accounts[1].pubkey, pda_key);
+
+        let accounts = [
+            authority.info(false),
+            pda.info(false),
+            to.info(false),
+            system.info(false),
+        ];
+        assert_eq!(
+            process_instruction(&id(), &accounts, &[bump]),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        drop(accounts);
+
+        // someone signing for the PDA of another authority
+        let mut other = TestAccount::new(Pubkey::new_unique(), 0);
+        let accounts = [
+            other.info(true),
+            pda.info(false),
+            to.info(false),
+            system.info(false),
+        ];
+        assert_eq!(
+            process_instruction(&id(), &accounts, &[bump]),
+            Err(ProgramError::InvalidSeeds)
+        );
+    }
+}
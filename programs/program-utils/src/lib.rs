@@ -0,0 +1,74 @@
+use solana_program::account_info::AccountInfo;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program::{invoke, invoke_signed};
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::rent::Rent;
+use solana_program::system_instruction;
+use solana_program::sysvar::Sysvar;
+
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+
+/// Create the PDA `account` with `space` bytes owned by `owner`, paid by `payer`, holding
+/// `lamports` on top of its rent exemption
+///
+/// Anyone can send lamports to a PDA before it exists, and `create_account` refuses
+/// accounts that hold lamports. Such an account keeps them, is topped up by the rent it
+/// still lacks plus `lamports`, then allocated and assigned instead.
+pub fn create_pda_account<'a>(
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    space: usize,
+    lamports: u64,
+    owner: &Pubkey,
+    seeds: &[&[u8]],
+) -> ProgramResult {
+    let minimum = Rent::get()?.minimum_balance(space);
+    if account.lamports() == 0 {
+        let lamports = minimum
+            .checked_add(lamports)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        return invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                account.key,
+                lamports,
+                space as u64,
+                owner,
+            ),
+            &[
+                payer.clone(),
+                account.clone(),
+                system_program_account.clone(),
+            ],
+            &[seeds],
+        );
+    }
+
+    let top_up = minimum
+        .saturating_sub(account.lamports())
+        .checked_add(lamports)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, top_up),
+            &[
+                payer.clone(),
+                account.clone(),
+                system_program_account.clone(),
+            ],
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(account.key, space as u64),
+        &[account.clone(), system_program_account.clone()],
+        &[seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(account.key, owner),
+        &[account.clone(), system_program_account.clone()],
+        &[seeds],
+    )
+}
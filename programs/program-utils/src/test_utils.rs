@@ -0,0 +1,132 @@
+use solana_program::account_info::AccountInfo;
+use solana_program::clock::{Clock, Epoch};
+use solana_program::entrypoint::{ProgramResult, SUCCESS};
+use solana_program::instruction::Instruction;
+use solana_program::program_error::ProgramError;
+use solana_program::program_stubs::{SyscallStubs, set_syscall_stubs};
+use solana_program::program_utils::limited_deserialize;
+use solana_program::pubkey::Pubkey;
+use solana_program::rent::Rent;
+use solana_program::system_instruction::SystemInstruction;
+use solana_program::system_program;
+use std::cell::RefCell;
+use std::sync::Once;
+
+thread_local! {
+    static INVOKED: RefCell<Vec<SystemInstruction>> = const { RefCell::new(vec![]) };
+    static CLOCK: RefCell<Clock> = RefCell::new(Clock::default());
+}
+
+/// Handler of the CPIs to programs other than the system program,
+/// e.g. `spl_token_2022::processor::Processor::process`
+pub type CpiProcessor = fn(&Pubkey, &[AccountInfo], &[u8]) -> ProgramResult;
+
+/// Serves the rent and clock sysvars and runs CPIs: system instructions are recorded and
+/// transfers move the lamports, other programs go to `processor`. PDAs of `program_id`
+/// signed for by their seeds are signers of the CPI.
+struct TestSyscalls {
+    program_id: Pubkey,
+    processor: Option<CpiProcessor>,
+}
+
+impl SyscallStubs for TestSyscalls {
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        SUCCESS
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = CLOCK.with(|clock| clock.borrow().clone());
+        unsafe { *(var_addr as *mut Clock) = clock };
+        SUCCESS
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let signers: Vec<Pubkey> = signers_seeds
+            .iter()
+            .filter_map(|seeds| Pubkey::create_program_address(seeds, &self.program_id).ok())
+            .collect();
+        let mut accounts = vec![];
+        for meta in &instruction.accounts {
+            let mut info = account_infos
+                .iter()
+                .find(|info| *info.key == meta.pubkey)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?
+                .clone();
+            info.is_signer |= signers.contains(info.key);
+            accounts.push(info);
+        }
+        if instruction.program_id != system_program::id() {
+            let processor = self.processor.ok_or(ProgramError::IncorrectProgramId)?;
+            return processor(&instruction.program_id, &accounts, &instruction.data);
+        }
+
+        let system_instruction: SystemInstruction = limited_deserialize(&instruction.data, 1232)
+            .map_err(|_| ProgramError::InvalidInstructionData)?;
+        if let SystemInstruction::Transfer { lamports } = system_instruction {
+            **accounts[0].try_borrow_mut_lamports()? -= lamports;
+            **accounts[1].try_borrow_mut_lamports()? += lamports;
+        }
+        INVOKED.with(|invoked| invoked.borrow_mut().push(system_instruction));
+        Ok(())
+    }
+}
+
+/// Install the syscall stubs for the program `program_id`, once per test binary
+pub fn stub_syscalls(program_id: Pubkey, processor: Option<CpiProcessor>) {
+    static STUBS: Once = Once::new();
+    STUBS.call_once(|| {
+        set_syscall_stubs(Box::new(TestSyscalls {
+            program_id,
+            processor,
+        }));
+    });
+}
+
+/// System program instructions invoked on this thread since the last call
+pub fn take_invoked() -> Vec<SystemInstruction> {
+    INVOKED.with(|invoked| invoked.take())
+}
+
+/// Clock returned by `Clock::get()` on this thread
+pub fn set_clock(clock: Clock) {
+    CLOCK.with(|current| *current.borrow_mut() = clock);
+}
+
+/// Backing storage of an `AccountInfo`
+pub struct TestAccount {
+    pub key: Pubkey,
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub data: Vec<u8>,
+}
+
+impl TestAccount {
+    pub fn new(key: Pubkey, owner: Pubkey, lamports: u64, data: Vec<u8>) -> Self {
+        Self {
+            key,
+            owner,
+            lamports,
+            data,
+        }
+    }
+
+    /// A writable, non-executable account
+    pub fn info(&mut self, is_signer: bool) -> AccountInfo<'_> {
+        AccountInfo::new(
+            &self.key,
+            is_signer,
+            true,
+            &mut self.lamports,
+            &mut self.data,
+            &self.owner,
+            false,
+            Epoch::default(),
+        )
+    }
+}
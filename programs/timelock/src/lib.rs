@@ -0,0 +1,176 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::clock::Clock;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use thiserror::Error;
+
+#[cfg(feature = "program")]
+pub mod processor;
+
+#[cfg(feature = "program")]
+pub use processor::process_instruction;
+
+solana_program::declare_id!("BmjDfg9YZDQ9FuxmNui9TUzRfY3DHZwihvueQ8SctiPY");
+
+#[cfg(all(feature = "program", not(feature = "no-entrypoint")))]
+solana_program::entrypoint!(process_instruction);
+
+pub const TIMELOCK_SEED: &[u8] = b"timelock";
+
+/// Errors of the time-lock program, returned as `ProgramError::Custom(code)`
+#[derive(Clone, Copy, Debug, Error, PartialEq, Eq)]
+pub enum TimeLockError {
+    #[error("vault is already initialized")]
+    AlreadyInitialized = 0,
+    #[error("vault is not initialized")]
+    Uninitialized = 1,
+    #[error("vault account is not the PDA of the funder and id")]
+    InvalidVaultAddress = 2,
+    #[error("signer is not the beneficiary of this vault")]
+    InvalidBeneficiary = 3,
+    #[error("schedule needs start <= cliff <= end and start < end")]
+    InvalidSchedule = 4,
+    #[error("nothing is unlocked yet")]
+    StillLocked = 5,
+    #[error("account is not the clock sysvar")]
+    InvalidClockAccount = 6,
+    #[error("amount must be greater than zero")]
+    ZeroAmount = 7,
+}
+
+impl TimeLockError {
+    /// The error behind a `ProgramError::Custom` code, e.g. from a failed transaction
+    pub fn from_code(code: u32) -> Option<Self> {
+        Some(match code {
+            0 => TimeLockError::AlreadyInitialized,
+            1 => TimeLockError::Uninitialized,
+            2 => TimeLockError::InvalidVaultAddress,
+            3 => TimeLockError::InvalidBeneficiary,
+            4 => TimeLockError::InvalidSchedule,
+            5 => TimeLockError::StillLocked,
+            6 => TimeLockError::InvalidClockAccount,
+            7 => TimeLockError::ZeroAmount,
+            _ => return None,
+        })
+    }
+}
+
+impl From<TimeLockError> for ProgramError {
+    fn from(error: TimeLockError) -> Self {
+        ProgramError::Custom(error as u32)
+    }
+}
+
+/// When a locked amount is released
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Schedule {
+    /// Everything at the first block with `Clock::unix_timestamp` at or past the timestamp
+    UnlockAt { unix_timestamp: i64 },
+    /// Everything from the slot on
+    UnlockAtSlot { slot: u64 },
+    /// Nothing before `cliff`, then linearly from `start` until all is released at `end`
+    ///
+    /// The share accrued between `start` and `cliff` is released at once at the cliff.
+    Vesting { start: i64, cliff: i64, end: i64 },
+}
+
+impl Schedule {
+    /// Largest borsh encoding, of `Schedule::Vesting`
+    pub const LEN: usize = 1 + 8 + 8 + 8;
+
+    /// Vesting schedules need `start <= cliff <= end`, `start < end`, and a span that fits
+    /// into an `i64`
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Schedule::UnlockAt { .. } | Schedule::UnlockAtSlot { .. } => true,
+            Schedule::Vesting { start, cliff, end } => {
+                start <= cliff && cliff <= end && start < end && end.checked_sub(start).is_some()
+            }
+        }
+    }
+
+    /// How much of `total` is released at `clock`
+    pub fn vested(&self, total: u64, clock: &Clock) -> u64 {
+        match *self {
+            Schedule::UnlockAt { unix_timestamp } if clock.unix_timestamp >= unix_timestamp => {
+                total
+            }
+            Schedule::UnlockAtSlot { slot } if clock.slot >= slot => total,
+            Schedule::Vesting { start, cliff, end } => {
+                let now = clock.unix_timestamp;
+                if now < cliff {
+                    0
+                } else if now >= end {
+                    total
+                } else {
+                    // below `total`, as `now - start < end - start`; widened, so even a
+                    // schedule failing `is_valid` can't overflow
+                    let elapsed = (now as i128 - start as i128) as u128;
+                    let duration = (end as i128 - start as i128) as u128;
+                    (total as u128 * elapsed / duration) as u64
+                }
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// Vault state, stored in the PDA of `[TIMELOCK_SEED, funder, id]` next to the locked lamports
+///
+/// Starts with `is_initialized` like `HelloState` of the getclock program.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeLock {
+    pub is_initialized: bool,
+    pub funder: Pubkey,
+    pub beneficiary: Pubkey,
+    pub id: u64,
+    pub bump: u8,
+    /// Lamports locked on creation, on top of the rent
+    pub total: u64,
+    pub withdrawn: u64,
+    pub schedule: Schedule,
+}
+
+impl TimeLock {
+    pub const LEN: usize = 1 + 32 + 32 + 8 + 1 + 8 + 8 + Schedule::LEN;
+
+    /// Released and not withdrawn yet
+    pub fn claimable(&self, clock: &Clock) -> u64 {
+        self.schedule
+            .vested(self.total, clock)
+            .saturating_sub(self.withdrawn)
+    }
+}
+
+/// Instructions of the time-lock program, borsh encoded
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub enum TimeLockInstruction {
+    /// Lock `amount` lamports of the funder for the beneficiary
+    ///
+    /// Accounts required
+    /// 1. [signer, writable] Funder
+    /// 2. [writable] Vault PDA
+    /// 3. [] System program
+    Create {
+        id: u64,
+        amount: u64,
+        beneficiary: Pubkey,
+        schedule: Schedule,
+    },
+    /// Pay out everything released so far, the last withdrawal closes the vault and
+    /// returns its rent to the funder
+    ///
+    /// Accounts required
+    /// 1. [signer, writable] Beneficiary
+    /// 2. [writable] Vault PDA
+    /// 3. [writable] Funder
+    /// 4. [] Clock sysvar, optional, read through `Clock::get()` when left out
+    Withdraw,
+}
+
+pub fn find_vault_address(funder: &Pubkey, id: u64, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[TIMELOCK_SEED, funder.as_ref(), &id.to_le_bytes()],
+        program_id,
+    )
+}
@@ -0,0 +1,482 @@
+use crate::{
+    Schedule, TIMELOCK_SEED, TimeLock, TimeLockError, TimeLockInstruction, find_vault_address,
+};
+use borsh::{BorshDeserialize, BorshSerialize};
+use program_utils::create_pda_account;
+use solana_program::account_info::{AccountInfo, next_account_info};
+use solana_program::clock::Clock;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::msg;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::system_program;
+use solana_program::sysvar::Sysvar;
+use solana_program::sysvar::clock::ID as SYSVAR_CLOCK_ID;
+
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let instruction = TimeLockInstruction::try_from_slice(instruction_data)
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+    match instruction {
+        TimeLockInstruction::Create {
+            id,
+            amount,
+            beneficiary,
+            schedule,
+        } => {
+            msg!("Instruction: Create");
+            process_create(program_id, accounts, id, amount, beneficiary, schedule)
+        }
+        TimeLockInstruction::Withdraw => {
+            msg!("Instruction: Withdraw");
+            process_withdraw(program_id, accounts)
+        }
+    }
+}
+
+fn process_create(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    id: u64,
+    amount: u64,
+    beneficiary: Pubkey,
+    schedule: Schedule,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let funder = next_account_info(accounts_iter)?;
+    let vault = next_account_info(accounts_iter)?;
+    let system_program_account = next_account_info(accounts_iter)?;
+
+    if !funder.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *system_program_account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if amount == 0 {
+        return Err(TimeLockError::ZeroAmount.into());
+    }
+    if !schedule.is_valid() {
+        return Err(TimeLockError::InvalidSchedule.into());
+    }
+    let (vault_address, bump) = find_vault_address(funder.key, id, program_id);
+    if *vault.key != vault_address {
+        return Err(TimeLockError::InvalidVaultAddress.into());
+    }
+    if vault.owner == program_id {
+        return Err(TimeLockError::AlreadyInitialized.into());
+    }
+
+    create_pda_account(
+        funder,
+        vault,
+        system_program_account,
+        TimeLock::LEN,
+        amount,
+        program_id,
+        &[
+            TIMELOCK_SEED,
+            funder.key.as_ref(),
+            &id.to_le_bytes(),
+            &[bump],
+        ],
+    )?;
+
+    let state = TimeLock {
+        is_initialized: true,
+        funder: *funder.key,
+        beneficiary,
+        id,
+        bump,
+        total: amount,
+        withdrawn: 0,
+        schedule,
+    };
+    state.serialize(&mut &mut vault.data.borrow_mut()[..])?;
+    msg!("Locked {} lamports for {}", amount, beneficiary);
+    Ok(())
+}
+
+fn process_withdraw(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let beneficiary = next_account_info(accounts_iter)?;
+    let vault = next_account_info(accounts_iter)?;
+    let funder = next_account_info(accounts_iter)?;
+
+    if !beneficiary.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if vault.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // `Schedule::UnlockAt` encodes shorter than the space reserved for it
+    let mut state = TimeLock::deserialize(&mut &vault.data.borrow()[..])?;
+    if !state.is_initialized {
+        return Err(TimeLockError::Uninitialized.into());
+    }
+    if *beneficiary.key != state.beneficiary {
+        return Err(TimeLockError::InvalidBeneficiary.into());
+    }
+    if *funder.key != state.funder {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // Passing SYSVAR_CLOCK_PUBKEY like `getclock::process_instruction` still works,
+    // otherwise the clock is read directly like `getclock::process_instruction_2`
+    let clock = match next_account_info(accounts_iter) {
+        Ok(sysvar_clock) if *sysvar_clock.key != SYSVAR_CLOCK_ID => {
+            return Err(TimeLockError::InvalidClockAccount.into());
+        }
+        Ok(sysvar_clock) => Clock::from_account_info(sysvar_clock)?,
+        Err(_) => Clock::get()?,
+    };
+    msg!("Current Timestamp: {}", clock.unix_timestamp);
+
+    let amount = state.claimable(&clock);
+    if amount == 0 {
+        return Err(TimeLockError::StillLocked.into());
+    }
+    transfer_lamports(vault, beneficiary, amount)?;
+    state.withdrawn += amount;
+    msg!("Withdrew {} of {} lamports", state.withdrawn, state.total);
+
+    if state.withdrawn == state.total {
+        transfer_lamports(vault, funder, vault.lamports())?;
+        vault.data.borrow_mut().fill(0);
+        return Ok(());
+    }
+    state.serialize(&mut &mut vault.data.borrow_mut()[..])?;
+    Ok(())
+}
+
+/// The program owns the vault, so it moves lamports without the system program
+fn transfer_lamports(from: &AccountInfo, to: &AccountInfo, lamports: u64) -> ProgramResult {
+    if **from.try_borrow_lamports()? < lamports {
+        return Err(ProgramError::InsufficientFunds);
+    }
+    **from.try_borrow_mut_lamports()? -= lamports;
+    **to.try_borrow_mut_lamports()? += lamports;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id;
+    use program_utils::test_utils::{TestAccount, set_clock, stub_syscalls, take_invoked};
+    use solana_program::rent::Rent;
+    use solana_program::system_instruction::SystemInstruction;
+    use solana_sdk::account::create_account_for_test;
+
+    fn clock(slot: u64, unix_timestamp: i64) -> Clock {
+        Clock {
+            slot,
+            unix_timestamp,
+            ..Clock::default()
+        }
+    }
+
+    fn create(id: u64, amount: u64, beneficiary: Pubkey, schedule: Schedule) -> Vec<u8> {
+        borsh::to_vec(&TimeLockInstruction::Create {
+            id,
+            amount,
+            beneficiary,
+            schedule,
+        })
+        .unwrap()
+    }
+
+    /// A funder, the vault PDA of its lock `id` holding `lamports` and the system program;
+    /// the system program isn't run here, so the vault data is sized already
+    fn create_accounts(id: u64, lamports: u64) -> [TestAccount; 3] {
+        let funder = TestAccount::new(
+            Pubkey::new_unique(),
+            system_program::id(),
+            1_000_000_000,
+            vec![],
+        );
+        let (vault_key, _) = find_vault_address(&funder.key, id, &crate::id());
+        let vault = TestAccount::new(
+            vault_key,
+            system_program::id(),
+            lamports,
+            vec![0; TimeLock::LEN],
+        );
+        let system = TestAccount::new(system_program::id(), Pubkey::default(), 1, vec![]);
+        [funder, vault, system]
+    }
+
+    fn clock_account(unix_timestamp: i64) -> TestAccount {
+        let data = create_account_for_test(&clock(0, unix_timestamp)).data;
+        TestAccount::new(SYSVAR_CLOCK_ID, Pubkey::default(), 1, data)
+    }
+
+    #[test]
+    fn test_vesting_schedule() {
+        let vesting = Schedule::Vesting {
+            start: 1_000,
+            cliff: 1_250,
+            end: 2_000,
+        };
+        assert!(vesting.is_valid());
+        assert_eq!(vesting.vested(1_000, &clock(0, 1_249)), 0);
+        // the share accrued before the cliff comes at once
+        assert_eq!(vesting.vested(1_000, &clock(0, 1_250)), 250);
+        assert_eq!(vesting.vested(1_000, &clock(0, 1_500)), 500);
+        assert_eq!(vesting.vested(1_000, &clock(0, 5_000)), 1_000);
+        // no overflow on large amounts
+        assert!(vesting.vested(u64::MAX, &clock(0, 1_999)) > u64::MAX / 1_000 * 998);
+
+        let lock = Schedule::UnlockAtSlot { slot: 100 };
+        assert_eq!(lock.vested(10, &clock(99, i64::MAX)), 0);
+        assert_eq!(lock.vested(10, &clock(100, 0)), 10);
+
+        assert!(
+            !Schedule::Vesting {
+                start: 10,
+                cliff: 5,
+                end: 20
+            }
+            .is_valid()
+        );
+
+        // a span beyond `i64` is rejected, and still computes without overflow
+        let huge = Schedule::Vesting {
+            start: i64::MIN,
+            cliff: 0,
+            end: i64::MAX,
+        };
+        assert!(!huge.is_valid());
+        assert_eq!(huge.vested(1_000, &clock(0, 0)), 500);
+        assert_eq!(huge.vested(1_000, &clock(0, -1)), 0);
+    }
+
+    #[test]
+    fn test_withdraw_with_clock_sysvar() {
+        let funder_key = Pubkey::new_unique();
+        let (vault_key, bump) = find_vault_address(&funder_key, 0, &id());
+        let state = TimeLock {
+            is_initialized: true,
+            funder: funder_key,
+            beneficiary: Pubkey::new_unique(),
+            id: 0,
+            bump,
+            total: 1_000,
+            withdrawn: 0,
+            schedule: Schedule::Vesting {
+                start: 0,
+                cliff: 100,
+                end: 1_000,
+            },
+        };
+        let mut data = vec![0; TimeLock::LEN];
+        state.serialize(&mut &mut data[..]).unwrap();
+        let mut vault = TestAccount::new(vault_key, id(), 1_000 + 50, data);
+        let mut beneficiary = TestAccount::new(state.beneficiary, system_program::id(), 0, vec![]);
+        let mut funder = TestAccount::new(funder_key, system_program::id(), 0, vec![]);
+        let withdraw = borsh::to_vec(&TimeLockInstruction::Withdraw).unwrap();
+
+        // before the cliff
+        let mut sysvar = clock_account(99);
+        let accounts = [
+            beneficiary.info(true),
+            vault.info(false),
+            funder.info(false),
+            sysvar.info(false),
+        ];
+        assert_eq!(
+            process_instruction(&id(), &accounts, &withdraw),
+            Err(TimeLockError::StillLocked.into())
+        );
+
+        // some other account in place of the clock
+        let mut fake = TestAccount::new(
+            Pubkey::new_unique(),
+            Pubkey::default(),
+            1,
+            sysvar.data.clone(),
+        );
+        let accounts = [
+            beneficiary.info(true),
+            vault.info(false),
+            funder.info(false),
+            fake.info(false),
+        ];
+        assert_eq!(
+            process_instruction(&id(), &accounts, &withdraw),
+            Err(TimeLockError::InvalidClockAccount.into())
+        );
+
+        let mut sysvar = clock_account(400);
+        let accounts = [
+            beneficiary.info(true),
+            vault.info(false),
+            funder.info(false),
+            sysvar.info(false),
+        ];
+        process_instruction(&id(), &accounts, &withdraw).unwrap();
+        drop(accounts);
+        assert_eq!(beneficiary.lamports, 400);
+        let updated = TimeLock::deserialize(&mut vault.data.as_slice()).unwrap();
+        assert_eq!(updated.withdrawn, 400);
+        assert_eq!(updated.claimable(&clock(0, 400)), 0);
+
+        // the last withdrawal closes the vault, the rent goes back to the funder
+        let mut sysvar = clock_account(1_000);
+        let accounts = [
+            beneficiary.info(true),
+            vault.info(false),
+            funder.info(false),
+            sysvar.info(false),
+        ];
+        process_instruction(&id(), &accounts, &withdraw).unwrap();
+        drop(accounts);
+        assert_eq!(beneficiary.lamports, 1_000);
+        assert_eq!(funder.lamports, 50);
+        assert_eq!(vault.lamports, 0);
+    }
+
+    #[test]
+    fn test_create() {
+        stub_syscalls(id(), None);
+        take_invoked();
+        let beneficiary = Pubkey::new_unique();
+        let schedule = Schedule::UnlockAt {
+            unix_timestamp: 1_000,
+        };
+        let [mut funder, mut vault, mut system] = create_accounts(7, 0);
+
+        let accounts = [funder.info(true), vault.info(false), system.info(false)];
+        assert_eq!(
+            process_instruction(&id(), &accounts, &create(7, 0, beneficiary, schedule)),
+            Err(TimeLockError::ZeroAmount.into())
+        );
+        let backwards = Schedule::Vesting {
+            start: 10,
+            cliff: 5,
+            end: 20,
+        };
+        assert_eq!(
+            process_instruction(&id(), &accounts, &create(7, 500, beneficiary, backwards)),
+            Err(TimeLockError::InvalidSchedule.into())
+        );
+        // the vault of lock 7 doesn't derive from id 8
+        assert_eq!(
+            process_instruction(&id(), &accounts, &create(8, 500, beneficiary, schedule)),
+            Err(TimeLockError::InvalidVaultAddress.into())
+        );
+        assert!(take_invoked().is_empty());
+
+        process_instruction(&id(), &accounts, &create(7, 500, beneficiary, schedule)).unwrap();
+        drop(accounts);
+        assert_eq!(
+            take_invoked(),
+            [SystemInstruction::CreateAccount {
+                lamports: Rent::default().minimum_balance(TimeLock::LEN) + 500,
+                space: TimeLock::LEN as u64,
+                owner: id(),
+            }]
+        );
+        let state = TimeLock::deserialize(&mut vault.data.as_slice()).unwrap();
+        assert_eq!(
+            (state.funder, state.beneficiary, state.id, state.total),
+            (funder.key, beneficiary, 7, 500)
+        );
+        assert_eq!(state.schedule, schedule);
+    }
+
+    #[test]
+    fn test_create_funded_vault() {
+        stub_syscalls(id(), None);
+        take_invoked();
+        // someone sent lamports ahead, `create_account` would fail
+        let [mut funder, mut vault, mut system] = create_accounts(1, 300);
+        let schedule = Schedule::UnlockAtSlot { slot: 10 };
+
+        let accounts = [funder.info(true), vault.info(false), system.info(false)];
+        let data = create(1, 500, Pubkey::new_unique(), schedule);
+        process_instruction(&id(), &accounts, &data).unwrap();
+        drop(accounts);
+
+        let minimum = Rent::default().minimum_balance(TimeLock::LEN);
+        assert_eq!(
+            take_invoked(),
+            [
+                SystemInstruction::Transfer {
+                    lamports: minimum - 300 + 500
+                },
+                SystemInstruction::Allocate {
+                    space: TimeLock::LEN as u64
+                },
+                SystemInstruction::Assign { owner: id() },
+            ]
+        );
+        assert_eq!(vault.lamports, minimum + 500);
+        assert_eq!(funder.lamports, 1_000_000_000 - (minimum - 300 + 500));
+    }
+
+    #[test]
+    fn test_withdraw_with_stubbed_clock() {
+        stub_syscalls(id(), None);
+        let funder_key = Pubkey::new_unique();
+        let (vault_key, bump) = find_vault_address(&funder_key, 0, &id());
+        let state = TimeLock {
+            is_initialized: true,
+            funder: funder_key,
+            beneficiary: Pubkey::new_unique(),
+            id: 0,
+            bump,
+            total: 1_000,
+            withdrawn: 0,
+            schedule: Schedule::UnlockAt {
+                unix_timestamp: 500,
+            },
+        };
+        let mut data = vec![0; TimeLock::LEN];
+        state.serialize(&mut &mut data[..]).unwrap();
+        let mut vault = TestAccount::new(vault_key, id(), 1_000 + 50, data);
+        let mut beneficiary = TestAccount::new(state.beneficiary, system_program::id(), 0, vec![]);
+        let mut funder = TestAccount::new(funder_key, system_program::id(), 0, vec![]);
+        let withdraw = borsh::to_vec(&TimeLockInstruction::Withdraw).unwrap();
+
+        // without the clock account, `Clock::get()` is read
+        set_clock(clock(0, 499));
+        let accounts = [
+            beneficiary.info(true),
+            vault.info(false),
+            funder.info(false),
+        ];
+        assert_eq!(
+            process_instruction(&id(), &accounts, &withdraw),
+            Err(TimeLockError::StillLocked.into())
+        );
+
+        set_clock(clock(0, 500));
+        process_instruction(&id(), &accounts, &withdraw).unwrap();
+        drop(accounts);
+        assert_eq!(beneficiary.lamports, 1_000);
+        assert_eq!((funder.lamports, vault.lamports), (50, 0));
+    }
+
+    #[test]
+    fn test_state_len() {
+        let state = TimeLock {
+            is_initialized: true,
+            funder: Pubkey::new_unique(),
+            beneficiary: Pubkey::new_unique(),
+            id: 0,
+            bump: 0,
+            total: 0,
+            withdrawn: 0,
+            schedule: Schedule::Vesting {
+                start: 0,
+                cliff: 0,
+                end: 1,
+            },
+        };
+        assert_eq!(borsh::to_vec(&state).unwrap().len(), TimeLock::LEN);
+    }
+}
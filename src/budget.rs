@@ -0,0 +1,247 @@
+use crate::lookup_table::unsigned_transaction;
+use solana_client::{
+    client_error::ClientError, nonblocking::rpc_client::RpcClient,
+    rpc_config::RpcSimulateTransactionConfig,
+};
+use solana_sdk::{
+    compute_budget::{self, ComputeBudgetInstruction},
+    instruction::Instruction,
+    message::{AddressLookupTableAccount, CompileError},
+    pubkey::Pubkey,
+    transaction::TransactionError,
+};
+use thiserror::Error;
+
+/// Highest compute unit limit a transaction may request
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// `getRecentPrioritizationFees` accepts at most this many accounts
+const MAX_FEE_ACCOUNTS: usize = 128;
+
+#[derive(Debug, Error)]
+pub enum BudgetError {
+    /// The transaction fails in simulation, sending it would fail the same way
+    #[error("simulation failed: {error}")]
+    SimulationFailed {
+        error: TransactionError,
+        logs: Vec<String>,
+    },
+    #[error("simulation didn't report units consumed")]
+    NoUnitsConsumed,
+    #[error(transparent)]
+    Compile(#[from] CompileError),
+    #[error(transparent)]
+    Rpc(#[from] ClientError),
+}
+
+/// How `estimate_compute_budget` sizes the limit and picks the price
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ComputeBudgetConfig {
+    /// Added on top of the simulated units, in percent
+    pub margin_percent: u32,
+    /// Lower bound of the margin, small transactions vary by more than a few percent
+    pub min_margin_units: u32,
+    /// Percentile (0-100) of the recent prioritization fees of the writable accounts
+    pub fee_percentile: u8,
+    /// Micro-lamports per compute unit
+    pub min_unit_price: u64,
+    pub max_unit_price: u64,
+}
+
+impl Default for ComputeBudgetConfig {
+    fn default() -> Self {
+        Self {
+            margin_percent: 10,
+            min_margin_units: 1_000,
+            fee_percentile: 75,
+            min_unit_price: 0,
+            max_unit_price: 1_000_000,
+        }
+    }
+}
+
+impl ComputeBudgetConfig {
+    /// Simulated units plus margin, capped at `MAX_COMPUTE_UNIT_LIMIT`
+    pub fn unit_limit(&self, units_consumed: u64) -> u32 {
+        let margin =
+            (units_consumed * self.margin_percent as u64 / 100).max(self.min_margin_units as u64);
+        (units_consumed + margin).min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32
+    }
+
+    /// The configured percentile of `fees`, clamped to the price bounds
+    pub fn unit_price(&self, fees: &[u64]) -> u64 {
+        percentile(fees, self.fee_percentile).clamp(self.min_unit_price, self.max_unit_price)
+    }
+}
+
+/// Compute unit limit and price of a transaction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ComputeBudget {
+    pub unit_limit: u32,
+    /// Micro-lamports per compute unit
+    pub unit_price: u64,
+    /// What the simulation consumed
+    pub units_consumed: u64,
+}
+
+impl ComputeBudget {
+    /// The two compute budget instructions, to be put in front of the others
+    pub fn instructions(&self) -> Vec<Instruction> {
+        vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(self.unit_limit),
+            ComputeBudgetInstruction::set_compute_unit_price(self.unit_price),
+        ]
+    }
+
+    /// `instructions` with this budget put in front
+    pub fn apply(&self, instructions: &[Instruction]) -> Vec<Instruction> {
+        let mut budgeted = self.instructions();
+        budgeted.extend_from_slice(instructions);
+        budgeted
+    }
+
+    /// Priority fee in lamports on top of the signature fees
+    pub fn priority_fee(&self) -> u64 {
+        (self.unit_limit as u128 * self.unit_price as u128).div_ceil(1_000_000) as u64
+    }
+}
+
+/// Nearest-rank percentile, 0 for no values
+pub fn percentile(values: &[u64], percentile: u8) -> u64 {
+    if values.is_empty() {
+        return 0;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let rank = (percentile.min(100) as usize * sorted.len()).div_ceil(100);
+    sorted[rank.saturating_sub(1)]
+}
+
+/// True if the caller already set a compute budget, which is then left alone
+pub fn has_compute_budget(instructions: &[Instruction]) -> bool {
+    instructions
+        .iter()
+        .any(|instruction| instruction.program_id == compute_budget::id())
+}
+
+/// The payer and every account an instruction writes, these decide the fee market
+pub fn writable_accounts(instructions: &[Instruction], payer: &Pubkey) -> Vec<Pubkey> {
+    let mut accounts = vec![*payer];
+    for meta in instructions.iter().flat_map(|ix| &ix.accounts) {
+        if meta.is_writable && !accounts.contains(&meta.pubkey) {
+            accounts.push(meta.pubkey);
+        }
+    }
+    accounts.truncate(MAX_FEE_ACCOUNTS);
+    accounts
+}
+
+/// Simulate `instructions` and size a compute budget for them
+///
+/// The simulation runs with the maximum limit and the same instruction layout as the
+/// final transaction, so instruction indexes in a simulation error match the sent one.
+/// Pass the lookup tables the transaction will be sent with, a batch may only fit with them.
+pub async fn estimate_compute_budget(
+    client: &RpcClient,
+    instructions: &[Instruction],
+    payer: &Pubkey,
+    lookup_tables: &[AddressLookupTableAccount],
+    config: &ComputeBudgetConfig,
+) -> Result<ComputeBudget, BudgetError> {
+    let probe = ComputeBudget {
+        unit_limit: MAX_COMPUTE_UNIT_LIMIT,
+        unit_price: 0,
+        units_consumed: 0,
+    };
+    let transaction = unsigned_transaction(payer, &probe.apply(instructions), lookup_tables)?;
+    let simulation = client
+        .simulate_transaction_with_config(
+            &transaction,
+            RpcSimulateTransactionConfig {
+                sig_verify: false,
+                replace_recent_blockhash: true,
+                commitment: Some(client.commitment()),
+                ..RpcSimulateTransactionConfig::default()
+            },
+        )
+        .await?
+        .value;
+    if let Some(err) = simulation.err {
+        return Err(BudgetError::SimulationFailed {
+            error: err,
+            logs: simulation.logs.unwrap_or_default(),
+        });
+    }
+    let units_consumed = simulation
+        .units_consumed
+        .ok_or(BudgetError::NoUnitsConsumed)?;
+
+    let fees: Vec<u64> = client
+        .get_recent_prioritization_fees(&writable_accounts(instructions, payer))
+        .await?
+        .into_iter()
+        .map(|fee| fee.prioritization_fee)
+        .collect();
+
+    Ok(ComputeBudget {
+        unit_limit: config.unit_limit(units_consumed),
+        unit_price: config.unit_price(&fees),
+        units_consumed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{instruction::AccountMeta, system_instruction};
+
+    #[test]
+    fn test_unit_limit() {
+        let config = ComputeBudgetConfig::default();
+        assert_eq!(config.unit_limit(150), 1_150);
+        assert_eq!(config.unit_limit(100_000), 110_000);
+        assert_eq!(config.unit_limit(1_390_000), MAX_COMPUTE_UNIT_LIMIT);
+    }
+
+    #[test]
+    fn test_percentile_and_price() {
+        let fees = [0, 0, 10, 20, 30, 40, 50, 60, 5_000_000, 100];
+        assert_eq!(percentile(&fees, 50), 30);
+        assert_eq!(percentile(&fees, 75), 60);
+        assert_eq!(percentile(&fees, 100), 5_000_000);
+        assert_eq!(percentile(&fees, 0), 0);
+        assert_eq!(percentile(&[], 75), 0);
+
+        let config = ComputeBudgetConfig {
+            fee_percentile: 100,
+            min_unit_price: 5,
+            ..ComputeBudgetConfig::default()
+        };
+        assert_eq!(config.unit_price(&fees), config.max_unit_price);
+        assert_eq!(config.unit_price(&[]), 5);
+    }
+
+    #[test]
+    fn test_instructions() {
+        let payer = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let readonly = Pubkey::new_unique();
+        let mut transfer = system_instruction::transfer(&payer, &to, 1);
+        transfer
+            .accounts
+            .push(AccountMeta::new_readonly(readonly, false));
+        assert_eq!(writable_accounts(&[transfer.clone()], &payer), [payer, to]);
+
+        let budget = ComputeBudget {
+            unit_limit: 1_500,
+            unit_price: 2_000,
+            units_consumed: 450,
+        };
+        let budgeted = budget.apply(&[transfer.clone()]);
+        assert_eq!(budgeted.len(), 3);
+        assert_eq!(budgeted[2], transfer);
+        assert!(has_compute_budget(&budgeted));
+        assert!(!has_compute_budget(&[transfer]));
+        assert_eq!(budget.priority_fee(), 3);
+    }
+}
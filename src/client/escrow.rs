@@ -0,0 +1,400 @@
+//! Instruction builders and RPC helpers for the escrow program in `crate::escrow`
+
+use crate::escrow::{Asset, Escrow, EscrowError, EscrowInstruction, vault_address};
+use crate::pda::{Pda, Seeds};
+use crate::sender::{SendError, TransactionSender};
+use crate::token::program::{TokenProgram, detect_token_program};
+use anyhow::{Result, bail};
+use borsh::BorshDeserialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_program,
+};
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+
+/// Seeds of the escrow of `maker` with `id`, the same the program checks
+pub fn escrow_seeds(maker: &Pubkey, id: u64) -> Seeds {
+    Seeds::new().str("escrow").pubkey(maker).u64(id)
+}
+
+pub fn escrow_address(program_id: &Pubkey, maker: &Pubkey, id: u64) -> Result<Pda> {
+    Ok(escrow_seeds(maker, id).find(program_id)?)
+}
+
+// source, destination, mint and token program of a token asset, nothing for SOL
+fn token_accounts(
+    asset: &Asset,
+    token_program: TokenProgram,
+    source: &Pubkey,
+    destination: &Pubkey,
+) -> Vec<AccountMeta> {
+    match asset {
+        Asset::Sol { .. } => vec![],
+        Asset::Token { mint, .. } => vec![
+            AccountMeta::new(*source, false),
+            AccountMeta::new(*destination, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(token_program.id(), false),
+        ],
+    }
+}
+
+// vault, destination, mint and token program of a token deposit paid out of the vault,
+// the mint is writable to take the vault's withheld transfer fees
+fn vault_accounts(
+    escrow_address: &Pubkey,
+    asset: &Asset,
+    token_program: TokenProgram,
+    destination: &Pubkey,
+) -> Vec<AccountMeta> {
+    let mut accounts = token_accounts(
+        asset,
+        token_program,
+        &vault(escrow_address, asset, token_program),
+        destination,
+    );
+    if let Some(mint) = accounts.get_mut(2) {
+        mint.is_writable = true;
+    }
+    accounts
+}
+
+// token account of `owner` for a token asset, the default address for SOL where it is unused
+fn token_account(asset: &Asset, token_program: TokenProgram, owner: &Pubkey) -> Pubkey {
+    match asset {
+        Asset::Sol { .. } => Pubkey::default(),
+        Asset::Token { mint, .. } => token_program.associated_token_address(owner, mint),
+    }
+}
+
+fn vault(escrow_address: &Pubkey, asset: &Asset, token_program: TokenProgram) -> Pubkey {
+    match asset {
+        Asset::Sol { .. } => Pubkey::default(),
+        Asset::Token { mint, .. } => vault_address(escrow_address, mint, &token_program.id()),
+    }
+}
+
+pub fn initialize(
+    program_id: &Pubkey,
+    maker: &Pubkey,
+    id: u64,
+    deposit: Asset,
+    receive: Asset,
+) -> Result<Instruction> {
+    let pda = escrow_address(program_id, maker, id)?;
+    Ok(Instruction::new_with_borsh(
+        *program_id,
+        &EscrowInstruction::Initialize {
+            id,
+            bump: pda.bump,
+            deposit,
+            receive,
+        },
+        vec![
+            AccountMeta::new(*maker, true),
+            AccountMeta::new(pda.address, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    ))
+}
+
+/// `token_program` is the deposit mint's program, ignored for SOL deposits
+pub fn deposit(
+    program_id: &Pubkey,
+    escrow_address: &Pubkey,
+    escrow: &Escrow,
+    token_program: TokenProgram,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(escrow.maker, true),
+        AccountMeta::new(*escrow_address, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    accounts.extend(token_accounts(
+        &escrow.deposit,
+        token_program,
+        &token_account(&escrow.deposit, token_program, &escrow.maker),
+        &vault(escrow_address, &escrow.deposit, token_program),
+    ));
+    Instruction::new_with_borsh(*program_id, &EscrowInstruction::Deposit, accounts)
+}
+
+/// Tokens move between associated token accounts of the taker and the maker
+pub fn exchange(
+    program_id: &Pubkey,
+    escrow_address: &Pubkey,
+    escrow: &Escrow,
+    taker: &Pubkey,
+    deposit_program: TokenProgram,
+    receive_program: TokenProgram,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*taker, true),
+        AccountMeta::new(escrow.maker, false),
+        AccountMeta::new(*escrow_address, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    accounts.extend(vault_accounts(
+        escrow_address,
+        &escrow.deposit,
+        deposit_program,
+        &token_account(&escrow.deposit, deposit_program, taker),
+    ));
+    accounts.extend(token_accounts(
+        &escrow.receive,
+        receive_program,
+        &token_account(&escrow.receive, receive_program, taker),
+        &token_account(&escrow.receive, receive_program, &escrow.maker),
+    ));
+    Instruction::new_with_borsh(*program_id, &EscrowInstruction::Exchange, accounts)
+}
+
+pub fn cancel(
+    program_id: &Pubkey,
+    escrow_address: &Pubkey,
+    escrow: &Escrow,
+    token_program: TokenProgram,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(escrow.maker, true),
+        AccountMeta::new(*escrow_address, false),
+    ];
+    accounts.extend(vault_accounts(
+        escrow_address,
+        &escrow.deposit,
+        token_program,
+        &token_account(&escrow.deposit, token_program, &escrow.maker),
+    ));
+    Instruction::new_with_borsh(*program_id, &EscrowInstruction::Cancel, accounts)
+}
+
+/// The program error behind a failed send, if the program returned one of its own
+pub fn escrow_error(error: &SendError) -> Option<EscrowError> {
+    match error.instruction_error()? {
+        (_, InstructionError::Custom(code)) => EscrowError::from_code(*code),
+        _ => None,
+    }
+}
+
+pub async fn fetch_escrow(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    address: &Pubkey,
+) -> Result<Escrow> {
+    let account = client.get_account(address).await?;
+    if account.owner != *program_id {
+        bail!("{} is not an escrow of {}", address, program_id);
+    }
+    Ok(Escrow::deserialize(&mut account.data.as_slice())?)
+}
+
+// SPL Token for SOL, where the program is never used
+async fn asset_program(client: &RpcClient, asset: &Asset) -> Result<TokenProgram> {
+    match asset {
+        Asset::Sol { .. } => Ok(TokenProgram::SplToken),
+        Asset::Token { mint, .. } => detect_token_program(client, mint).await,
+    }
+}
+
+fn create_token_account(
+    payer: &Pubkey,
+    owner: &Pubkey,
+    asset: &Asset,
+    token_program: TokenProgram,
+) -> Option<Instruction> {
+    match asset {
+        Asset::Sol { .. } => None,
+        Asset::Token { mint, .. } => Some(create_associated_token_account_idempotent(
+            payer,
+            owner,
+            mint,
+            &token_program.id(),
+        )),
+    }
+}
+
+/// Create and fund an escrow in one transaction, returns its address
+pub async fn make_escrow(
+    sender: &TransactionSender,
+    program_id: &Pubkey,
+    maker: &Keypair,
+    id: u64,
+    deposit_asset: Asset,
+    receive_asset: Asset,
+) -> Result<Pubkey> {
+    let client = sender.client();
+    let pda = escrow_address(program_id, &maker.pubkey(), id)?;
+    let escrow = Escrow {
+        is_initialized: true,
+        maker: maker.pubkey(),
+        id,
+        bump: pda.bump,
+        funded: false,
+        deposit: deposit_asset,
+        receive: receive_asset,
+    };
+    let deposit_program = asset_program(client, &deposit_asset).await?;
+
+    let mut instructions = vec![initialize(
+        program_id,
+        &maker.pubkey(),
+        id,
+        deposit_asset,
+        receive_asset,
+    )?];
+    instructions.extend(create_token_account(
+        &maker.pubkey(),
+        &pda.address,
+        &deposit_asset,
+        deposit_program,
+    ));
+    instructions.push(deposit(program_id, &pda.address, &escrow, deposit_program));
+
+    let outcome = sender
+        .send(&instructions, &maker.pubkey(), &[maker])
+        .await?;
+    println!("Escrow: {}", pda.address);
+    println!("Escrow Signature: {}", outcome.signature);
+    Ok(pda.address)
+}
+
+/// Pay the maker and receive the deposit, the taker pays for missing token accounts
+pub async fn take_escrow(
+    sender: &TransactionSender,
+    program_id: &Pubkey,
+    taker: &Keypair,
+    escrow_address: &Pubkey,
+) -> Result<Signature> {
+    let client = sender.client();
+    let escrow = fetch_escrow(client, program_id, escrow_address).await?;
+    let deposit_program = asset_program(client, &escrow.deposit).await?;
+    let receive_program = asset_program(client, &escrow.receive).await?;
+
+    let mut instructions = vec![];
+    instructions.extend(create_token_account(
+        &taker.pubkey(),
+        &taker.pubkey(),
+        &escrow.deposit,
+        deposit_program,
+    ));
+    instructions.extend(create_token_account(
+        &taker.pubkey(),
+        &escrow.maker,
+        &escrow.receive,
+        receive_program,
+    ));
+    instructions.push(exchange(
+        program_id,
+        escrow_address,
+        &escrow,
+        &taker.pubkey(),
+        deposit_program,
+        receive_program,
+    ));
+
+    let outcome = sender
+        .send(&instructions, &taker.pubkey(), &[taker])
+        .await?;
+    Ok(outcome.signature)
+}
+
+/// Refund the deposit and close the escrow
+pub async fn cancel_escrow(
+    sender: &TransactionSender,
+    program_id: &Pubkey,
+    maker: &Keypair,
+    escrow_address: &Pubkey,
+) -> Result<Signature> {
+    let client = sender.client();
+    let escrow = fetch_escrow(client, program_id, escrow_address).await?;
+    let deposit_program = asset_program(client, &escrow.deposit).await?;
+
+    let mut instructions = vec![];
+    instructions.extend(create_token_account(
+        &maker.pubkey(),
+        &maker.pubkey(),
+        &escrow.deposit,
+        deposit_program,
+    ));
+    instructions.push(cancel(program_id, escrow_address, &escrow, deposit_program));
+
+    let outcome = sender
+        .send(&instructions, &maker.pubkey(), &[maker])
+        .await?;
+    Ok(outcome.signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escrow::{find_escrow_address, id};
+    use crate::pda::{PdaError, verify_pda};
+
+    #[test]
+    fn test_escrow_seeds_match_program() {
+        let maker = Pubkey::new_unique();
+        let (address, bump) = find_escrow_address(&maker, 42, &id());
+        assert_eq!(
+            escrow_address(&id(), &maker, 42).unwrap(),
+            Pda { address, bump }
+        );
+        assert!(verify_pda(&address, &escrow_seeds(&maker, 42), bump, &id()).is_ok());
+
+        // seeds of another maker or id derive another address
+        assert!(matches!(
+            verify_pda(&address, &escrow_seeds(&maker, 43), bump, &id()),
+            Err(PdaError::AddressMismatch { .. }) | Err(PdaError::NoViableBump)
+        ));
+        assert!(matches!(
+            verify_pda(
+                &address,
+                &escrow_seeds(&Pubkey::new_unique(), 42),
+                bump,
+                &id()
+            ),
+            Err(PdaError::AddressMismatch { .. }) | Err(PdaError::NoViableBump)
+        ));
+    }
+
+    #[test]
+    fn test_token_escrow_accounts() {
+        let maker = Pubkey::new_unique();
+        let taker = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let pda = escrow_address(&id(), &maker, 1).unwrap();
+        let escrow = Escrow {
+            is_initialized: true,
+            maker,
+            id: 1,
+            bump: pda.bump,
+            funded: true,
+            deposit: Asset::Token { mint, amount: 10 },
+            receive: Asset::Sol { lamports: 500 },
+        };
+        let program = TokenProgram::Token2022;
+
+        let instruction = exchange(&id(), &pda.address, &escrow, &taker, program, program);
+        // no token accounts for the SOL payment
+        assert_eq!(instruction.accounts.len(), 8);
+        assert_eq!(
+            instruction.accounts[4].pubkey,
+            vault_address(&pda.address, &mint, &spl_token_2022::id())
+        );
+        assert_eq!(
+            instruction.accounts[5].pubkey,
+            program.associated_token_address(&taker, &mint)
+        );
+        // for the vault's withheld transfer fees
+        assert_eq!(instruction.accounts[6], AccountMeta::new(mint, false));
+
+        let instruction = cancel(&id(), &pda.address, &escrow, program);
+        assert_eq!(instruction.accounts[0], AccountMeta::new(maker, true));
+        assert_eq!(
+            instruction.accounts[3].pubkey,
+            program.associated_token_address(&maker, &mint)
+        );
+    }
+}
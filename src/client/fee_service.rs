@@ -0,0 +1,186 @@
+//! Instruction builders and RPC helpers for the fee service program in `crate::writeprogram`
+
+use crate::sender::{SendError, TransactionSender};
+use crate::writeprogram::{
+    ServiceConfig, ServiceError, ServiceInstruction, find_config_address, find_treasury_address,
+};
+use anyhow::{Result, bail};
+use borsh::BorshDeserialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_program,
+};
+
+pub fn initialize(program_id: &Pubkey, admin: &Pubkey, fee: u64) -> Instruction {
+    let (config, _) = find_config_address(admin, program_id);
+    let (treasury, _) = find_treasury_address(&config, program_id);
+    Instruction::new_with_borsh(
+        *program_id,
+        &ServiceInstruction::Initialize { fee },
+        vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new(config, false),
+            AccountMeta::new(treasury, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn execute(program_id: &Pubkey, user: &Pubkey, config: &Pubkey) -> Instruction {
+    let (treasury, _) = find_treasury_address(config, program_id);
+    Instruction::new_with_borsh(
+        *program_id,
+        &ServiceInstruction::Execute,
+        vec![
+            AccountMeta::new(*user, true),
+            AccountMeta::new(*config, false),
+            AccountMeta::new(treasury, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn update_fee(program_id: &Pubkey, admin: &Pubkey, fee: u64) -> Instruction {
+    let (config, _) = find_config_address(admin, program_id);
+    Instruction::new_with_borsh(
+        *program_id,
+        &ServiceInstruction::UpdateFee { fee },
+        vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(config, false),
+        ],
+    )
+}
+
+pub fn withdraw_treasury(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    destination: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (config, _) = find_config_address(admin, program_id);
+    let (treasury, _) = find_treasury_address(&config, program_id);
+    Instruction::new_with_borsh(
+        *program_id,
+        &ServiceInstruction::WithdrawTreasury { amount },
+        vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new_readonly(config, false),
+            AccountMeta::new(treasury, false),
+            AccountMeta::new(*destination, false),
+        ],
+    )
+}
+
+/// The program error behind a failed send, if the program returned one of its own
+pub fn service_error(error: &SendError) -> Option<ServiceError> {
+    match error.instruction_error()? {
+        (_, InstructionError::Custom(code)) => ServiceError::from_code(*code),
+        _ => None,
+    }
+}
+
+pub async fn fetch_service_config(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    config: &Pubkey,
+) -> Result<ServiceConfig> {
+    let account = client.get_account(config).await?;
+    if account.owner != *program_id {
+        bail!("{} is not owned by {}", config, program_id);
+    }
+    Ok(ServiceConfig::try_from_slice(&account.data)?)
+}
+
+/// Create the admin's config and treasury, returns the config address
+pub async fn initialize_service(
+    sender: &TransactionSender,
+    program_id: &Pubkey,
+    admin: &Keypair,
+    fee: u64,
+) -> Result<Pubkey> {
+    let instruction = initialize(program_id, &admin.pubkey(), fee);
+    let outcome = sender
+        .send(&[instruction], &admin.pubkey(), &[admin])
+        .await?;
+    let (config, _) = find_config_address(&admin.pubkey(), program_id);
+    println!("Service Config: {}", config);
+    println!("Initialize Signature: {}", outcome.signature);
+    Ok(config)
+}
+
+pub async fn execute_service(
+    sender: &TransactionSender,
+    program_id: &Pubkey,
+    user: &Keypair,
+    config: &Pubkey,
+) -> Result<Signature> {
+    let instruction = execute(program_id, &user.pubkey(), config);
+    let outcome = sender.send(&[instruction], &user.pubkey(), &[user]).await?;
+    Ok(outcome.signature)
+}
+
+pub async fn update_service_fee(
+    sender: &TransactionSender,
+    program_id: &Pubkey,
+    admin: &Keypair,
+    fee: u64,
+) -> Result<Signature> {
+    let instruction = update_fee(program_id, &admin.pubkey(), fee);
+    let outcome = sender
+        .send(&[instruction], &admin.pubkey(), &[admin])
+        .await?;
+    Ok(outcome.signature)
+}
+
+pub async fn withdraw_service_treasury(
+    sender: &TransactionSender,
+    program_id: &Pubkey,
+    admin: &Keypair,
+    destination: &Pubkey,
+    amount: u64,
+) -> Result<Signature> {
+    let instruction = withdraw_treasury(program_id, &admin.pubkey(), destination, amount);
+    let outcome = sender
+        .send(&[instruction], &admin.pubkey(), &[admin])
+        .await?;
+    Ok(outcome.signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writeprogram::id;
+
+    #[test]
+    fn test_instruction_builders() {
+        let admin = Pubkey::new_unique();
+        let (config, _) = find_config_address(&admin, &id());
+        let (treasury, _) = find_treasury_address(&config, &id());
+
+        let init = initialize(&id(), &admin, 5);
+        assert_eq!(
+            init.accounts
+                .iter()
+                .map(|meta| meta.pubkey)
+                .collect::<Vec<_>>(),
+            [admin, config, treasury, system_program::id()]
+        );
+        assert_eq!(
+            ServiceInstruction::try_from_slice(&init.data).unwrap(),
+            ServiceInstruction::Initialize { fee: 5 }
+        );
+
+        let user = Pubkey::new_unique();
+        let execute = execute(&id(), &user, &config);
+        assert!(execute.accounts[0].is_signer && execute.accounts[0].is_writable);
+        assert_eq!(execute.accounts[2].pubkey, treasury);
+
+        let withdraw = withdraw_treasury(&id(), &admin, &user, 10);
+        assert!(!withdraw.accounts[1].is_writable);
+        assert_eq!(withdraw.accounts[3].pubkey, user);
+    }
+}
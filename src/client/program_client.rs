@@ -65,7 +65,7 @@ fn initialize_counter(program: &Program<Rc<Keypair>>, counter: &Keypair) -> anyh
     let signature = program
         .request()
         .instruction(initialize_ix)
-        .signer(&counter)
+        .signer(counter)
         .send()
         .unwrap();
 
@@ -83,44 +83,37 @@ fn initialize_counter(program: &Program<Rc<Keypair>>, counter: &Keypair) -> anyh
 mod test {
     use super::*;
     use crate::common;
+    use crate::config::ClusterConfig;
     use anchor_client::Client;
-    use anchor_client::Cluster;
-    use solana_sdk::commitment_config::CommitmentConfig;
 
     #[test]
     fn test_initialize_counter() {
-        let keypair_path = "/home/gidon/.config/solana/id.json".to_string();
-        let payer = common::get_key_pair_from_local_json(&keypair_path).unwrap();
+        let payer = common::get_local_key_pair().unwrap();
 
         let counter = Keypair::new();
         println!("Payer: {}", payer.pubkey());
         println!("Generated Counter: {}", counter.pubkey());
 
         // Create program client
-        let client = Client::new_with_options(
-            Cluster::Localnet,
-            Rc::new(payer),
-            CommitmentConfig::confirmed(),
-        );
+        let config = ClusterConfig::resolve().unwrap();
+        let client =
+            Client::new_with_options(config.anchor_cluster(), Rc::new(payer), config.commitment);
         let program: Program<Rc<Keypair>> = client.program(counter::ID).unwrap();
         initialize_counter(&program, &counter).unwrap();
     }
 
     #[test]
     fn test_increment_account() {
-        let keypair_path = "/home/gidon/.config/solana/id.json".to_string();
-        let payer = common::get_key_pair_from_local_json(&keypair_path).unwrap();
+        let payer = common::get_local_key_pair().unwrap();
 
         let counter = Keypair::new();
         println!("Payer: {}", payer.pubkey());
         println!("Generated Counter: {}", counter.pubkey());
 
         // Create program client
-        let client = Client::new_with_options(
-            Cluster::Localnet,
-            Rc::new(payer),
-            CommitmentConfig::confirmed(),
-        );
+        let config = ClusterConfig::resolve().unwrap();
+        let client =
+            Client::new_with_options(config.anchor_cluster(), Rc::new(payer), config.commitment);
         let program: Program<Rc<Keypair>> = client.program(counter::ID).unwrap();
         initialize_counter(&program, &counter).unwrap();
 
@@ -129,19 +122,16 @@ mod test {
 
     #[test]
     fn test_initialize_and_incremenet_account() {
-        let keypair_path = "/home/gidon/.config/solana/id.json".to_string();
-        let payer = common::get_key_pair_from_local_json(&keypair_path).unwrap();
+        let payer = common::get_local_key_pair().unwrap();
 
         let counter = Keypair::new();
         println!("Payer: {}", payer.pubkey());
         println!("Generated Counter: {}", counter.pubkey());
 
         // Create program client
-        let client = Client::new_with_options(
-            Cluster::Localnet,
-            Rc::new(payer),
-            CommitmentConfig::confirmed(),
-        );
+        let config = ClusterConfig::resolve().unwrap();
+        let client =
+            Client::new_with_options(config.anchor_cluster(), Rc::new(payer), config.commitment);
         let program: Program<Rc<Keypair>> = client.program(counter::ID).unwrap();
 
         initialize_and_incremenet_account(&program, &counter).unwrap();
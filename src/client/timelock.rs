@@ -0,0 +1,208 @@
+//! Instruction builders and RPC helpers for the time-lock program in `crate::timelock`
+
+use crate::sender::{SendError, TransactionSender};
+use crate::sysvar::fetch_clock;
+use crate::timelock::{Schedule, TimeLock, TimeLockError, TimeLockInstruction, find_vault_address};
+use anyhow::{Result, bail};
+use borsh::BorshDeserialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    clock::Clock,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_program,
+    sysvar::clock::ID as SYSVAR_CLOCK_ID,
+};
+
+/// How `Withdraw` reads the clock
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClockSource {
+    /// `Clock::get()`, one account less
+    #[default]
+    Syscall,
+    /// The clock sysvar passed as an account
+    Sysvar,
+}
+
+pub fn create(
+    program_id: &Pubkey,
+    funder: &Pubkey,
+    id: u64,
+    beneficiary: &Pubkey,
+    amount: u64,
+    schedule: Schedule,
+) -> Instruction {
+    let (vault, _) = find_vault_address(funder, id, program_id);
+    Instruction::new_with_borsh(
+        *program_id,
+        &TimeLockInstruction::Create {
+            id,
+            amount,
+            beneficiary: *beneficiary,
+            schedule,
+        },
+        vec![
+            AccountMeta::new(*funder, true),
+            AccountMeta::new(vault, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn withdraw(
+    program_id: &Pubkey,
+    vault: &Pubkey,
+    state: &TimeLock,
+    clock_source: ClockSource,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(state.beneficiary, true),
+        AccountMeta::new(*vault, false),
+        AccountMeta::new(state.funder, false),
+    ];
+    if clock_source == ClockSource::Sysvar {
+        accounts.push(AccountMeta::new_readonly(SYSVAR_CLOCK_ID, false));
+    }
+    Instruction::new_with_borsh(*program_id, &TimeLockInstruction::Withdraw, accounts)
+}
+
+/// The program error behind a failed send, if the program returned one of its own
+pub fn timelock_error(error: &SendError) -> Option<TimeLockError> {
+    match error.instruction_error()? {
+        (_, InstructionError::Custom(code)) => TimeLockError::from_code(*code),
+        _ => None,
+    }
+}
+
+pub async fn fetch_timelock(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    vault: &Pubkey,
+) -> Result<TimeLock> {
+    let account = client.get_account(vault).await?;
+    if account.owner != *program_id {
+        bail!("{} is not a vault of {}", vault, program_id);
+    }
+    Ok(TimeLock::deserialize(&mut account.data.as_slice())?)
+}
+
+/// What a vault releases at the current on-chain clock
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Claimable {
+    pub total: u64,
+    pub vested: u64,
+    pub withdrawn: u64,
+    /// What `Withdraw` would pay out now
+    pub claimable: u64,
+    pub clock: Clock,
+}
+
+pub fn claimable_at(state: &TimeLock, clock: &Clock) -> Claimable {
+    Claimable {
+        total: state.total,
+        vested: state.schedule.vested(state.total, clock),
+        withdrawn: state.withdrawn,
+        claimable: state.claimable(clock),
+        clock: clock.clone(),
+    }
+}
+
+pub async fn fetch_claimable(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    vault: &Pubkey,
+) -> Result<Claimable> {
+    let state = fetch_timelock(client, program_id, vault).await?;
+    Ok(claimable_at(&state, &fetch_clock(client).await?))
+}
+
+/// Lock `amount` lamports of the funder, returns the vault address
+pub async fn create_timelock(
+    sender: &TransactionSender,
+    program_id: &Pubkey,
+    funder: &Keypair,
+    id: u64,
+    beneficiary: &Pubkey,
+    amount: u64,
+    schedule: Schedule,
+) -> Result<Pubkey> {
+    let instruction = create(
+        program_id,
+        &funder.pubkey(),
+        id,
+        beneficiary,
+        amount,
+        schedule,
+    );
+    let outcome = sender
+        .send(&[instruction], &funder.pubkey(), &[funder])
+        .await?;
+    let (vault, _) = find_vault_address(&funder.pubkey(), id, program_id);
+    println!("Time Lock Vault: {}", vault);
+    println!("Create Signature: {}", outcome.signature);
+    Ok(vault)
+}
+
+/// Withdraw what is claimable, fails early instead of sending when nothing is
+pub async fn withdraw_timelock(
+    sender: &TransactionSender,
+    program_id: &Pubkey,
+    beneficiary: &Keypair,
+    vault: &Pubkey,
+    clock_source: ClockSource,
+) -> Result<Signature> {
+    let client = sender.client();
+    let state = fetch_timelock(client, program_id, vault).await?;
+    if claimable_at(&state, &fetch_clock(client).await?).claimable == 0 {
+        bail!("nothing in {} is unlocked yet", vault);
+    }
+    let instruction = withdraw(program_id, vault, &state, clock_source);
+    let outcome = sender
+        .send(&[instruction], &beneficiary.pubkey(), &[beneficiary])
+        .await?;
+    Ok(outcome.signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timelock::id;
+
+    #[test]
+    fn test_withdraw_clock_source() {
+        let funder = Pubkey::new_unique();
+        let (vault, bump) = find_vault_address(&funder, 3, &id());
+        let state = TimeLock {
+            is_initialized: true,
+            funder,
+            beneficiary: Pubkey::new_unique(),
+            id: 3,
+            bump,
+            total: 100,
+            withdrawn: 20,
+            schedule: Schedule::UnlockAt {
+                unix_timestamp: 500,
+            },
+        };
+
+        assert_eq!(
+            withdraw(&id(), &vault, &state, ClockSource::Syscall)
+                .accounts
+                .len(),
+            3
+        );
+        let instruction = withdraw(&id(), &vault, &state, ClockSource::Sysvar);
+        assert_eq!(
+            instruction.accounts[3],
+            AccountMeta::new_readonly(SYSVAR_CLOCK_ID, false)
+        );
+
+        let clock = Clock {
+            unix_timestamp: 500,
+            ..Clock::default()
+        };
+        let claimable = claimable_at(&state, &clock);
+        assert_eq!((claimable.vested, claimable.claimable), (100, 80));
+    }
+}
@@ -1,11 +1,11 @@
+use crate::config::ClusterConfig;
+use crate::keypair::{self, KeypairError, KeypairSource};
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::account::Account;
-use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Keypair;
 use solana_sdk::signer::Signer;
-use solana_sdk::{bs58, pubkey};
-use std::fs;
 use std::sync::Arc;
 
 pub fn create_keypair() -> Keypair {
@@ -15,25 +15,16 @@ pub fn create_keypair() -> Keypair {
     keypair
 }
 
-pub fn restore_keypair_from_secret_bytes() -> Keypair {
-    let keypair_bytes = [
-        188, 216, 33, 82, 41, 164, 217, 226, 89, 215, 164, 19, 77, 210, 71, 105, 225, 168, 110, 35,
-        186, 151, 23, 182, 172, 0, 108, 218, 212, 79, 94, 51, 36, 188, 202, 153, 62, 245, 160, 129,
-        54, 76, 158, 97, 207, 116, 118, 161, 141, 10, 82, 191, 10, 76, 231, 88, 199, 109, 226, 70,
-        36, 35, 44, 34,
-    ];
-    let keypair = Keypair::from_bytes(&keypair_bytes).unwrap();
+pub fn restore_keypair_from_secret_bytes(keypair_bytes: &[u8]) -> Result<Keypair, KeypairError> {
+    let keypair = keypair::keypair_from_bytes(keypair_bytes)?;
     println!("recovered address: {}", keypair.pubkey());
-    keypair
+    Ok(keypair)
 }
 
-pub fn restore_keypair_from_secret_base58() -> Keypair {
-    let keypair_base58 =
-        "4UzFMkVbk1q6ApxvDS8inUxg4cMBxCQRVXRx5msqQyktbi1QkJkt574Jda6BjZThSJi54CHfVoLFdVFX8XFn233L";
-    let keypair_bytes = bs58::decode(keypair_base58).into_vec().unwrap();
-    let keypair = Keypair::from_bytes(&keypair_bytes).unwrap();
+pub fn restore_keypair_from_secret_base58(keypair_base58: &str) -> Result<Keypair, KeypairError> {
+    let keypair = keypair::keypair_from_base58(keypair_base58)?;
     println!("recovered address: {}", keypair.pubkey());
-    keypair
+    Ok(keypair)
 }
 
 pub fn validate_public_key() {
@@ -48,10 +39,8 @@ pub fn validate_public_key() {
     println!("is off curve: {}", off_curve_public_key.is_on_curve());
 }
 
-pub fn get_rpc_client() -> RpcClient {
-    let host = "http://127.0.0.1:8899".to_string();
-    let client = RpcClient::new_with_commitment(host, CommitmentConfig::confirmed());
-    client
+pub fn get_rpc_client(config: &ClusterConfig) -> RpcClient {
+    config.rpc_client()
 }
 
 pub async fn airdrop(client: &RpcClient, keypair: &Keypair, lamport: u64) -> anyhow::Result<()> {
@@ -80,21 +69,13 @@ pub async fn get_account(client: &RpcClient, keypair: &Pubkey) -> anyhow::Result
     Ok(account_info)
 }
 
-pub fn get_key_pair_from_local_json(keypair_path: &str) -> anyhow::Result<Keypair> {
-    let keypair_file = fs::read_to_string(keypair_path)?;
-    let keypair_bytes: Vec<u8> = serde_json::from_str(&keypair_file)?;
-
-    let default_keypair = Keypair::from_bytes(&keypair_bytes)?;
-    Ok(default_keypair)
+pub fn get_key_pair_from_local_json(keypair_path: &str) -> Result<Keypair, KeypairError> {
+    KeypairSource::JsonFile(keypair_path.into()).load()
 }
 
-pub fn get_local_key_pair() -> anyhow::Result<Keypair> {
-    let path = "/home/gidon/.config/solana/id.json".to_string();
-    let keypair_file = fs::read_to_string(&path)?;
-    let keypair_bytes: Vec<u8> = serde_json::from_str(&keypair_file)?;
-
-    let default_keypair = Keypair::from_bytes(&keypair_bytes)?;
-    Ok(default_keypair)
+/// `SOLANA_KEYPAIR` if set, otherwise the Solana CLI default keypair
+pub fn get_local_key_pair() -> Result<Keypair, KeypairError> {
+    KeypairSource::from_env_or_default().load()
 }
 
 #[cfg(test)]
@@ -103,7 +84,7 @@ mod tests {
     use solana_sdk::sysvar;
     #[test]
     fn test_get_account() {
-        let client = get_rpc_client();
+        let client = get_rpc_client(&ClusterConfig::resolve().unwrap());
         let pub_key_id = sysvar::clock::ID;
         let account_info = tokio_test::block_on(get_account(&client, &pub_key_id));
         println!("{:#?}", account_info);
@@ -111,7 +92,16 @@ mod tests {
 
     #[test]
     fn test_get_key_pair_from_local_json() {
-        let keypair_path = "/home/gidon/.config/solana/id.json".to_string();
-        get_key_pair_from_local_json(&keypair_path).unwrap();
+        let keypair_path = keypair::default_keypair_path().unwrap();
+        get_key_pair_from_local_json(keypair_path.to_str().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_restore_keypair() {
+        let keypair = Keypair::new();
+        let restored = restore_keypair_from_secret_bytes(&keypair.to_bytes()).unwrap();
+        assert_eq!(restored.pubkey(), keypair.pubkey());
+        let restored = restore_keypair_from_secret_base58(&keypair.to_base58_string()).unwrap();
+        assert_eq!(restored.pubkey(), keypair.pubkey());
     }
 }
@@ -0,0 +1,381 @@
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Env var that selects a profile (localnet/devnet/testnet/mainnet or a custom name)
+pub const ENV_PROFILE: &str = "SOLANA_PROFILE";
+/// Env var pointing at this crate's own config file
+pub const ENV_CONFIG_FILE: &str = "RUST_SOLANA_CONFIG";
+pub const ENV_RPC_URL: &str = "SOLANA_RPC_URL";
+pub const ENV_WS_URL: &str = "SOLANA_WS_URL";
+pub const ENV_COMMITMENT: &str = "SOLANA_COMMITMENT";
+pub const ENV_TIMEOUT_SECS: &str = "SOLANA_RPC_TIMEOUT_SECS";
+
+/// Config file used when `RUST_SOLANA_CONFIG` is not set
+pub const DEFAULT_CONFIG_FILE: &str = "rust-solana.yml";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Named cluster profiles
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cluster {
+    Localnet,
+    Devnet,
+    Testnet,
+    Mainnet,
+    Custom(String),
+}
+
+impl Cluster {
+    /// Default JSON RPC url of the cluster, `None` for custom profiles
+    pub fn default_url(&self) -> Option<&'static str> {
+        match self {
+            Cluster::Localnet => Some("http://127.0.0.1:8899"),
+            Cluster::Devnet => Some("https://api.devnet.solana.com"),
+            Cluster::Testnet => Some("https://api.testnet.solana.com"),
+            Cluster::Mainnet => Some("https://api.mainnet-beta.solana.com"),
+            Cluster::Custom(_) => None,
+        }
+    }
+}
+
+impl FromStr for Cluster {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let name = s.trim();
+        if name.is_empty() {
+            return Err(anyhow!("empty cluster profile name"));
+        }
+        Ok(match name.to_ascii_lowercase().as_str() {
+            "localnet" | "localhost" | "l" => Cluster::Localnet,
+            "devnet" | "d" => Cluster::Devnet,
+            "testnet" | "t" => Cluster::Testnet,
+            "mainnet" | "mainnet-beta" | "m" => Cluster::Mainnet,
+            _ => Cluster::Custom(name.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for Cluster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cluster::Localnet => write!(f, "localnet"),
+            Cluster::Devnet => write!(f, "devnet"),
+            Cluster::Testnet => write!(f, "testnet"),
+            Cluster::Mainnet => write!(f, "mainnet"),
+            Cluster::Custom(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// One profile entry of the config file, every field is optional
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ProfileSettings {
+    pub json_rpc_url: Option<String>,
+    pub websocket_url: Option<String>,
+    pub commitment: Option<String>,
+    pub timeout_secs: Option<u64>,
+}
+
+/// Layout of the crate's config file (YAML, same format family as the Solana CLI config.yml)
+///
+/// ```yaml
+/// profile: devnet
+/// profiles:
+///   devnet:
+///     commitment: finalized
+///   my-rpc:
+///     json_rpc_url: https://rpc.example.com
+///     timeout_secs: 60
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ConfigFile {
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub profiles: HashMap<String, ProfileSettings>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &str) -> Result<Self> {
+        solana_cli_config::load_config_file(path)
+            .with_context(|| format!("failed to load config file {}", path))
+    }
+}
+
+/// Resolved cluster/RPC settings
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClusterConfig {
+    pub cluster: Cluster,
+    pub json_rpc_url: String,
+    pub websocket_url: String,
+    pub commitment: CommitmentConfig,
+    pub timeout: Duration,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self::for_cluster(Cluster::Localnet)
+    }
+}
+
+impl ClusterConfig {
+    /// Built-in settings of a named cluster, custom clusters fall back to localhost
+    pub fn for_cluster(cluster: Cluster) -> Self {
+        let json_rpc_url = cluster
+            .default_url()
+            .unwrap_or(Cluster::Localnet.default_url().unwrap())
+            .to_string();
+        Self {
+            websocket_url: solana_cli_config::Config::compute_websocket_url(&json_rpc_url),
+            json_rpc_url,
+            cluster,
+            commitment: CommitmentConfig::confirmed(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Resolve the settings from the process environment, the crate config file and
+    /// the Solana CLI config.yml
+    pub fn resolve() -> Result<Self> {
+        let config_file = Self::config_file_path(|key| std::env::var(key).ok())?;
+        let cli_config_file = solana_cli_config::CONFIG_FILE
+            .as_ref()
+            .filter(|path| Path::new(path).exists())
+            .cloned();
+
+        Self::resolve_with(
+            |key| std::env::var(key).ok(),
+            config_file.as_deref(),
+            cli_config_file.as_deref(),
+        )
+    }
+
+    /// Path of the crate config file: `RUST_SOLANA_CONFIG` must name an existing file,
+    /// the implicit `DEFAULT_CONFIG_FILE` is only used when it exists
+    fn config_file_path(env: impl Fn(&str) -> Option<String>) -> Result<Option<String>> {
+        match env(ENV_CONFIG_FILE) {
+            Some(path) if !Path::new(&path).exists() => Err(anyhow!(
+                "config file {} set by {} does not exist",
+                path,
+                ENV_CONFIG_FILE
+            )),
+            Some(path) => Ok(Some(path)),
+            None => {
+                Ok(Some(DEFAULT_CONFIG_FILE.to_string()).filter(|path| Path::new(path).exists()))
+            }
+        }
+    }
+
+    /// Resolution order, later steps override earlier ones:
+    /// 1. the selected profile (`SOLANA_PROFILE`, then `profile:` in the config file) with its
+    ///    built-in defaults and its config file section, or the Solana CLI config.yml when
+    ///    no profile is selected
+    /// 2. `SOLANA_RPC_URL`, `SOLANA_WS_URL`, `SOLANA_COMMITMENT`, `SOLANA_RPC_TIMEOUT_SECS`
+    pub fn resolve_with(
+        env: impl Fn(&str) -> Option<String>,
+        config_file: Option<&str>,
+        cli_config_file: Option<&str>,
+    ) -> Result<Self> {
+        let file = match config_file {
+            Some(path) => ConfigFile::load(path)?,
+            None => ConfigFile::default(),
+        };
+
+        let profile = env(ENV_PROFILE).or_else(|| file.profile.clone());
+        let mut config = match profile {
+            Some(name) => {
+                let cluster = Cluster::from_str(&name)?;
+                let settings = file.profiles.get(name.trim());
+                if let Cluster::Custom(_) = cluster
+                    && settings.and_then(|s| s.json_rpc_url.as_ref()).is_none()
+                    && env(ENV_RPC_URL).is_none()
+                {
+                    return Err(anyhow!("custom profile `{}` needs a json_rpc_url", name));
+                }
+                let mut config = Self::for_cluster(cluster);
+                if let Some(settings) = settings {
+                    config.apply(settings)?;
+                }
+                config
+            }
+            None => match cli_config_file {
+                Some(path) => Self::from_cli_config(path)?,
+                None => Self::default(),
+            },
+        };
+
+        config.apply(&ProfileSettings {
+            json_rpc_url: env(ENV_RPC_URL),
+            websocket_url: env(ENV_WS_URL),
+            commitment: env(ENV_COMMITMENT),
+            timeout_secs: env(ENV_TIMEOUT_SECS)
+                .map(|secs| secs.trim().parse())
+                .transpose()
+                .with_context(|| format!("invalid {}", ENV_TIMEOUT_SECS))?,
+        })?;
+        Ok(config)
+    }
+
+    /// Settings of the Solana CLI config.yml (`solana config set ...`)
+    pub fn from_cli_config(path: &str) -> Result<Self> {
+        let cli_config = solana_cli_config::Config::load(path)
+            .with_context(|| format!("failed to load Solana CLI config {}", path))?;
+        let mut config = Self::for_cluster(Cluster::Custom("solana-cli".to_string()));
+        config.apply(&ProfileSettings {
+            json_rpc_url: Some(cli_config.json_rpc_url),
+            websocket_url: Some(cli_config.websocket_url).filter(|url| !url.is_empty()),
+            commitment: Some(cli_config.commitment),
+            timeout_secs: None,
+        })?;
+        Ok(config)
+    }
+
+    fn apply(&mut self, settings: &ProfileSettings) -> Result<()> {
+        if let Some(url) = &settings.json_rpc_url {
+            self.json_rpc_url = url.clone();
+            // keep the websocket url in sync unless it is set explicitly
+            self.websocket_url = solana_cli_config::Config::compute_websocket_url(url);
+        }
+        if let Some(url) = &settings.websocket_url {
+            self.websocket_url = url.clone();
+        }
+        if let Some(commitment) = &settings.commitment {
+            self.commitment = CommitmentConfig::from_str(commitment.trim())
+                .map_err(|_| anyhow!("invalid commitment `{}`", commitment))?;
+        }
+        if let Some(secs) = settings.timeout_secs {
+            self.timeout = Duration::from_secs(secs);
+        }
+        Ok(())
+    }
+
+    pub fn rpc_client(&self) -> RpcClient {
+        RpcClient::new_with_timeout_and_commitment(
+            self.json_rpc_url.clone(),
+            self.timeout,
+            self.commitment,
+        )
+    }
+
+    /// The same settings for anchor-client
+    pub fn anchor_cluster(&self) -> anchor_client::Cluster {
+        anchor_client::Cluster::Custom(self.json_rpc_url.clone(), self.websocket_url.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::commitment_config::CommitmentLevel;
+
+    fn write_temp(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{}", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn env_of(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn test_default_is_localnet() {
+        let config = ClusterConfig::resolve_with(env_of(&[]), None, None).unwrap();
+        assert_eq!(config.cluster, Cluster::Localnet);
+        assert_eq!(config.json_rpc_url, "http://127.0.0.1:8899");
+        assert_eq!(config.websocket_url, "ws://127.0.0.1:8900/");
+        assert_eq!(config.commitment, CommitmentConfig::confirmed());
+    }
+
+    #[test]
+    fn test_profile_from_env() {
+        let config =
+            ClusterConfig::resolve_with(env_of(&[(ENV_PROFILE, "devnet")]), None, None).unwrap();
+        assert_eq!(config.cluster, Cluster::Devnet);
+        assert_eq!(config.json_rpc_url, "https://api.devnet.solana.com");
+        assert_eq!(config.websocket_url, "wss://api.devnet.solana.com/");
+    }
+
+    #[test]
+    fn test_config_file_profile_and_env_override() {
+        let path = write_temp(
+            "rust-solana.yml",
+            "profile: my-rpc\nprofiles:\n  my-rpc:\n    json_rpc_url: https://rpc.example.com\n    commitment: finalized\n    timeout_secs: 60\n",
+        );
+        let config = ClusterConfig::resolve_with(env_of(&[]), Some(&path), None).unwrap();
+        assert_eq!(config.cluster, Cluster::Custom("my-rpc".to_string()));
+        assert_eq!(config.json_rpc_url, "https://rpc.example.com");
+        assert_eq!(config.commitment.commitment, CommitmentLevel::Finalized);
+        assert_eq!(config.timeout, Duration::from_secs(60));
+
+        let config = ClusterConfig::resolve_with(
+            env_of(&[
+                (ENV_WS_URL, "wss://ws.example.com"),
+                (ENV_TIMEOUT_SECS, "5"),
+            ]),
+            Some(&path),
+            None,
+        )
+        .unwrap();
+        assert_eq!(config.json_rpc_url, "https://rpc.example.com");
+        assert_eq!(config.websocket_url, "wss://ws.example.com");
+        assert_eq!(config.timeout, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_custom_profile_without_url() {
+        let result = ClusterConfig::resolve_with(env_of(&[(ENV_PROFILE, "unknown")]), None, None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_cli_config_used_without_profile() {
+        let path = write_temp(
+            "cli-config.yml",
+            "json_rpc_url: https://api.testnet.solana.com\nwebsocket_url: ''\nkeypair_path: /tmp/id.json\naddress_labels: {}\ncommitment: processed\n",
+        );
+        let config = ClusterConfig::resolve_with(env_of(&[]), None, Some(&path)).unwrap();
+        assert_eq!(config.json_rpc_url, "https://api.testnet.solana.com");
+        assert_eq!(config.websocket_url, "wss://api.testnet.solana.com/");
+        assert_eq!(config.commitment.commitment, CommitmentLevel::Processed);
+
+        // an explicit profile wins over the CLI config
+        let config =
+            ClusterConfig::resolve_with(env_of(&[(ENV_PROFILE, "localnet")]), None, Some(&path))
+                .unwrap();
+        assert_eq!(config.json_rpc_url, "http://127.0.0.1:8899");
+    }
+
+    #[test]
+    fn test_config_file_path() {
+        let path = write_temp("env-config.yml", "profile: devnet\n");
+        assert_eq!(
+            ClusterConfig::config_file_path(env_of(&[(ENV_CONFIG_FILE, &path)])).unwrap(),
+            Some(path.clone())
+        );
+        // a file named by the env var must exist
+        let result = ClusterConfig::config_file_path(env_of(&[(
+            ENV_CONFIG_FILE,
+            "/nonexistent/config.yml",
+        )]));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_invalid_commitment() {
+        let result =
+            ClusterConfig::resolve_with(env_of(&[(ENV_COMMITMENT, "whenever")]), None, None);
+        assert!(result.is_err());
+    }
+}
@@ -1,11 +1,10 @@
 use crate::common::airdrop;
+use crate::sender::TransactionSender;
 use anyhow::Result;
-use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::{
     program_pack::Pack,
     signature::{Keypair, Signer},
     system_instruction::create_account,
-    transaction::Transaction,
 };
 use spl_token_2022::{id as token_2022_program_id, instruction::initialize_mint, state::Mint};
 
@@ -15,8 +14,8 @@ use spl_token_2022::{id as token_2022_program_id, instruction::initialize_mint,
 * Invoke the custom program, which now owns the account, to initialize the account data as defined
 * by the program's instruction
 */
-pub async fn create_data_account(client: &RpcClient, mint: &Keypair) -> Result<()> {
-    let recent_blockhash = client.get_latest_blockhash().await?;
+pub async fn create_data_account(sender: &TransactionSender, mint: &Keypair) -> Result<()> {
+    let client = sender.client();
 
     // Generate a new keypair for the fee payer
     let fee_payer = Keypair::new();
@@ -47,16 +46,15 @@ pub async fn create_data_account(client: &RpcClient, mint: &Keypair) -> Result<(
         9,                         // decimals
     )?;
 
-    // Create transaction and add instructions
-    let transaction = Transaction::new_signed_with_payer(
-        &[create_account_instruction, initialize_mint_instruction],
-        Some(&fee_payer.pubkey()),
-        &[&fee_payer, &mint],
-        recent_blockhash,
-    );
-
     // Send and confirm transaction
-    let transaction_signature = client.send_and_confirm_transaction(&transaction).await?;
+    let transaction_signature = sender
+        .send(
+            &[create_account_instruction, initialize_mint_instruction],
+            &fee_payer.pubkey(),
+            &[&fee_payer, mint],
+        )
+        .await?
+        .signature;
 
     println!("Mint Address: {}", mint.pubkey());
     println!("Transaction Signature: {}", transaction_signature);
@@ -71,18 +69,22 @@ pub async fn create_data_account(client: &RpcClient, mint: &Keypair) -> Result<(
 mod tests {
     use super::*;
     use crate::common;
+    use crate::config::ClusterConfig;
+    use std::sync::Arc;
     #[tokio::test]
     async fn test_create_account_one() -> Result<()> {
-        let client = common::get_rpc_client();
+        let client = common::get_rpc_client(&ClusterConfig::resolve()?);
+        let sender = TransactionSender::new(Arc::new(client));
         let account = Keypair::new();
-        create_data_account(&client, &account).await?;
+        create_data_account(&sender, &account).await?;
         Ok(())
     }
 
     #[tokio::test]
     async fn test_create_account_two() {
-        let client = crate::common::get_rpc_client();
+        let client = common::get_rpc_client(&ClusterConfig::resolve().unwrap());
+        let sender = TransactionSender::new(Arc::new(client));
         let account = Keypair::new();
-        create_data_account(&client, &account).await.unwrap();
+        create_data_account(&sender, &account).await.unwrap();
     }
 }
@@ -0,0 +1,259 @@
+use bip39::{Language, Mnemonic};
+use solana_keypair::{
+    keypair_from_seed_phrase_and_passphrase, seed_derivable::keypair_from_seed_and_derivation_path,
+};
+use solana_sdk::bs58;
+use solana_sdk::derivation_path::DerivationPath;
+use solana_sdk::signature::Keypair;
+use solana_seed_phrase::generate_seed_from_seed_phrase_and_passphrase;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Env var read by `KeypairSource::from_env_or_default`
+pub const ENV_KEYPAIR: &str = "SOLANA_KEYPAIR";
+
+/// Keypair path used by the Solana CLI when its config.yml doesn't say otherwise
+pub const DEFAULT_KEYPAIR_PATH: &str = "~/.config/solana/id.json";
+
+#[derive(Debug, Error)]
+pub enum KeypairError {
+    #[error("failed to read keypair file {path}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("keypair file {path} is not a JSON byte array: {source}")]
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("invalid base58 keypair: {0}")]
+    Base58(String),
+    #[error("invalid keypair bytes: {0}")]
+    InvalidBytes(String),
+    #[error("invalid seed phrase: {0}")]
+    SeedPhrase(String),
+    #[error("invalid derivation path `{path}`: {reason}")]
+    DerivationPath { path: String, reason: String },
+    #[error("environment variable {0} is not set")]
+    EnvNotSet(String),
+    #[error("cannot expand `~`, HOME is not set")]
+    NoHomeDir,
+}
+
+/// Where a keypair is loaded from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeypairSource {
+    /// JSON byte-array file as written by `solana-keygen`, `~` is expanded
+    JsonFile(PathBuf),
+    /// Base58 encoded 64-byte secret key (Phantom export format)
+    Base58(String),
+    /// BIP39 seed phrase, e.g. derivation path `m/44'/501'/0'/0'`
+    SeedPhrase {
+        phrase: String,
+        passphrase: String,
+        derivation_path: Option<String>,
+    },
+    /// `keypair_path` of the Solana CLI config.yml, or `~/.config/solana/id.json`
+    DefaultCli,
+    /// Env var holding a file path, a base58 string or a JSON byte array
+    Env(String),
+}
+
+impl KeypairSource {
+    /// `SOLANA_KEYPAIR` when it is set, the Solana CLI keypair otherwise
+    pub fn from_env_or_default() -> Self {
+        if std::env::var_os(ENV_KEYPAIR).is_some() {
+            KeypairSource::Env(ENV_KEYPAIR.to_string())
+        } else {
+            KeypairSource::DefaultCli
+        }
+    }
+
+    pub fn load(&self) -> Result<Keypair, KeypairError> {
+        match self {
+            KeypairSource::JsonFile(path) => read_json_file(&expand_tilde(path)?),
+            KeypairSource::Base58(value) => keypair_from_base58(value),
+            KeypairSource::SeedPhrase {
+                phrase,
+                passphrase,
+                derivation_path,
+            } => keypair_from_seed_phrase(phrase, passphrase, derivation_path.as_deref()),
+            KeypairSource::DefaultCli => read_json_file(&default_keypair_path()?),
+            KeypairSource::Env(var) => {
+                let value = std::env::var(var).map_err(|_| KeypairError::EnvNotSet(var.clone()))?;
+                keypair_from_str(&value)
+            }
+        }
+    }
+}
+
+/// Replace a leading `~` with the home directory
+pub fn expand_tilde(path: &Path) -> Result<PathBuf, KeypairError> {
+    match path.strip_prefix("~") {
+        Ok(rest) => {
+            let home = std::env::var_os("HOME").ok_or(KeypairError::NoHomeDir)?;
+            Ok(PathBuf::from(home).join(rest))
+        }
+        Err(_) => Ok(path.to_path_buf()),
+    }
+}
+
+/// Keypair path configured for the Solana CLI
+pub fn default_keypair_path() -> Result<PathBuf, KeypairError> {
+    let configured = solana_cli_config::CONFIG_FILE
+        .as_ref()
+        .and_then(|file| solana_cli_config::Config::load(file).ok())
+        .map(|config| config.keypair_path)
+        .filter(|path| !path.is_empty());
+    expand_tilde(Path::new(
+        configured.as_deref().unwrap_or(DEFAULT_KEYPAIR_PATH),
+    ))
+}
+
+pub fn keypair_from_bytes(bytes: &[u8]) -> Result<Keypair, KeypairError> {
+    Keypair::from_bytes(bytes).map_err(|err| KeypairError::InvalidBytes(err.to_string()))
+}
+
+pub fn keypair_from_base58(value: &str) -> Result<Keypair, KeypairError> {
+    let bytes = bs58::decode(value.trim())
+        .into_vec()
+        .map_err(|err| KeypairError::Base58(err.to_string()))?;
+    keypair_from_bytes(&bytes)
+}
+
+/// Without a derivation path the seed is used directly, like `solana-keygen recover prompt://`
+pub fn keypair_from_seed_phrase(
+    phrase: &str,
+    passphrase: &str,
+    derivation_path: Option<&str>,
+) -> Result<Keypair, KeypairError> {
+    let phrase = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+    Mnemonic::validate(&phrase, Language::English)
+        .map_err(|err| KeypairError::SeedPhrase(err.to_string()))?;
+
+    match derivation_path {
+        Some(path) => {
+            let derivation_path = DerivationPath::from_absolute_path_str(path).map_err(|err| {
+                KeypairError::DerivationPath {
+                    path: path.to_string(),
+                    reason: err.to_string(),
+                }
+            })?;
+            let seed = generate_seed_from_seed_phrase_and_passphrase(&phrase, passphrase);
+            keypair_from_seed_and_derivation_path(&seed, Some(derivation_path))
+                .map_err(|err| KeypairError::SeedPhrase(err.to_string()))
+        }
+        None => keypair_from_seed_phrase_and_passphrase(&phrase, passphrase)
+            .map_err(|err| KeypairError::SeedPhrase(err.to_string())),
+    }
+}
+
+/// A JSON byte array, an existing file path or a base58 string
+fn keypair_from_str(value: &str) -> Result<Keypair, KeypairError> {
+    let value = value.trim();
+    if value.starts_with('[') {
+        let bytes: Vec<u8> = serde_json::from_str(value)
+            .map_err(|err| KeypairError::InvalidBytes(err.to_string()))?;
+        return keypair_from_bytes(&bytes);
+    }
+    let path = expand_tilde(Path::new(value))?;
+    if path.is_file() {
+        return read_json_file(&path);
+    }
+    keypair_from_base58(value)
+}
+
+fn read_json_file(path: &Path) -> Result<Keypair, KeypairError> {
+    let content = std::fs::read_to_string(path).map_err(|source| KeypairError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let bytes: Vec<u8> = serde_json::from_str(&content).map_err(|source| KeypairError::Json {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    keypair_from_bytes(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signer::Signer;
+
+    const PHRASE: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn test_json_file_and_base58() {
+        let keypair = Keypair::new();
+        let path = std::env::temp_dir().join(format!("{}-id.json", std::process::id()));
+        std::fs::write(
+            &path,
+            serde_json::to_string(&keypair.to_bytes().to_vec()).unwrap(),
+        )
+        .unwrap();
+
+        let loaded = KeypairSource::JsonFile(path.clone()).load().unwrap();
+        assert_eq!(loaded.pubkey(), keypair.pubkey());
+
+        let loaded = keypair_from_str(path.to_str().unwrap()).unwrap();
+        assert_eq!(loaded.pubkey(), keypair.pubkey());
+
+        let loaded = KeypairSource::Base58(keypair.to_base58_string())
+            .load()
+            .unwrap();
+        assert_eq!(loaded.pubkey(), keypair.pubkey());
+    }
+
+    #[test]
+    fn test_invalid_inputs() {
+        let missing = KeypairSource::JsonFile("/nonexistent/id.json".into()).load();
+        assert!(matches!(missing, Err(KeypairError::Io { .. })));
+
+        let short = keypair_from_str("[1, 2, 3]");
+        assert!(matches!(short, Err(KeypairError::InvalidBytes(_))));
+
+        let not_base58 = KeypairSource::Base58("0OIl".to_string()).load();
+        assert!(matches!(not_base58, Err(KeypairError::Base58(_))));
+
+        let unset = KeypairSource::Env("RUST_SOLANA_TEST_UNSET_KEYPAIR".to_string()).load();
+        assert!(matches!(unset, Err(KeypairError::EnvNotSet(_))));
+    }
+
+    #[test]
+    fn test_seed_phrase() {
+        let derived = keypair_from_seed_phrase(PHRASE, "", Some("m/44'/501'/0'/0'")).unwrap();
+        let again = KeypairSource::SeedPhrase {
+            phrase: format!("  {}\n", PHRASE),
+            passphrase: String::new(),
+            derivation_path: Some("m/44'/501'/0'/0'".to_string()),
+        }
+        .load()
+        .unwrap();
+        assert_eq!(derived.pubkey(), again.pubkey());
+        assert_eq!(
+            derived.pubkey().to_string(),
+            "HAgk14JpMQLgt6rVgv7cBQFJWFto5Dqxi472uT3DKpqk"
+        );
+
+        let other_account = keypair_from_seed_phrase(PHRASE, "", Some("m/44'/501'/1'/0'")).unwrap();
+        let no_path = keypair_from_seed_phrase(PHRASE, "", None).unwrap();
+        assert_ne!(derived.pubkey(), other_account.pubkey());
+        assert_ne!(derived.pubkey(), no_path.pubkey());
+
+        let bad_checksum = keypair_from_seed_phrase(&PHRASE.replace("about", "abandon"), "", None);
+        assert!(matches!(bad_checksum, Err(KeypairError::SeedPhrase(_))));
+
+        let bad_path = keypair_from_seed_phrase(PHRASE, "", Some("44/501"));
+        assert!(matches!(bad_path, Err(KeypairError::DerivationPath { .. })));
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        let home = std::env::var("HOME").unwrap();
+        let expanded = expand_tilde(Path::new("~/.config/solana/id.json")).unwrap();
+        assert_eq!(expanded, Path::new(&home).join(".config/solana/id.json"));
+        assert_eq!(
+            expand_tilde(Path::new("/tmp/id.json")).unwrap(),
+            Path::new("/tmp/id.json")
+        );
+    }
+}
@@ -1,27 +1,60 @@
 #[allow(dead_code)]
 pub mod common;
 
+pub mod budget;
+
+pub mod config;
+
+pub mod keypair;
+
+pub mod lookup_table;
+
+pub mod memo;
+
+pub mod nonce;
+
+pub mod offline;
+
+pub mod payout;
+
+pub mod pda;
+
+pub mod sender;
+
+pub mod simulate;
+
+pub mod sysvar;
+
 #[allow(dead_code)]
 pub mod transaction;
 
 #[allow(dead_code)]
 pub mod accounts;
 
-#[allow(dead_code)]
-pub mod writeprogram;
+pub use fee_service_program as writeprogram;
+
+pub use escrow_program as escrow;
 
 #[allow(dead_code)]
 pub mod getclock;
 
+pub use timelock_program as timelock;
+
 #[allow(dead_code)]
 pub mod create_data_account;
 
 #[allow(dead_code)]
 pub mod token {
+    pub mod amount;
     pub mod confidential_transfer;
     pub mod create_mint_account;
     pub mod create_token_account;
+    pub mod metadata;
+    pub mod mint_builder;
+    pub mod mint_cache;
     pub mod mint_token;
+    pub mod program;
+    pub mod service;
     pub mod token_transfer;
 
     pub mod token_op;
@@ -34,7 +67,11 @@ pub mod bin_code_demo;
 
 #[allow(dead_code)]
 pub mod client {
+    pub mod escrow;
+    pub mod fee_service;
     pub mod program_client;
+    pub mod timelock;
 }
 
+#[allow(dead_code)]
 pub mod serialize;
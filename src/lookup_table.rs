@@ -0,0 +1,341 @@
+use crate::sender::TransactionSender;
+use anyhow::{Result, anyhow};
+use solana_address_lookup_table_interface::{instruction, state::AddressLookupTable};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    instruction::Instruction,
+    message::{AddressLookupTableAccount, CompileError, Message, VersionedMessage, v0},
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    signer::{SignerError, signers::Signers},
+    transaction::VersionedTransaction,
+};
+
+/// Addresses per extend instruction, more don't fit in one transaction
+pub const ADDRESSES_PER_EXTEND: usize = 20;
+
+/// An address lookup table with its metadata
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LookupTable {
+    pub address: Pubkey,
+    /// `None` once the table was frozen
+    pub authority: Option<Pubkey>,
+    /// `u64::MAX` while the table is active
+    pub deactivation_slot: u64,
+    pub last_extended_slot: u64,
+    pub addresses: Vec<Pubkey>,
+}
+
+impl LookupTable {
+    pub fn is_active(&self) -> bool {
+        self.deactivation_slot == u64::MAX
+    }
+
+    /// What `v0::Message::try_compile` takes
+    pub fn account(&self) -> AddressLookupTableAccount {
+        AddressLookupTableAccount {
+            key: self.address,
+            addresses: self.addresses.clone(),
+        }
+    }
+}
+
+pub async fn fetch_lookup_table(client: &RpcClient, address: &Pubkey) -> Result<LookupTable> {
+    let data = client.get_account_data(address).await?;
+    let table = AddressLookupTable::deserialize(&data)
+        .map_err(|err| anyhow!("{} is not a lookup table: {}", address, err))?;
+    Ok(LookupTable {
+        address: *address,
+        authority: table.meta.authority,
+        deactivation_slot: table.meta.deactivation_slot,
+        last_extended_slot: table.meta.last_extended_slot,
+        addresses: table.addresses.to_vec(),
+    })
+}
+
+/// Fetch tables to compile messages against
+pub async fn fetch_lookup_table_accounts(
+    client: &RpcClient,
+    addresses: &[Pubkey],
+) -> Result<Vec<AddressLookupTableAccount>> {
+    let mut accounts = Vec::with_capacity(addresses.len());
+    for address in addresses {
+        accounts.push(fetch_lookup_table(client, address).await?.account());
+    }
+    Ok(accounts)
+}
+
+/// Create an empty table owned by `authority`, returns its address
+pub async fn create_lookup_table(
+    sender: &TransactionSender,
+    authority: &Keypair,
+    payer: &Keypair,
+) -> Result<Pubkey> {
+    // the table address derives from a slot that must still be in SlotHashes
+    let recent_slot = sender
+        .client()
+        .get_slot_with_commitment(CommitmentConfig::finalized())
+        .await?;
+    let (create_ix, table_address) =
+        instruction::create_lookup_table(authority.pubkey(), payer.pubkey(), recent_slot);
+    let outcome = sender
+        .send(&[create_ix], &payer.pubkey(), &[payer, authority])
+        .await?;
+    println!("Lookup Table: {}", table_address);
+    println!("Lookup Table Signature: {}", outcome.signature);
+    Ok(table_address)
+}
+
+/// Append `addresses`, `ADDRESSES_PER_EXTEND` per transaction
+///
+/// New addresses can be looked up from the next slot on.
+pub async fn extend_lookup_table(
+    sender: &TransactionSender,
+    table_address: &Pubkey,
+    authority: &Keypair,
+    payer: &Keypair,
+    addresses: &[Pubkey],
+) -> Result<Vec<Signature>> {
+    let mut signatures = vec![];
+    for chunk in addresses.chunks(ADDRESSES_PER_EXTEND) {
+        let extend_ix = instruction::extend_lookup_table(
+            *table_address,       // lookup table
+            authority.pubkey(),   // authority
+            Some(payer.pubkey()), // payer of the extra rent
+            chunk.to_vec(),       // new addresses
+        );
+        let outcome = sender
+            .send(&[extend_ix], &payer.pubkey(), &[payer, authority])
+            .await?;
+        signatures.push(outcome.signature);
+    }
+    Ok(signatures)
+}
+
+/// Start the cool-down after which the table can be closed
+pub async fn deactivate_lookup_table(
+    sender: &TransactionSender,
+    table_address: &Pubkey,
+    authority: &Keypair,
+) -> Result<Signature> {
+    let deactivate_ix = instruction::deactivate_lookup_table(*table_address, authority.pubkey());
+    let outcome = sender
+        .send(&[deactivate_ix], &authority.pubkey(), &[authority])
+        .await?;
+    Ok(outcome.signature)
+}
+
+/// Close a deactivated table once its deactivation slot left SlotHashes, about 513 slots
+pub async fn close_lookup_table(
+    sender: &TransactionSender,
+    table_address: &Pubkey,
+    authority: &Keypair,
+    recipient: &Pubkey,
+) -> Result<Signature> {
+    let close_ix = instruction::close_lookup_table(*table_address, authority.pubkey(), *recipient);
+    let outcome = sender
+        .send(&[close_ix], &authority.pubkey(), &[authority])
+        .await?;
+    Ok(outcome.signature)
+}
+
+/// A legacy message without tables, otherwise a v0 message that looks up every non-signer
+/// account found in `lookup_tables`
+pub fn compile_message(
+    payer: &Pubkey,
+    instructions: &[Instruction],
+    lookup_tables: &[AddressLookupTableAccount],
+    blockhash: Hash,
+) -> Result<VersionedMessage, CompileError> {
+    if lookup_tables.is_empty() {
+        let message = Message::new_with_blockhash(instructions, Some(payer), &blockhash);
+        return Ok(VersionedMessage::Legacy(message));
+    }
+    let message = v0::Message::try_compile(payer, instructions, lookup_tables, blockhash)?;
+    Ok(VersionedMessage::V0(message))
+}
+
+/// `compile_message` with default signatures, enough for simulation
+pub fn unsigned_transaction(
+    payer: &Pubkey,
+    instructions: &[Instruction],
+    lookup_tables: &[AddressLookupTableAccount],
+) -> Result<VersionedTransaction, CompileError> {
+    let message = compile_message(payer, instructions, lookup_tables, Hash::default())?;
+    Ok(VersionedTransaction {
+        signatures: vec![Signature::default(); message.header().num_required_signatures as usize],
+        message,
+    })
+}
+
+/// Compile and sign a transaction, v0 if any lookup tables are given
+pub fn build_versioned_transaction<S: Signers + ?Sized>(
+    payer: &Pubkey,
+    instructions: &[Instruction],
+    lookup_tables: &[AddressLookupTableAccount],
+    signers: &S,
+    blockhash: Hash,
+) -> Result<VersionedTransaction> {
+    let message = compile_message(payer, instructions, lookup_tables, blockhash)?;
+    Ok(sign_message(message, signers)?)
+}
+
+/// Sign `message` with the signers it requires
+///
+/// Unlike `VersionedTransaction::try_new`, signers the message doesn't require and
+/// duplicates, e.g. an authority that is also the payer, are left out.
+pub fn sign_message<S: Signers + ?Sized>(
+    message: VersionedMessage,
+    signers: &S,
+) -> Result<VersionedTransaction, SignerError> {
+    let num_required_signatures = message.header().num_required_signatures as usize;
+    let required = &message.static_account_keys()[..num_required_signatures];
+    let pubkeys = signers.pubkeys();
+    let signatures = signers.try_sign_message(&message.serialize())?;
+    let signatures = required
+        .iter()
+        .map(|key| {
+            pubkeys
+                .iter()
+                .position(|pubkey| pubkey == key)
+                .map(|index| signatures[index])
+                .ok_or(SignerError::NotEnoughSigners)
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(VersionedTransaction {
+        signatures,
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common;
+    use crate::config::ClusterConfig;
+    use solana_sdk::{native_token::LAMPORTS_PER_SOL, system_instruction};
+    use std::sync::Arc;
+
+    fn transfers(payer: &Pubkey, recipients: &[Pubkey]) -> Vec<Instruction> {
+        recipients
+            .iter()
+            .map(|to| system_instruction::transfer(payer, to, LAMPORTS_PER_SOL / 100))
+            .collect()
+    }
+
+    #[test]
+    fn test_compile_message() {
+        let payer = Keypair::new();
+        let recipients: Vec<Pubkey> = (0..40).map(|_| Pubkey::new_unique()).collect();
+        let instructions = transfers(&payer.pubkey(), &recipients);
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: recipients.clone(),
+        };
+
+        let legacy = compile_message(&payer.pubkey(), &instructions, &[], Hash::default()).unwrap();
+        assert!(matches!(legacy, VersionedMessage::Legacy(_)));
+        assert_eq!(legacy.static_account_keys().len(), 42);
+
+        let message =
+            compile_message(&payer.pubkey(), &instructions, &[table], Hash::default()).unwrap();
+        // payer and system program stay static, the recipients are looked up
+        assert_eq!(message.static_account_keys().len(), 2);
+        let lookups = message.address_table_lookups().unwrap();
+        assert_eq!(lookups[0].writable_indexes.len(), 40);
+
+        let transaction = build_versioned_transaction(
+            &payer.pubkey(),
+            &instructions,
+            &[],
+            &[&payer],
+            Hash::new_unique(),
+        )
+        .unwrap();
+        assert!(transaction.verify_with_results().into_iter().all(|ok| ok));
+        let unsigned = unsigned_transaction(&payer.pubkey(), &instructions, &[]).unwrap();
+        assert_eq!(unsigned.signatures, [Signature::default()]);
+    }
+
+    #[test]
+    fn test_sign_lookup_table_instructions() {
+        let payer = Keypair::new();
+        let authority = Keypair::new();
+
+        // creating only needs the payer to sign, the authority is one signer too many
+        let (create_ix, table_address) =
+            instruction::create_lookup_table(authority.pubkey(), payer.pubkey(), 1);
+        let message =
+            compile_message(&payer.pubkey(), &[create_ix], &[], Hash::new_unique()).unwrap();
+        let transaction = sign_message(message, &[&payer, &authority]).unwrap();
+        assert_eq!(transaction.signatures.len(), 1);
+        assert!(transaction.verify_with_results().into_iter().all(|ok| ok));
+
+        // an authority that also pays signs once
+        let extend_ix = instruction::extend_lookup_table(
+            table_address,
+            payer.pubkey(),
+            Some(payer.pubkey()),
+            vec![Pubkey::new_unique()],
+        );
+        let message =
+            compile_message(&payer.pubkey(), &[extend_ix], &[], Hash::new_unique()).unwrap();
+        let transaction = sign_message(message.clone(), &[&payer, &payer]).unwrap();
+        assert_eq!(transaction.signatures.len(), 1);
+        assert!(transaction.verify_with_results().into_iter().all(|ok| ok));
+
+        assert_eq!(
+            sign_message(message, &[&authority]).unwrap_err(),
+            SignerError::NotEnoughSigners
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lookup_table_lifecycle() -> Result<()> {
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let payer = Keypair::new();
+        common::airdrop(&client, &payer, LAMPORTS_PER_SOL * 2).await?;
+        let sender = TransactionSender::new(client.clone());
+
+        let table_address = create_lookup_table(&sender, &payer, &payer).await?;
+        let recipients: Vec<Pubkey> = (0..30).map(|_| Pubkey::new_unique()).collect();
+        let signatures =
+            extend_lookup_table(&sender, &table_address, &payer, &payer, &recipients).await?;
+        assert_eq!(signatures.len(), 2);
+
+        let table = fetch_lookup_table(&client, &table_address).await?;
+        assert!(table.is_active());
+        assert_eq!(table.authority, Some(payer.pubkey()));
+        assert_eq!(table.addresses, recipients);
+
+        // extended addresses resolve from the next slot on
+        let extended_slot = table.last_extended_slot;
+        while client.get_slot().await? <= extended_slot {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        }
+
+        // 30 transfers exceed the legacy size limit, with the table they fit
+        let sender = sender.with_lookup_tables(vec![table.account()]);
+        sender
+            .send(
+                &transfers(&payer.pubkey(), &recipients),
+                &payer.pubkey(),
+                &[&payer],
+            )
+            .await?;
+        assert_eq!(
+            client.get_balance(&recipients[29]).await?,
+            LAMPORTS_PER_SOL / 100
+        );
+
+        deactivate_lookup_table(&sender, &table_address, &payer).await?;
+        assert!(
+            !fetch_lookup_table(&client, &table_address)
+                .await?
+                .is_active()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,364 @@
+use crate::sender::TransactionSender;
+use anyhow::{Result, anyhow};
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, rpc_client::GetConfirmedSignaturesForAddress2Config,
+    rpc_config::RpcTransactionConfig,
+};
+use solana_sdk::{
+    instruction::{CompiledInstruction, Instruction},
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+};
+use solana_transaction_status_client_types::{
+    UiTransactionEncoding, option_serializer::OptionSerializer,
+};
+use spl_token_2022::{
+    extension::transfer_fee::instruction::TransferFeeInstruction,
+    extension::{
+        BaseStateWithExtensions, ExtensionType, StateWithExtensionsOwned,
+        memo_transfer::{
+            instruction::{disable_required_transfer_memos, enable_required_transfer_memos},
+            memo_required,
+        },
+    },
+    instruction::{TokenInstruction, reallocate},
+    state::Account,
+};
+
+/// An SPL memo and the accounts that sign it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Memo {
+    pub text: String,
+    /// Must be among the signers of the transaction
+    pub signers: Vec<Pubkey>,
+}
+
+impl Memo {
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            signers: vec![],
+        }
+    }
+
+    pub fn signed_by(mut self, signer: Pubkey) -> Self {
+        self.signers.push(signer);
+        self
+    }
+
+    pub fn instruction(&self) -> Instruction {
+        let signers: Vec<&Pubkey> = self.signers.iter().collect();
+        spl_memo::build_memo(self.text.as_bytes(), &signers)
+    }
+}
+
+fn is_token_program(program_id: &Pubkey) -> bool {
+    *program_id == spl_token::id() || *program_id == spl_token_2022::id()
+}
+
+/// Token transfers, Token-2022 checks the instruction right before each for a memo
+// the unchecked `Transfer` is deprecated but still sent
+#[allow(deprecated)]
+fn is_token_transfer(instruction: &Instruction) -> bool {
+    if !is_token_program(&instruction.program_id) {
+        return false;
+    }
+    match TokenInstruction::unpack(&instruction.data) {
+        Ok(TokenInstruction::Transfer { .. } | TokenInstruction::TransferChecked { .. }) => true,
+        Ok(TokenInstruction::TransferFeeExtension) => matches!(
+            TransferFeeInstruction::unpack(&instruction.data[1..]),
+            Ok(TransferFeeInstruction::TransferCheckedWithFee { .. })
+        ),
+        _ => false,
+    }
+}
+
+/// Where `attach_memo` puts the memo: right before every token transfer, where Token-2022
+/// looks for it, otherwise in front
+///
+/// Positions are indexes into `instructions`, in ascending order.
+pub fn memo_positions(instructions: &[Instruction]) -> Vec<usize> {
+    let positions: Vec<usize> = instructions
+        .iter()
+        .enumerate()
+        .filter(|(_, instruction)| is_token_transfer(instruction))
+        .map(|(index, _)| index)
+        .collect();
+    if positions.is_empty() {
+        return vec![0];
+    }
+    positions
+}
+
+/// `instructions` with the memo inserted at every one of `memo_positions`
+pub fn attach_memo(instructions: &[Instruction], memo: &Memo) -> Vec<Instruction> {
+    let positions = memo_positions(instructions);
+    let mut with_memo = Vec::with_capacity(instructions.len() + positions.len());
+    for (index, instruction) in instructions.iter().enumerate() {
+        if positions.contains(&index) {
+            with_memo.push(memo.instruction());
+        }
+        with_memo.push(instruction.clone());
+    }
+    if instructions.is_empty() {
+        with_memo.push(memo.instruction());
+    }
+    with_memo
+}
+
+/// True if the token account rejects transfers without a memo
+pub fn account_requires_memo(data: &[u8]) -> Result<bool> {
+    let state = StateWithExtensionsOwned::<Account>::unpack(data.to_vec())?;
+    Ok(memo_required(&state))
+}
+
+pub async fn requires_memo(client: &RpcClient, token_account: &Pubkey) -> Result<bool> {
+    let data = client.get_account_data(token_account).await?;
+    account_requires_memo(&data)
+}
+
+/// Make incoming transfers to a Token-2022 account require a memo
+///
+/// Accounts created without the MemoTransfer extension are reallocated first, paid by `owner`.
+pub async fn enable_required_memos(
+    sender: &TransactionSender,
+    token_account: &Pubkey,
+    owner: &Keypair,
+) -> Result<Signature> {
+    let data = sender.client().get_account_data(token_account).await?;
+    let state = StateWithExtensionsOwned::<Account>::unpack(data)?;
+    let program_id = spl_token_2022::id();
+
+    let mut instructions = vec![];
+    if !state
+        .get_extension_types()?
+        .contains(&ExtensionType::MemoTransfer)
+    {
+        instructions.push(reallocate(
+            &program_id,                    // program id
+            token_account,                  // account
+            &owner.pubkey(),                // payer
+            &owner.pubkey(),                // owner
+            &[&owner.pubkey()],             // signers
+            &[ExtensionType::MemoTransfer], // extension types
+        )?);
+    }
+    instructions.push(enable_required_transfer_memos(
+        &program_id,
+        token_account,
+        &owner.pubkey(),
+        &[&owner.pubkey()],
+    )?);
+
+    let outcome = sender
+        .send(&instructions, &owner.pubkey(), &[owner])
+        .await?;
+    Ok(outcome.signature)
+}
+
+pub async fn disable_required_memos(
+    sender: &TransactionSender,
+    token_account: &Pubkey,
+    owner: &Keypair,
+) -> Result<Signature> {
+    let instruction = disable_required_transfer_memos(
+        &spl_token_2022::id(),
+        token_account,
+        &owner.pubkey(),
+        &[&owner.pubkey()],
+    )?;
+    let outcome = sender
+        .send(&[instruction], &owner.pubkey(), &[owner])
+        .await?;
+    Ok(outcome.signature)
+}
+
+/// A memo found in a transaction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedMemo {
+    /// Index of the memo instruction
+    pub index: usize,
+    pub text: String,
+    pub signers: Vec<Pubkey>,
+}
+
+/// Memos of both memo program versions among `instructions`
+///
+/// Memos that aren't UTF-8 are decoded lossily, the program rejects them but v1 didn't.
+pub fn decode_memos(
+    account_keys: &[Pubkey],
+    instructions: &[CompiledInstruction],
+) -> Result<Vec<DecodedMemo>> {
+    let key = |index: u8| {
+        account_keys
+            .get(index as usize)
+            .copied()
+            .ok_or_else(|| anyhow!("account index {} out of range", index))
+    };
+
+    let mut memos = vec![];
+    for (index, instruction) in instructions.iter().enumerate() {
+        let program_id = key(instruction.program_id_index)?;
+        if program_id != spl_memo::id() && program_id != spl_memo::v1::id() {
+            continue;
+        }
+        memos.push(DecodedMemo {
+            index,
+            text: String::from_utf8_lossy(&instruction.data).into_owned(),
+            signers: instruction
+                .accounts
+                .iter()
+                .map(|&account| key(account))
+                .collect::<Result<_>>()?,
+        });
+    }
+    Ok(memos)
+}
+
+/// Fetch a confirmed transaction and decode its memos
+pub async fn get_transaction_memos(
+    client: &RpcClient,
+    signature: &Signature,
+) -> Result<Vec<DecodedMemo>> {
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        commitment: Some(client.commitment()),
+        max_supported_transaction_version: Some(0),
+    };
+    let confirmed = client
+        .get_transaction_with_config(signature, config)
+        .await?;
+    let transaction = confirmed
+        .transaction
+        .transaction
+        .decode()
+        .ok_or_else(|| anyhow!("failed to decode transaction {}", signature))?;
+
+    // memo signers may be loaded from lookup tables in v0 transactions
+    let mut account_keys = transaction.message.static_account_keys().to_vec();
+    if let Some(meta) = confirmed.transaction.meta
+        && let OptionSerializer::Some(loaded) = meta.loaded_addresses
+    {
+        for address in loaded.writable.iter().chain(loaded.readonly.iter()) {
+            account_keys.push(address.parse()?);
+        }
+    }
+    decode_memos(&account_keys, transaction.message.instructions())
+}
+
+/// Memos of the latest `limit` transactions touching `address`, newest first
+///
+/// Uses the memo field of `getSignaturesForAddress`, one request for all of them. The node
+/// formats each memo as `[length] text` and joins several memos of one transaction with "; ",
+/// use `get_transaction_memos` for the exact memos.
+pub async fn address_memos(
+    client: &RpcClient,
+    address: &Pubkey,
+    limit: usize,
+) -> Result<Vec<(Signature, String)>> {
+    let config = GetConfirmedSignaturesForAddress2Config {
+        limit: Some(limit),
+        commitment: Some(client.commitment()),
+        ..GetConfirmedSignaturesForAddress2Config::default()
+    };
+    let statuses = client
+        .get_signatures_for_address_with_config(address, config)
+        .await?;
+
+    let mut memos = vec![];
+    for status in statuses {
+        if status.err.is_none()
+            && let Some(memo) = status.memo
+        {
+            memos.push((status.signature.parse()?, memo));
+        }
+    }
+    Ok(memos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common;
+    use crate::config::ClusterConfig;
+    use crate::transaction::send_sol;
+    use solana_sdk::{message::Message, native_token::LAMPORTS_PER_SOL, system_instruction};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_attach_and_decode() {
+        let payer = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let create =
+            spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                &payer,
+                &payer,
+                &mint,
+                &spl_token_2022::id(),
+            );
+        let transfer = spl_token_2022::instruction::transfer_checked(
+            &spl_token_2022::id(),
+            &Pubkey::new_unique(),
+            &mint,
+            &Pubkey::new_unique(),
+            &payer,
+            &[],
+            1,
+            0,
+        )
+        .unwrap();
+        let memo = Memo::new("invoice 42").signed_by(payer);
+
+        // the memo goes right before the token transfer
+        let instructions = attach_memo(&[create.clone(), transfer.clone()], &memo);
+        assert_eq!(instructions[0], create);
+        assert_eq!(instructions[1].program_id, spl_memo::id());
+
+        // and before every other one
+        let batch = [
+            create.clone(),
+            transfer.clone(),
+            create.clone(),
+            transfer.clone(),
+        ];
+        assert_eq!(memo_positions(&batch), [1, 3]);
+        let with_memos = attach_memo(&batch, &memo);
+        assert_eq!(with_memos.len(), 6);
+        assert_eq!(with_memos[3], create);
+        assert_eq!(with_memos[4].program_id, spl_memo::id());
+        assert_eq!(with_memos[5], transfer);
+
+        // without token transfers it goes first
+        let sol = system_instruction::transfer(&payer, &Pubkey::new_unique(), 1);
+        assert_eq!(memo_positions(&[sol.clone(), create.clone()]), [0]);
+        assert_eq!(attach_memo(&[sol], &memo)[0].program_id, spl_memo::id());
+
+        let message = Message::new(&instructions, Some(&payer));
+        let memos = decode_memos(&message.account_keys, &message.instructions).unwrap();
+        assert_eq!(
+            memos,
+            [DecodedMemo {
+                index: 1,
+                text: "invoice 42".to_string(),
+                signers: vec![payer],
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memo_on_send_sol() -> Result<()> {
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let payer = Keypair::new();
+        common::airdrop(&client, &payer, LAMPORTS_PER_SOL).await?;
+
+        let sender = TransactionSender::new(client.clone())
+            .with_memo(Some(Memo::new("payroll 2026-10").signed_by(payer.pubkey())));
+        let to = Pubkey::new_unique();
+        let outcome = send_sol(&sender, &payer, &to, LAMPORTS_PER_SOL / 10).await?;
+
+        let memos = get_transaction_memos(&client, &outcome.signature).await?;
+        assert_eq!(memos[0].text, "payroll 2026-10");
+        assert_eq!(memos[0].signers, [payer.pubkey()]);
+        Ok(())
+    }
+}
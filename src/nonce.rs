@@ -0,0 +1,238 @@
+use crate::sender::TransactionSender;
+use anyhow::Result;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    nonce_utils::{self, nonblocking::data_from_account},
+};
+use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    instruction::Instruction,
+    nonce::State,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    signer::{SignerError, signers::Signers},
+    system_instruction,
+    transaction::Transaction,
+};
+
+/// Nonce account whose stored nonce replaces the recent blockhash
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NonceAccount {
+    pub address: Pubkey,
+    /// Signs the `advance_nonce_account` instruction of every transaction using the nonce
+    pub authority: Pubkey,
+}
+
+/// State of an initialized nonce account
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NonceInfo {
+    pub address: Pubkey,
+    pub authority: Pubkey,
+    /// Goes into the transaction in place of a recent blockhash
+    pub blockhash: Hash,
+    pub lamports_per_signature: u64,
+    pub lamports: u64,
+}
+
+/// Decode a fetched nonce account
+pub fn nonce_info(address: &Pubkey, account: &Account) -> Result<NonceInfo, nonce_utils::Error> {
+    let data = data_from_account(account)?;
+    Ok(NonceInfo {
+        address: *address,
+        authority: data.authority,
+        blockhash: data.blockhash(),
+        lamports_per_signature: data.get_lamports_per_signature(),
+        lamports: account.lamports,
+    })
+}
+
+pub async fn fetch_nonce(client: &RpcClient, address: &Pubkey) -> Result<NonceInfo> {
+    let account =
+        nonce_utils::nonblocking::get_account_with_commitment(client, address, client.commitment())
+            .await?;
+    Ok(nonce_info(address, &account)?)
+}
+
+/// `instructions` with `advance_nonce_account` in front, where the runtime looks for it
+pub fn nonce_instructions(nonce: &NonceAccount, instructions: &[Instruction]) -> Vec<Instruction> {
+    let mut with_advance = vec![system_instruction::advance_nonce_account(
+        &nonce.address,
+        &nonce.authority,
+    )];
+    with_advance.extend_from_slice(instructions);
+    with_advance
+}
+
+/// Sign a transaction that stays valid until the nonce is advanced, no RPC involved
+///
+/// `nonce_hash` is the stored nonce, fetched with `fetch_nonce` while online. `signers` must
+/// include the nonce authority.
+pub fn build_nonce_transaction<S: Signers + ?Sized>(
+    instructions: &[Instruction],
+    payer: &Pubkey,
+    nonce: &NonceAccount,
+    nonce_hash: Hash,
+    signers: &S,
+) -> Result<Transaction, SignerError> {
+    let mut transaction =
+        Transaction::new_with_payer(&nonce_instructions(nonce, instructions), Some(payer));
+    transaction.try_sign(signers, nonce_hash)?;
+    Ok(transaction)
+}
+
+/// Create and initialize a rent-exempt nonce account controlled by `authority`
+pub async fn create_nonce_account(
+    sender: &TransactionSender,
+    payer: &Keypair,
+    nonce_account: &Keypair,
+    authority: &Pubkey,
+) -> Result<Signature> {
+    let lamports = sender
+        .client()
+        .get_minimum_balance_for_rent_exemption(State::size())
+        .await?;
+    let instructions = system_instruction::create_nonce_account(
+        &payer.pubkey(),         // funding account
+        &nonce_account.pubkey(), // nonce account
+        authority,               // nonce authority
+        lamports,                // rent exempt balance
+    );
+    let outcome = sender
+        .send(&instructions, &payer.pubkey(), &[payer, nonce_account])
+        .await?;
+    println!("Nonce Account: {}", nonce_account.pubkey());
+    println!("Nonce Transaction Signature: {}", outcome.signature);
+    Ok(outcome.signature)
+}
+
+/// Hand the nonce to `new_authority`
+pub async fn authorize_nonce_account(
+    sender: &TransactionSender,
+    address: &Pubkey,
+    authority: &Keypair,
+    new_authority: &Pubkey,
+) -> Result<Signature> {
+    let instruction =
+        system_instruction::authorize_nonce_account(address, &authority.pubkey(), new_authority);
+    let outcome = sender
+        .send(&[instruction], &authority.pubkey(), &[authority])
+        .await?;
+    Ok(outcome.signature)
+}
+
+/// Move lamports out, withdrawing everything closes the account
+pub async fn withdraw_nonce_account(
+    sender: &TransactionSender,
+    address: &Pubkey,
+    authority: &Keypair,
+    to: &Pubkey,
+    lamports: u64,
+) -> Result<Signature> {
+    let instruction =
+        system_instruction::withdraw_nonce_account(address, &authority.pubkey(), to, lamports);
+    let outcome = sender
+        .send(&[instruction], &authority.pubkey(), &[authority])
+        .await?;
+    Ok(outcome.signature)
+}
+
+/// Store a new nonce, which invalidates every transaction signed with the old one
+pub async fn advance_nonce_account(
+    sender: &TransactionSender,
+    address: &Pubkey,
+    authority: &Keypair,
+) -> Result<Signature> {
+    let instruction = system_instruction::advance_nonce_account(address, &authority.pubkey());
+    let outcome = sender
+        .send(&[instruction], &authority.pubkey(), &[authority])
+        .await?;
+    Ok(outcome.signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common;
+    use crate::config::ClusterConfig;
+    use crate::transaction::send_sol;
+    use solana_sdk::{native_token::LAMPORTS_PER_SOL, system_program};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_build_nonce_transaction() {
+        let payer = Keypair::new();
+        let authority = Keypair::new();
+        let nonce = NonceAccount {
+            address: Pubkey::new_unique(),
+            authority: authority.pubkey(),
+        };
+        let nonce_hash = Hash::new_unique();
+        let transfer = system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1);
+
+        let transaction = build_nonce_transaction(
+            &[transfer],
+            &payer.pubkey(),
+            &nonce,
+            nonce_hash,
+            &[&payer, &authority],
+        )
+        .unwrap();
+        assert_eq!(transaction.message.recent_blockhash, nonce_hash);
+        assert_eq!(transaction.message.instructions.len(), 2);
+        let advance = &transaction.message.instructions[0];
+        assert_eq!(
+            transaction.message.account_keys[advance.program_id_index as usize],
+            system_program::id()
+        );
+        assert!(transaction.verify().is_ok());
+
+        // the nonce authority has to sign
+        let unsigned = build_nonce_transaction(&[], &payer.pubkey(), &nonce, nonce_hash, &[&payer]);
+        assert!(unsigned.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_nonce_lifecycle() -> Result<()> {
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let payer = Keypair::new();
+        common::airdrop(&client, &payer, LAMPORTS_PER_SOL).await?;
+        let sender = TransactionSender::new(client.clone());
+
+        let nonce_keypair = Keypair::new();
+        create_nonce_account(&sender, &payer, &nonce_keypair, &payer.pubkey()).await?;
+        let info = fetch_nonce(&client, &nonce_keypair.pubkey()).await?;
+        assert_eq!(info.authority, payer.pubkey());
+
+        // send_sol on a sender in nonce mode consumes the nonce
+        let nonce = NonceAccount {
+            address: nonce_keypair.pubkey(),
+            authority: payer.pubkey(),
+        };
+        let nonce_sender = sender.clone().with_durable_nonce(Some(nonce));
+        let to = Pubkey::new_unique();
+        let outcome = send_sol(&nonce_sender, &payer, &to, LAMPORTS_PER_SOL / 10).await?;
+        assert_eq!(outcome.blockhash, info.blockhash);
+        assert_ne!(
+            fetch_nonce(&client, &nonce.address).await?.blockhash,
+            info.blockhash
+        );
+
+        let new_authority = Keypair::new();
+        authorize_nonce_account(&sender, &nonce.address, &payer, &new_authority.pubkey()).await?;
+        let info = fetch_nonce(&client, &nonce.address).await?;
+        assert_eq!(info.authority, new_authority.pubkey());
+
+        common::airdrop(&client, &new_authority, LAMPORTS_PER_SOL).await?;
+        withdraw_nonce_account(
+            &sender,
+            &nonce.address,
+            &new_authority,
+            &payer.pubkey(),
+            info.lamports,
+        )
+        .await?;
+        assert!(fetch_nonce(&client, &nonce.address).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,340 @@
+use anyhow::{Result, anyhow, bail};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    bs58,
+    hash::Hash,
+    instruction::Instruction,
+    message::Message,
+    pubkey::Pubkey,
+    sanitize::Sanitize,
+    signature::{Presigner, Signature},
+    signer::signers::Signers,
+    transaction::Transaction,
+};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageEncoding {
+    Base58,
+    #[default]
+    Base64,
+}
+
+/// Serialized message bytes, what every signer signs
+pub fn encode_message(message: &Message, encoding: MessageEncoding) -> String {
+    let bytes = message.serialize();
+    match encoding {
+        MessageEncoding::Base58 => bs58::encode(bytes).into_string(),
+        MessageEncoding::Base64 => STANDARD.encode(bytes),
+    }
+}
+
+pub fn decode_message(encoded: &str, encoding: MessageEncoding) -> Result<Message> {
+    let bytes = match encoding {
+        MessageEncoding::Base58 => bs58::decode(encoded).into_vec()?,
+        MessageEncoding::Base64 => STANDARD.decode(encoded)?,
+    };
+    // messages are serialized with bincode 1, which the legacy config reproduces
+    let (message, _): (Message, usize) =
+        bincode::serde::decode_from_slice(&bytes, bincode::config::legacy())?;
+    // a header claiming more signers than keys would panic later on
+    message
+        .sanitize()
+        .map_err(|err| anyhow!("malformed message: {}", err))?;
+    Ok(message)
+}
+
+/// One required signer of a manifest and its signature once collected
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestSigner {
+    pub pubkey: String,
+    pub signature: Option<String>,
+}
+
+/// JSON document passed between the service and the signing machines
+///
+/// Holds the message and every required signer, in the order of the transaction's
+/// signatures, with the signatures collected so far.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SigningManifest {
+    pub encoding: MessageEncoding,
+    pub message: String,
+    pub fee_payer: String,
+    /// Recent blockhash or durable nonce the message was built with
+    pub recent_blockhash: String,
+    pub signers: Vec<ManifestSigner>,
+}
+
+impl SigningManifest {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Decode the message, which a signer should inspect before signing
+    pub fn message(&self) -> Result<Message> {
+        let message = decode_message(&self.message, self.encoding)?;
+        if message.recent_blockhash.to_string() != self.recent_blockhash {
+            bail!("manifest blockhash doesn't match its message");
+        }
+        Ok(message)
+    }
+
+    /// Pubkeys still missing a signature
+    pub fn missing_signers(&self) -> Vec<&str> {
+        self.signers
+            .iter()
+            .filter(|signer| signer.signature.is_none())
+            .map(|signer| signer.pubkey.as_str())
+            .collect()
+    }
+
+    /// Sign the message with each of `signers` that is required, returns how many signed
+    ///
+    /// Meant to run on the machine holding the keys, only the manifest leaves it.
+    pub fn sign<S: Signers + ?Sized>(&mut self, signers: &S) -> Result<usize> {
+        let message_bytes = self.message()?.serialize();
+        let pubkeys = signers.pubkeys();
+        let signatures = signers.try_sign_message(&message_bytes)?;
+
+        let mut signed = 0;
+        for (pubkey, signature) in pubkeys.iter().zip(signatures) {
+            let Some(entry) = self
+                .signers
+                .iter_mut()
+                .find(|entry| entry.pubkey == pubkey.to_string())
+            else {
+                continue;
+            };
+            entry.signature = Some(signature.to_string());
+            signed += 1;
+        }
+        Ok(signed)
+    }
+}
+
+/// A transaction collecting signatures from several parties
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartialTransaction {
+    transaction: Transaction,
+}
+
+impl PartialTransaction {
+    /// Unsigned transaction over `instructions`
+    ///
+    /// Signatures from machines that take long to respond need `blockhash` to be a durable
+    /// nonce, see `crate::nonce::nonce_instructions`.
+    pub fn new(instructions: &[Instruction], payer: &Pubkey, blockhash: Hash) -> Self {
+        let message = Message::new_with_blockhash(instructions, Some(payer), &blockhash);
+        Self {
+            transaction: Transaction::new_unsigned(message),
+        }
+    }
+
+    /// Fails for messages that don't sanitize, as `decode_message` does
+    pub fn from_message(message: Message) -> Result<Self> {
+        message
+            .sanitize()
+            .map_err(|err| anyhow!("malformed message: {}", err))?;
+        Ok(Self {
+            transaction: Transaction::new_unsigned(message),
+        })
+    }
+
+    pub fn message(&self) -> &Message {
+        &self.transaction.message
+    }
+
+    /// Signer pubkeys in signature order
+    pub fn required_signers(&self) -> &[Pubkey] {
+        let num_signers = self.transaction.message.header.num_required_signatures as usize;
+        &self.transaction.message.account_keys[..num_signers]
+    }
+
+    pub fn missing_signers(&self) -> Vec<Pubkey> {
+        self.required_signers()
+            .iter()
+            .zip(&self.transaction.signatures)
+            .filter(|(_, signature)| **signature == Signature::default())
+            .map(|(pubkey, _)| *pubkey)
+            .collect()
+    }
+
+    /// Sign with keys held in this process, the other signatures stay untouched
+    pub fn sign<S: Signers + ?Sized>(&mut self, signers: &S) -> Result<()> {
+        let blockhash = self.transaction.message.recent_blockhash;
+        self.transaction.try_partial_sign(signers, blockhash)?;
+        Ok(())
+    }
+
+    /// Add a signature made elsewhere, fails unless it is `pubkey`'s signature of this message
+    pub fn add_signature(&mut self, pubkey: &Pubkey, signature: &Signature) -> Result<()> {
+        self.sign(&[&Presigner::new(pubkey, signature)])
+    }
+
+    /// Take over every signature collected in `manifest`
+    pub fn merge(&mut self, manifest: &SigningManifest) -> Result<()> {
+        if manifest.message()? != self.transaction.message {
+            bail!("manifest is for a different message");
+        }
+        for signer in &manifest.signers {
+            if let Some(signature) = &signer.signature {
+                let pubkey = Pubkey::from_str(&signer.pubkey)?;
+                self.add_signature(&pubkey, &Signature::from_str(signature)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn manifest(&self, encoding: MessageEncoding) -> SigningManifest {
+        let message = &self.transaction.message;
+        let signers = self
+            .required_signers()
+            .iter()
+            .zip(&self.transaction.signatures)
+            .map(|(pubkey, signature)| ManifestSigner {
+                pubkey: pubkey.to_string(),
+                signature: (*signature != Signature::default()).then(|| signature.to_string()),
+            })
+            .collect();
+        SigningManifest {
+            encoding,
+            message: encode_message(message, encoding),
+            fee_payer: message.account_keys[0].to_string(),
+            recent_blockhash: message.recent_blockhash.to_string(),
+            signers,
+        }
+    }
+
+    /// The transaction, once every required signature is present and valid
+    pub fn into_transaction(self) -> Result<Transaction> {
+        let missing = self.missing_signers();
+        if !missing.is_empty() {
+            bail!("missing signatures from {:?}", missing);
+        }
+        self.transaction.verify()?;
+        Ok(self.transaction)
+    }
+}
+
+/// Verify every signature and send, returns once confirmed
+pub async fn broadcast(client: &RpcClient, transaction: &Transaction) -> Result<Signature> {
+    transaction
+        .verify()
+        .map_err(|err| anyhow!("refusing to broadcast: {}", err))?;
+    let signature = client.send_and_confirm_transaction(transaction).await?;
+    println!("Transaction Signature: {}", signature);
+    Ok(signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common;
+    use crate::config::ClusterConfig;
+    use solana_sdk::{
+        native_token::LAMPORTS_PER_SOL,
+        signature::{Keypair, Signer},
+        system_instruction,
+    };
+
+    // hot payer plus a cold key that owns the funds
+    fn transfer(
+        payer: &Keypair,
+        cold: &Keypair,
+        to: &Pubkey,
+        blockhash: Hash,
+    ) -> PartialTransaction {
+        let mut transfer = system_instruction::transfer(&cold.pubkey(), to, LAMPORTS_PER_SOL / 10);
+        transfer.accounts[0].is_signer = true;
+        PartialTransaction::new(&[transfer], &payer.pubkey(), blockhash)
+    }
+
+    #[test]
+    fn test_message_encoding() {
+        let payer = Keypair::new();
+        let cold = Keypair::new();
+        let partial = transfer(&payer, &cold, &Pubkey::new_unique(), Hash::new_unique());
+        for encoding in [MessageEncoding::Base58, MessageEncoding::Base64] {
+            let encoded = encode_message(partial.message(), encoding);
+            assert_eq!(
+                &decode_message(&encoded, encoding).unwrap(),
+                partial.message()
+            );
+        }
+        assert!(decode_message("not a message", MessageEncoding::Base64).is_err());
+
+        // more required signers than account keys
+        let mut message = partial.message().clone();
+        message.header.num_required_signatures = 5;
+        let encoded = encode_message(&message, MessageEncoding::Base64);
+        assert!(decode_message(&encoded, MessageEncoding::Base64).is_err());
+        assert!(PartialTransaction::from_message(message).is_err());
+        assert!(PartialTransaction::from_message(partial.message().clone()).is_ok());
+    }
+
+    #[test]
+    fn test_multi_party_signing() {
+        let payer = Keypair::new();
+        let cold = Keypair::new();
+        let mut partial = transfer(&payer, &cold, &Pubkey::new_unique(), Hash::new_unique());
+        assert_eq!(partial.required_signers(), [payer.pubkey(), cold.pubkey()]);
+
+        partial.sign(&[&payer]).unwrap();
+        assert_eq!(partial.missing_signers(), [cold.pubkey()]);
+        let json = partial.manifest(MessageEncoding::Base58).to_json().unwrap();
+
+        // on the cold machine
+        let mut manifest = SigningManifest::from_json(&json).unwrap();
+        assert_eq!(manifest.missing_signers(), [cold.pubkey().to_string()]);
+        assert_eq!(manifest.sign(&[&cold, &Keypair::new()]).unwrap(), 1);
+        assert!(manifest.missing_signers().is_empty());
+        let json = manifest.to_json().unwrap();
+
+        // back on the service host, a wrong signature is refused
+        let forged = Keypair::new().sign_message(&partial.message().serialize());
+        assert!(partial.add_signature(&cold.pubkey(), &forged).is_err());
+        assert!(partial.clone().into_transaction().is_err());
+
+        partial
+            .merge(&SigningManifest::from_json(&json).unwrap())
+            .unwrap();
+        let transaction = partial.into_transaction().unwrap();
+        assert!(transaction.is_signed());
+
+        // a manifest of another message doesn't merge
+        let mut other = transfer(&payer, &cold, &Pubkey::new_unique(), Hash::new_unique());
+        assert!(
+            other
+                .merge(&SigningManifest::from_json(&json).unwrap())
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_offline_signing_broadcast() -> Result<()> {
+        let client = common::get_rpc_client(&ClusterConfig::resolve()?);
+        let payer = Keypair::new();
+        let cold = Keypair::new();
+        common::airdrop(&client, &payer, LAMPORTS_PER_SOL).await?;
+        common::airdrop(&client, &cold, LAMPORTS_PER_SOL).await?;
+        let to = Pubkey::new_unique();
+
+        let mut partial = transfer(&payer, &cold, &to, client.get_latest_blockhash().await?);
+        partial.sign(&[&payer])?;
+        let mut manifest =
+            SigningManifest::from_json(&partial.manifest(MessageEncoding::Base64).to_json()?)?;
+        manifest.sign(&[&cold])?;
+        partial.merge(&manifest)?;
+
+        broadcast(&client, &partial.into_transaction()?).await?;
+        assert_eq!(client.get_balance(&to).await?, LAMPORTS_PER_SOL / 10);
+        Ok(())
+    }
+}
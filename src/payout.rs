@@ -0,0 +1,832 @@
+use crate::budget::{ComputeBudget, MAX_COMPUTE_UNIT_LIMIT};
+use crate::sender::TransactionSender;
+use crate::token::{amount::UiAmount, mint_cache::MintCache};
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    instruction::Instruction,
+    message::Message,
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_instruction,
+};
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_token_2022::instruction::transfer_checked;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+pub const SOL_DECIMALS: u8 = 9;
+
+// rough per-instruction costs used for packing, the sender sizes the real budget
+const SOL_TRANSFER_UNITS: u32 = 300;
+const TOKEN_TRANSFER_UNITS: u32 = 10_000;
+const CREATE_ATA_UNITS: u32 = 40_000;
+
+/// One line of a payout list, amounts are human readable like "1.5"
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PayoutEntry {
+    pub recipient: Pubkey,
+    pub amount: String,
+    /// `None` pays SOL
+    pub mint: Option<Pubkey>,
+}
+
+impl PayoutEntry {
+    // identifies the entry in the journal, so a changed input isn't resumed
+    fn key(&self, index: usize) -> String {
+        let asset = self.mint.map_or("SOL".to_string(), |mint| mint.to_string());
+        format!("{}:{}:{}:{}", index, self.recipient, asset, self.amount)
+    }
+}
+
+/// Parse `recipient,amount[,mint]` lines, with an optional header line
+///
+/// Blank lines and lines starting with `#` are skipped.
+pub fn parse_csv(input: &str) -> Result<Vec<PayoutEntry>> {
+    let mut entries = vec![];
+    for (number, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if entries.is_empty() && fields[0].eq_ignore_ascii_case("recipient") {
+            continue;
+        }
+        let (recipient, amount, mint) = match fields.as_slice() {
+            [recipient, amount] => (recipient, amount, None),
+            [recipient, amount, ""] => (recipient, amount, None),
+            [recipient, amount, mint] => (recipient, amount, Some(mint)),
+            _ => bail!("line {}: expected recipient,amount[,mint]", number + 1),
+        };
+        entries.push(PayoutEntry {
+            recipient: Pubkey::from_str(recipient)
+                .map_err(|err| anyhow!("line {}: recipient: {}", number + 1, err))?,
+            amount: amount.to_string(),
+            mint: mint
+                .map(|mint| Pubkey::from_str(mint))
+                .transpose()
+                .map_err(|err| anyhow!("line {}: mint: {}", number + 1, err))?,
+        });
+    }
+    Ok(entries)
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonAmount {
+    Text(String),
+    Number(serde_json::Number),
+}
+
+#[derive(Deserialize)]
+struct JsonEntry {
+    recipient: String,
+    amount: JsonAmount,
+    mint: Option<String>,
+}
+
+/// Parse `[{"recipient": .., "amount": "1.5", "mint": ..}]`, amounts may also be numbers
+pub fn parse_json(input: &str) -> Result<Vec<PayoutEntry>> {
+    let raw: Vec<JsonEntry> = serde_json::from_str(input)?;
+    raw.into_iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            Ok(PayoutEntry {
+                recipient: Pubkey::from_str(&entry.recipient)
+                    .map_err(|err| anyhow!("entry {}: recipient: {}", index, err))?,
+                amount: match entry.amount {
+                    JsonAmount::Text(amount) => amount,
+                    JsonAmount::Number(amount) => amount.to_string(),
+                },
+                mint: entry
+                    .mint
+                    .map(|mint| Pubkey::from_str(&mint))
+                    .transpose()
+                    .map_err(|err| anyhow!("entry {}: mint: {}", index, err))?,
+            })
+        })
+        .collect()
+}
+
+/// Read a `.csv` or `.json` payout list
+pub fn load_payouts(path: &Path) -> Result<Vec<PayoutEntry>> {
+    let input = fs::read_to_string(path)?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => parse_json(&input),
+        Some("csv") => parse_csv(&input),
+        _ => bail!("{} is neither .csv nor .json", path.display()),
+    }
+}
+
+/// A payout turned into instructions
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlannedPayout {
+    pub index: usize,
+    /// In base units of the mint, lamports for SOL
+    pub amount: u64,
+    /// Creates the recipient's ATA if it didn't exist at planning time
+    pub setup: Option<Instruction>,
+    pub transfer: Instruction,
+    pub units: u32,
+}
+
+/// Payouts that go into one transaction
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Batch {
+    pub payouts: Vec<usize>,
+    pub instructions: Vec<Instruction>,
+    pub units: u32,
+}
+
+/// Size of a legacy transaction over `instructions` once signed by `payer` alone
+pub fn transaction_size(payer: &Pubkey, instructions: &[Instruction]) -> usize {
+    let message = Message::new(instructions, Some(payer));
+    let signatures = message.header.num_required_signatures as usize;
+    // one byte of short_vec length, fine below 128 signatures
+    1 + signatures * 64 + message.serialize().len()
+}
+
+/// Fill transactions greedily, in input order, up to the packet size and `max_units`
+///
+/// The size includes the two compute budget instructions the sender adds.
+pub fn pack(payer: &Pubkey, payouts: &[PlannedPayout], max_units: u32) -> Result<Vec<Batch>> {
+    let budget = ComputeBudget {
+        unit_limit: MAX_COMPUTE_UNIT_LIMIT,
+        unit_price: u64::MAX,
+        units_consumed: 0,
+    };
+    let fits = |batch: &Batch| {
+        batch.units <= max_units
+            && transaction_size(payer, &budget.apply(&batch.instructions)) <= PACKET_DATA_SIZE
+    };
+
+    let with_payout = |batch: &Batch, payout: &PlannedPayout| {
+        let mut candidate = batch.clone();
+        if let Some(setup) = &payout.setup {
+            // two payouts to the same new account share one create
+            if !candidate.instructions.contains(setup) {
+                candidate.instructions.push(setup.clone());
+                candidate.units += CREATE_ATA_UNITS;
+            }
+        }
+        candidate.instructions.push(payout.transfer.clone());
+        candidate.units += payout.units;
+        candidate.payouts.push(payout.index);
+        candidate
+    };
+
+    let mut batches = vec![];
+    let mut current = Batch::default();
+    for payout in payouts {
+        let mut candidate = with_payout(&current, payout);
+        if !fits(&candidate) && !current.payouts.is_empty() {
+            batches.push(std::mem::take(&mut current));
+            candidate = with_payout(&current, payout);
+        }
+        if !fits(&candidate) {
+            bail!("payout {} doesn't fit into a transaction", payout.index);
+        }
+        current = candidate;
+    }
+    if !current.payouts.is_empty() {
+        batches.push(current);
+    }
+    Ok(batches)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+enum JournalState {
+    /// Written before sending, so a crash leaves a trace
+    Pending,
+    Confirmed {
+        signature: String,
+        slot: u64,
+    },
+    /// Definitely not executed, sent again on resume
+    Failed {
+        error: String,
+    },
+    /// The send ended without a definitive outcome, `signature` may still have landed
+    Unknown {
+        error: String,
+        signature: Option<String>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct JournalRecord {
+    keys: Vec<String>,
+    #[serde(flatten)]
+    state: JournalState,
+}
+
+/// Append-only JSON lines file recording every batch, flushed to disk per record
+struct Journal {
+    file: File,
+}
+
+impl Journal {
+    /// Open `path`, returning the last state recorded per payout key
+    fn open(path: &Path) -> Result<(Self, HashMap<String, JournalState>)> {
+        let mut states = HashMap::new();
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                // a torn last line from a crash mid-write
+                let Ok(record) = serde_json::from_str::<JournalRecord>(&line) else {
+                    continue;
+                };
+                for key in record.keys {
+                    states.insert(key, record.state.clone());
+                }
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok((Self { file }, states))
+    }
+
+    fn record(&mut self, keys: &[String], state: JournalState) -> Result<()> {
+        let record = JournalRecord {
+            keys: keys.to_vec(),
+            state,
+        };
+        writeln!(self.file, "{}", serde_json::to_string(&record)?)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PayoutStatus {
+    Paid {
+        signature: Signature,
+        slot: u64,
+    },
+    Failed(String),
+    /// Sent without a definitive outcome, or before a crash, check on chain before resending
+    Unknown {
+        signature: Option<Signature>,
+        error: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReportRow {
+    pub index: usize,
+    pub recipient: Pubkey,
+    pub mint: Option<Pubkey>,
+    pub amount: String,
+    pub status: PayoutStatus,
+}
+
+/// Requested and settled amounts of one asset, in base units
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AssetTotals {
+    pub requested: u128,
+    pub paid: u128,
+    pub failed: u128,
+    pub unknown: u128,
+}
+
+/// Outcome of every payout, reconciled against the input
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PayoutReport {
+    pub rows: Vec<ReportRow>,
+    /// Keyed by mint, `None` for SOL
+    pub totals: BTreeMap<Option<Pubkey>, AssetTotals>,
+}
+
+impl PayoutReport {
+    pub fn is_complete(&self) -> bool {
+        self.rows
+            .iter()
+            .all(|row| matches!(row.status, PayoutStatus::Paid { .. }))
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = "index,recipient,amount,mint,status,signature,error\n".to_string();
+        for row in &self.rows {
+            let mint = row.mint.map(|mint| mint.to_string()).unwrap_or_default();
+            let (status, signature, error) = match &row.status {
+                PayoutStatus::Paid { signature, .. } => ("paid", signature.to_string(), ""),
+                PayoutStatus::Failed(error) => ("failed", String::new(), error.as_str()),
+                PayoutStatus::Unknown { signature, error } => (
+                    "unknown",
+                    signature
+                        .map(|signature| signature.to_string())
+                        .unwrap_or_default(),
+                    error.as_str(),
+                ),
+            };
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},\"{}\"",
+                row.index,
+                row.recipient,
+                row.amount,
+                mint,
+                status,
+                signature,
+                error.replace('"', "'")
+            );
+        }
+        csv
+    }
+}
+
+/// Limits of a payout run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PayoutConfig {
+    /// Transactions awaiting confirmation at once
+    pub max_in_flight: usize,
+    /// Pause between starting two transactions
+    pub send_interval: Duration,
+    /// Packing stops below this many estimated compute units per transaction
+    pub max_units_per_transaction: u32,
+    /// Send payouts again whose outcome is unknown, e.g. lost in a crash, may pay twice
+    pub resend_unknown: bool,
+}
+
+impl Default for PayoutConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 4,
+            send_interval: Duration::from_millis(100),
+            max_units_per_transaction: 1_200_000,
+            resend_unknown: false,
+        }
+    }
+}
+
+/// Pays SOL and tokens from `payer`'s wallet to many recipients
+pub struct PayoutEngine {
+    sender: TransactionSender,
+    payer: Arc<Keypair>,
+    config: PayoutConfig,
+    mints: MintCache,
+}
+
+impl PayoutEngine {
+    pub fn new(sender: TransactionSender, payer: Arc<Keypair>, config: PayoutConfig) -> Self {
+        Self {
+            sender,
+            payer,
+            config,
+            mints: MintCache::new(),
+        }
+    }
+
+    /// Convert amounts to base units and build the instructions of `entries`
+    pub async fn plan(&self, entries: &[(usize, PayoutEntry)]) -> Result<Vec<PlannedPayout>> {
+        let client = self.sender.client();
+        let payer = self.payer.pubkey();
+
+        // which recipient ATAs exist decides whether a create goes in front
+        let mut atas = vec![];
+        for (_, entry) in entries {
+            if let Some(mint) = &entry.mint {
+                let program = self.mints.program(client, mint).await?;
+                atas.push(program.associated_token_address(&entry.recipient, mint));
+            }
+        }
+        let mut existing = HashSet::new();
+        for chunk in atas.chunks(100) {
+            let accounts = client.get_multiple_accounts(chunk).await?;
+            for (address, account) in chunk.iter().zip(accounts) {
+                if account.is_some() {
+                    existing.insert(*address);
+                }
+            }
+        }
+
+        let mut planned = vec![];
+        for (index, entry) in entries {
+            let index = *index;
+            let payout = match &entry.mint {
+                None => {
+                    let amount = UiAmount::parse_base_units(&entry.amount, SOL_DECIMALS)
+                        .map_err(|err| anyhow!("payout {}: {}", index, err))?;
+                    PlannedPayout {
+                        index,
+                        amount,
+                        setup: None,
+                        transfer: system_instruction::transfer(&payer, &entry.recipient, amount),
+                        units: SOL_TRANSFER_UNITS,
+                    }
+                }
+                Some(mint) => {
+                    let info = self.mints.get(client, mint).await?;
+                    let program_id = info.program.id();
+                    let decimals = info.state.decimals;
+                    let amount = UiAmount::parse_base_units(&entry.amount, decimals)
+                        .map_err(|err| anyhow!("payout {}: {}", index, err))?;
+                    let source = info.program.associated_token_address(&payer, mint);
+                    let destination = info
+                        .program
+                        .associated_token_address(&entry.recipient, mint);
+                    let setup = (!existing.contains(&destination)).then(|| {
+                        create_associated_token_account_idempotent(
+                            &payer,
+                            &entry.recipient,
+                            mint,
+                            &program_id,
+                        )
+                    });
+                    let transfer = transfer_checked(
+                        &program_id,  // program id
+                        &source,      // source
+                        mint,         // mint
+                        &destination, // destination
+                        &payer,       // owner of source
+                        &[&payer],    // signers
+                        amount,       // amount
+                        decimals,     // decimals
+                    )?;
+                    PlannedPayout {
+                        index,
+                        amount,
+                        setup,
+                        transfer,
+                        units: TOKEN_TRANSFER_UNITS,
+                    }
+                }
+            };
+            planned.push(payout);
+        }
+        Ok(planned)
+    }
+
+    /// Pay `entries`, resuming from the journal at `journal_path` if it exists
+    ///
+    /// Payouts the journal records as confirmed are not sent again, failed ones are. Those
+    /// without a definitive outcome only with `PayoutConfig::resend_unknown`.
+    pub async fn run(&self, entries: &[PayoutEntry], journal_path: &Path) -> Result<PayoutReport> {
+        let keys: Vec<String> = entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| entry.key(index))
+            .collect();
+        let (journal, mut states) = Journal::open(journal_path)?;
+        let journal = Arc::new(Mutex::new(journal));
+        if let Some(stale) = states.keys().find(|key| !keys.contains(key)) {
+            bail!(
+                "journal {} has payout {} that isn't in the input",
+                journal_path.display(),
+                stale
+            );
+        }
+
+        let todo: Vec<(usize, PayoutEntry)> = entries
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| match states.get(&keys[*index]) {
+                None | Some(JournalState::Failed { .. }) => true,
+                Some(JournalState::Pending | JournalState::Unknown { .. }) => {
+                    self.config.resend_unknown
+                }
+                Some(JournalState::Confirmed { .. }) => false,
+            })
+            .map(|(index, entry)| (index, entry.clone()))
+            .collect();
+        let planned = self.plan(&todo).await?;
+        let batches = pack(
+            &self.payer.pubkey(),
+            &planned,
+            self.config.max_units_per_transaction,
+        )?;
+        println!(
+            "paying {} of {} payouts in {} transactions",
+            todo.len(),
+            entries.len(),
+            batches.len()
+        );
+
+        let permits = Arc::new(Semaphore::new(self.config.max_in_flight));
+        let mut tasks = JoinSet::new();
+        // dropping the JoinSet would abort sends in flight, so errors wait until all are done
+        let mut first_error = None;
+        for batch in batches {
+            let permit = match permits.clone().acquire_owned().await {
+                Ok(permit) => permit,
+                Err(err) => {
+                    first_error = Some(err.into());
+                    break;
+                }
+            };
+            let batch_keys: Vec<String> = batch
+                .payouts
+                .iter()
+                .map(|index| keys[*index].clone())
+                .collect();
+            if let Err(err) = journal
+                .lock()
+                .unwrap()
+                .record(&batch_keys, JournalState::Pending)
+            {
+                first_error = Some(err);
+                break;
+            }
+
+            let sender = self.sender.clone();
+            let payer = self.payer.clone();
+            let journal = journal.clone();
+            tasks.spawn(async move {
+                let result = sender
+                    .send(&batch.instructions, &payer.pubkey(), &[payer.as_ref()])
+                    .await;
+                drop(permit);
+                let state = match result {
+                    Ok(outcome) => JournalState::Confirmed {
+                        signature: outcome.signature.to_string(),
+                        slot: outcome.slot,
+                    },
+                    Err(err) if err.is_definitive() => JournalState::Failed {
+                        error: err.to_string(),
+                    },
+                    Err(err) => JournalState::Unknown {
+                        error: err.to_string(),
+                        signature: err
+                            .unresolved_signature()
+                            .map(|signature| signature.to_string()),
+                    },
+                };
+                journal.lock().unwrap().record(&batch_keys, state.clone())?;
+                Ok::<_, anyhow::Error>((batch_keys, state))
+            });
+            tokio::time::sleep(self.config.send_interval).await;
+        }
+        while let Some(result) = tasks.join_next().await {
+            match result {
+                Ok(Ok((batch_keys, state))) => {
+                    for key in batch_keys {
+                        states.insert(key, state.clone());
+                    }
+                }
+                Ok(Err(err)) => {
+                    first_error.get_or_insert(err);
+                }
+                Err(err) => {
+                    first_error.get_or_insert(err.into());
+                }
+            }
+        }
+        if let Some(err) = first_error {
+            return Err(err);
+        }
+
+        let amounts: HashMap<usize, u64> = planned
+            .iter()
+            .map(|payout| (payout.index, payout.amount))
+            .collect();
+        self.report(entries, &keys, &states, &amounts).await
+    }
+
+    async fn report(
+        &self,
+        entries: &[PayoutEntry],
+        keys: &[String],
+        states: &HashMap<String, JournalState>,
+        planned_amounts: &HashMap<usize, u64>,
+    ) -> Result<PayoutReport> {
+        let mut rows = vec![];
+        let mut totals: BTreeMap<Option<Pubkey>, AssetTotals> = BTreeMap::new();
+        for (index, entry) in entries.iter().enumerate() {
+            let status = match states.get(&keys[index]) {
+                Some(JournalState::Confirmed { signature, slot }) => PayoutStatus::Paid {
+                    signature: Signature::from_str(signature)?,
+                    slot: *slot,
+                },
+                Some(JournalState::Failed { error }) => PayoutStatus::Failed(error.clone()),
+                Some(JournalState::Unknown { error, signature }) => PayoutStatus::Unknown {
+                    signature: signature.as_deref().map(Signature::from_str).transpose()?,
+                    error: error.clone(),
+                },
+                Some(JournalState::Pending) => PayoutStatus::Unknown {
+                    signature: None,
+                    error: "sent before a crash".to_string(),
+                },
+                None => PayoutStatus::Failed("not sent".to_string()),
+            };
+            let amount = match planned_amounts.get(&index) {
+                Some(amount) => *amount,
+                None => {
+                    let decimals = match &entry.mint {
+                        Some(mint) => self.mints.decimals(self.sender.client(), mint).await?,
+                        None => SOL_DECIMALS,
+                    };
+                    UiAmount::parse_base_units(&entry.amount, decimals)?
+                }
+            } as u128;
+
+            let asset = totals.entry(entry.mint).or_default();
+            asset.requested += amount;
+            match &status {
+                PayoutStatus::Paid { .. } => asset.paid += amount,
+                PayoutStatus::Failed(_) => asset.failed += amount,
+                PayoutStatus::Unknown { .. } => asset.unknown += amount,
+            }
+            rows.push(ReportRow {
+                index,
+                recipient: entry.recipient,
+                mint: entry.mint,
+                amount: entry.amount.clone(),
+                status,
+            });
+        }
+        Ok(PayoutReport { rows, totals })
+    }
+}
+
+/// Journal path next to the payout list, `payroll.csv` journals into `payroll.csv.journal`
+pub fn journal_path_for(input: &Path) -> PathBuf {
+    let mut path = input.as_os_str().to_owned();
+    path.push(".journal");
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common;
+    use crate::config::ClusterConfig;
+    use solana_sdk::native_token::LAMPORTS_PER_SOL;
+
+    fn sol_payout(payer: &Pubkey, index: usize) -> PlannedPayout {
+        PlannedPayout {
+            index,
+            amount: 1,
+            setup: None,
+            transfer: system_instruction::transfer(payer, &Pubkey::new_unique(), 1),
+            units: SOL_TRANSFER_UNITS,
+        }
+    }
+
+    #[test]
+    fn test_parse_inputs() {
+        let recipient = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let csv = format!(
+            "recipient,amount,mint\n# comment\n{recipient},1.5\n\n{recipient}, 2 ,{mint}\n"
+        );
+        let entries = parse_csv(&csv).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].mint, None);
+        assert_eq!(entries[1].amount, "2");
+        assert_eq!(entries[1].mint, Some(mint));
+        assert!(parse_csv("not-a-key,1").is_err());
+        assert!(parse_csv(&format!("{recipient}")).is_err());
+
+        let json = format!(
+            r#"[{{"recipient": "{recipient}", "amount": 0.25}},
+                {{"recipient": "{recipient}", "amount": "3", "mint": "{mint}"}}]"#
+        );
+        let from_json = parse_json(&json).unwrap();
+        assert_eq!(from_json[0].amount, "0.25");
+        assert_eq!(from_json[1].amount, "3");
+        assert_eq!(from_json[1].mint, Some(mint));
+    }
+
+    #[test]
+    fn test_pack() {
+        let payer = Pubkey::new_unique();
+        let payouts: Vec<PlannedPayout> = (0..50).map(|index| sol_payout(&payer, index)).collect();
+        let batches = pack(&payer, &payouts, 1_200_000).unwrap();
+        assert!(batches.len() > 1);
+        assert_eq!(
+            batches.iter().map(|b| b.payouts.len()).sum::<usize>(),
+            payouts.len()
+        );
+        for batch in &batches {
+            let budget = ComputeBudget {
+                unit_limit: 1,
+                unit_price: 1,
+                units_consumed: 0,
+            };
+            assert!(
+                transaction_size(&payer, &budget.apply(&batch.instructions)) <= PACKET_DATA_SIZE
+            );
+        }
+        // input order is kept
+        assert_eq!(batches[1].payouts[0], batches[0].payouts.len());
+
+        // the unit limit splits as well
+        let batches = pack(&payer, &payouts[..4], SOL_TRANSFER_UNITS * 2).unwrap();
+        assert_eq!(batches.len(), 2);
+        assert!(pack(&payer, &payouts[..1], 1).is_err());
+
+        // a shared account create is added once
+        let create = create_associated_token_account_idempotent(
+            &payer,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &spl_token::id(),
+        );
+        let mut twice = vec![sol_payout(&payer, 0), sol_payout(&payer, 1)];
+        for payout in &mut twice {
+            payout.setup = Some(create.clone());
+        }
+        let batches = pack(&payer, &twice, 1_200_000).unwrap();
+        assert_eq!(batches[0].instructions.len(), 3);
+    }
+
+    #[test]
+    fn test_journal() {
+        let path = std::env::temp_dir().join(format!("payout-{}.journal", Pubkey::new_unique()));
+        let keys = ["0:a:SOL:1".to_string(), "1:b:SOL:2".to_string()];
+        {
+            let (mut journal, states) = Journal::open(&path).unwrap();
+            assert!(states.is_empty());
+            journal.record(&keys, JournalState::Pending).unwrap();
+            journal
+                .record(
+                    &keys[..1],
+                    JournalState::Failed {
+                        error: "boom".to_string(),
+                    },
+                )
+                .unwrap();
+            journal
+                .record(
+                    &keys[1..],
+                    JournalState::Unknown {
+                        error: "expired".to_string(),
+                        signature: Some(Signature::default().to_string()),
+                    },
+                )
+                .unwrap();
+        }
+        // a torn line from a crash is ignored
+        fs::write(
+            &path,
+            fs::read_to_string(&path).unwrap() + "{\"keys\": [\"1:b",
+        )
+        .unwrap();
+
+        let (_, states) = Journal::open(&path).unwrap();
+        assert!(matches!(states[&keys[0]], JournalState::Failed { .. }));
+        assert!(matches!(
+            &states[&keys[1]],
+            JournalState::Unknown {
+                signature: Some(_),
+                ..
+            }
+        ));
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            journal_path_for(Path::new("/tmp/payroll.csv")),
+            Path::new("/tmp/payroll.csv.journal")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sol_payouts() -> Result<()> {
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let payer = Arc::new(Keypair::new());
+        common::airdrop(&client, &payer, LAMPORTS_PER_SOL).await?;
+        let engine = PayoutEngine::new(
+            TransactionSender::new(client.clone()),
+            payer.clone(),
+            PayoutConfig::default(),
+        );
+
+        let entries: Vec<PayoutEntry> = (0..30)
+            .map(|_| PayoutEntry {
+                recipient: Pubkey::new_unique(),
+                amount: "0.001".to_string(),
+                mint: None,
+            })
+            .collect();
+        let journal = std::env::temp_dir().join(format!("payout-{}.journal", payer.pubkey()));
+        let report = engine.run(&entries, &journal).await?;
+        assert!(report.is_complete());
+        assert_eq!(
+            report.totals[&None].paid,
+            30 * LAMPORTS_PER_SOL as u128 / 1_000
+        );
+        assert_eq!(
+            client.get_balance(&entries[29].recipient).await?,
+            LAMPORTS_PER_SOL / 1_000
+        );
+
+        // resuming sends nothing again
+        let report = engine.run(&entries, &journal).await?;
+        assert!(report.is_complete());
+        fs::remove_file(&journal)?;
+        Ok(())
+    }
+}
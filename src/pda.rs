@@ -0,0 +1,301 @@
+use anyhow::{Result, bail};
+use borsh::BorshDeserialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    program_error::ProgramError,
+    pubkey::{MAX_SEED_LEN, MAX_SEEDS, Pubkey},
+};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use thiserror::Error;
+
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum PdaError {
+    /// The bump takes one of the `MAX_SEEDS` slots
+    #[error("{0} seeds, at most {max} fit next to the bump", max = MAX_SEEDS - 1)]
+    TooManySeeds(usize),
+    #[error("seed {index} is {len} bytes, at most {MAX_SEED_LEN} are allowed")]
+    SeedTooLong { index: usize, len: usize },
+    #[error("no bump puts the address off the curve")]
+    NoViableBump,
+    #[error("seeds derive {expected}, not {actual}")]
+    AddressMismatch { expected: Pubkey, actual: Pubkey },
+    #[error("bump {bump} is valid but not the canonical bump {canonical}")]
+    NonCanonicalBump { bump: u8, canonical: u8 },
+}
+
+impl From<PdaError> for ProgramError {
+    fn from(_: PdaError) -> Self {
+        ProgramError::InvalidSeeds
+    }
+}
+
+/// Seeds of a program derived address, built from typed parts
+///
+/// Integers are encoded little-endian like borsh does, which is what on-chain programs
+/// usually pass as seeds.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Seeds {
+    seeds: Vec<Vec<u8>>,
+}
+
+impl Seeds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bytes(mut self, bytes: &[u8]) -> Self {
+        self.seeds.push(bytes.to_vec());
+        self
+    }
+
+    pub fn str(self, seed: &str) -> Self {
+        self.bytes(seed.as_bytes())
+    }
+
+    pub fn pubkey(self, pubkey: &Pubkey) -> Self {
+        self.bytes(pubkey.as_ref())
+    }
+
+    pub fn u8(self, value: u8) -> Self {
+        self.bytes(&[value])
+    }
+
+    pub fn u16(self, value: u16) -> Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn u32(self, value: u32) -> Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn u64(self, value: u64) -> Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn i64(self, value: i64) -> Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    pub fn len(&self) -> usize {
+        self.seeds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seeds.is_empty()
+    }
+
+    /// What `find_program_address` and friends take
+    pub fn as_slices(&self) -> Vec<&[u8]> {
+        self.seeds.iter().map(Vec::as_slice).collect()
+    }
+
+    /// The seeds followed by the bump, for `invoke_signed`
+    pub fn signer_seeds<'a>(&'a self, bump: &'a [u8; 1]) -> Vec<&'a [u8]> {
+        let mut seeds = self.as_slices();
+        seeds.push(bump);
+        seeds
+    }
+
+    /// Check the limits `find_program_address` panics on
+    pub fn validate(&self) -> Result<(), PdaError> {
+        if self.seeds.len() >= MAX_SEEDS {
+            return Err(PdaError::TooManySeeds(self.seeds.len()));
+        }
+        match self.seeds.iter().position(|seed| seed.len() > MAX_SEED_LEN) {
+            Some(index) => Err(PdaError::SeedTooLong {
+                index,
+                len: self.seeds[index].len(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// The address with the canonical bump, the highest one that is off the curve
+    pub fn find(&self, program_id: &Pubkey) -> Result<Pda, PdaError> {
+        self.validate()?;
+        let (address, bump) = Pubkey::try_find_program_address(&self.as_slices(), program_id)
+            .ok_or(PdaError::NoViableBump)?;
+        Ok(Pda { address, bump })
+    }
+
+    /// The address for a known bump, cheap compared to `find`
+    pub fn create(&self, program_id: &Pubkey, bump: u8) -> Result<Pubkey, PdaError> {
+        self.validate()?;
+        Pubkey::create_program_address(&self.signer_seeds(&[bump]), program_id)
+            .map_err(|_| PdaError::NoViableBump)
+    }
+}
+
+/// A program derived address and its bump
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pda {
+    pub address: Pubkey,
+    pub bump: u8,
+}
+
+/// Check that `address` derives from `seeds` and `bump`, and that `bump` is canonical
+///
+/// A program accepting any valid bump lets callers pick between up to 256 addresses for
+/// the same seeds.
+pub fn verify_pda(
+    address: &Pubkey,
+    seeds: &Seeds,
+    bump: u8,
+    program_id: &Pubkey,
+) -> Result<(), PdaError> {
+    let expected = seeds.create(program_id, bump)?;
+    if expected != *address {
+        return Err(PdaError::AddressMismatch {
+            expected,
+            actual: *address,
+        });
+    }
+    let canonical = seeds.find(program_id)?.bump;
+    if bump != canonical {
+        return Err(PdaError::NonCanonicalBump { bump, canonical });
+    }
+    Ok(())
+}
+
+/// Caches canonical bumps so `find_program_address` runs once per seeds and program
+///
+/// A miss can cost up to 255 hashes and curve checks, a hit is a map lookup.
+#[derive(Debug, Default)]
+pub struct BumpCache {
+    pdas: RwLock<HashMap<(Pubkey, Seeds), Pda>>,
+}
+
+impl BumpCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn find(&self, seeds: &Seeds, program_id: &Pubkey) -> Result<Pda, PdaError> {
+        let key = (*program_id, seeds.clone());
+        if let Some(pda) = self.pdas.read().unwrap().get(&key) {
+            return Ok(*pda);
+        }
+        let pda = seeds.find(program_id)?;
+        self.pdas.write().unwrap().insert(key, pda);
+        Ok(pda)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pdas.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pdas.read().unwrap().is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.pdas.write().unwrap().clear();
+    }
+}
+
+/// A fetched program derived account with its decoded state
+#[derive(Clone, Debug, PartialEq)]
+pub struct PdaAccount<T> {
+    pub pda: Pda,
+    pub lamports: u64,
+    pub state: T,
+}
+
+/// Derive the address and fetch the borsh encoded state stored there
+///
+/// `None` while the account doesn't exist. Trailing bytes after the state are ignored, as
+/// programs often allocate more space than the current state needs.
+pub async fn fetch_pda_account<T: BorshDeserialize>(
+    client: &RpcClient,
+    seeds: &Seeds,
+    program_id: &Pubkey,
+) -> Result<Option<PdaAccount<T>>> {
+    let pda = seeds.find(program_id)?;
+    let Some(account) = client
+        .get_account_with_commitment(&pda.address, client.commitment())
+        .await?
+        .value
+    else {
+        return Ok(None);
+    };
+    if account.owner != *program_id {
+        bail!(
+            "{} is owned by {}, not {}",
+            pda.address,
+            account.owner,
+            program_id
+        );
+    }
+    let state = T::deserialize(&mut account.data.as_slice())?;
+    Ok(Some(PdaAccount {
+        pda,
+        lamports: account.lamports,
+        state,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeds_and_verification() {
+        let program_id = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let seeds = Seeds::new().str("escrow").pubkey(&owner).u64(7);
+        assert_eq!(
+            seeds.as_slices(),
+            [&b"escrow"[..], owner.as_ref(), &7u64.to_le_bytes()]
+        );
+
+        let pda = seeds.find(&program_id).unwrap();
+        let (address, bump) = Pubkey::find_program_address(&seeds.as_slices(), &program_id);
+        assert_eq!(pda, Pda { address, bump });
+        assert_eq!(seeds.create(&program_id, pda.bump).unwrap(), pda.address);
+        assert!(verify_pda(&pda.address, &seeds, pda.bump, &program_id).is_ok());
+
+        // another valid bump derives another address, which isn't canonical
+        let (bump, address) = (0..pda.bump)
+            .rev()
+            .find_map(|bump| Some((bump, seeds.create(&program_id, bump).ok()?)))
+            .unwrap();
+        assert_eq!(
+            verify_pda(&address, &seeds, bump, &program_id),
+            Err(PdaError::NonCanonicalBump {
+                bump,
+                canonical: pda.bump
+            })
+        );
+        assert!(matches!(
+            verify_pda(&owner, &seeds, pda.bump, &program_id),
+            Err(PdaError::AddressMismatch { .. })
+        ));
+
+        assert_eq!(
+            Seeds::new().bytes(&[0; 33]).find(&program_id),
+            Err(PdaError::SeedTooLong { index: 0, len: 33 })
+        );
+        let too_many = (0..16).fold(Seeds::new(), |seeds, i| seeds.u8(i));
+        assert_eq!(too_many.find(&program_id), Err(PdaError::TooManySeeds(16)));
+    }
+
+    #[test]
+    fn test_bump_cache() {
+        let program_id = Pubkey::new_unique();
+        let cache = BumpCache::new();
+        let seeds = Seeds::new().str("vault").u32(1);
+
+        let pda = cache.find(&seeds, &program_id).unwrap();
+        assert_eq!(cache.find(&seeds, &program_id).unwrap(), pda);
+        assert_eq!(cache.len(), 1);
+
+        cache.find(&seeds, &Pubkey::new_unique()).unwrap();
+        cache
+            .find(&Seeds::new().str("vault").u32(2), &program_id)
+            .unwrap();
+        assert_eq!(cache.len(), 3);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,819 @@
+use crate::budget::{
+    BudgetError, ComputeBudgetConfig, estimate_compute_budget, has_compute_budget,
+};
+use crate::lookup_table::{compile_message, sign_message};
+use crate::memo::{Memo, attach_memo, memo_positions};
+use crate::nonce::{NonceAccount, nonce_info, nonce_instructions};
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    nonblocking::rpc_client::RpcClient,
+    nonce_utils,
+    rpc_config::RpcSendTransactionConfig,
+    rpc_request::{RpcError, RpcResponseErrorData},
+};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    instruction::{Instruction, InstructionError},
+    message::{AddressLookupTableAccount, CompileError},
+    pubkey::Pubkey,
+    signature::Signature,
+    signer::{SignerError, signers::Signers},
+    transaction::{TransactionError, VersionedTransaction},
+};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// How often and how patiently `TransactionSender` retries
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Sends with a fresh blockhash, expired blockhashes and transport errors both count;
+    /// also the failed status polls in a row given up on while confirming. At least one
+    /// send is made, 0 counts as 1.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Pause between signature status polls while waiting for confirmation
+    pub poll_interval: Duration,
+    /// How long a durable nonce transaction may go without status before it is sent again,
+    /// it doesn't expire by block height
+    pub nonce_timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+            poll_interval: Duration::from_millis(500),
+            nonce_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before retry number `retry` (starting at 1) after a transport error
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SendError {
+    /// The transaction was executed (or failed preflight simulation) and returned an error
+    #[error("transaction {signature} failed: {error}")]
+    Failed {
+        signature: Signature,
+        error: TransactionError,
+        /// Program logs, only known when preflight simulation caught the failure
+        logs: Vec<String>,
+    },
+    /// An instruction the sender put in front of the given ones failed, e.g. a compute
+    /// unit limit or a nonce advance; `error` indexes the transaction as sent
+    #[error("instruction added by the sender failed: {error}")]
+    AddedInstructionFailed {
+        /// `None` when simulating the compute budget caught the failure
+        signature: Option<Signature>,
+        error: TransactionError,
+        logs: Vec<String>,
+    },
+    #[error("transaction not confirmed after {attempts} attempts, last signature {signature}")]
+    Expired { attempts: u32, signature: Signature },
+    #[error("RPC transport error after {attempts} attempts: {source}")]
+    Transport {
+        attempts: u32,
+        /// The transaction sent when the RPC failed, it may still land
+        signature: Option<Signature>,
+        source: ClientError,
+    },
+    #[error("failed to sign transaction: {0}")]
+    Signing(#[from] SignerError),
+    #[error("failed to size the compute budget: {0}")]
+    Budget(#[from] BudgetError),
+    #[error("failed to compile message: {0}")]
+    Compile(#[from] CompileError),
+    #[error("unusable nonce account: {0}")]
+    Nonce(#[from] nonce_utils::Error),
+}
+
+impl SendError {
+    /// Whether the transaction surely didn't execute and can be sent again
+    ///
+    /// After transport errors and expired blockhashes the outcome is unknown, the
+    /// transaction may have landed on a node the RPC didn't hear from yet.
+    pub fn is_definitive(&self) -> bool {
+        matches!(
+            self,
+            SendError::Failed { .. }
+                | SendError::AddedInstructionFailed { .. }
+                | SendError::Budget(_)
+                | SendError::Compile(_)
+                | SendError::Signing(_)
+        )
+    }
+
+    /// Signature to look up on chain when the outcome isn't definitive
+    pub fn unresolved_signature(&self) -> Option<Signature> {
+        match self {
+            SendError::Expired { signature, .. } => Some(*signature),
+            SendError::Transport { signature, .. } => *signature,
+            _ => None,
+        }
+    }
+
+    /// Index and error of the failing instruction, if an instruction failed
+    ///
+    /// The index points into the instructions given to `TransactionSender::send`, not
+    /// counting the compute budget instructions the sender adds. Failures of those are
+    /// `AddedInstructionFailed` and have no index here.
+    pub fn instruction_error(&self) -> Option<(u8, &InstructionError)> {
+        if let SendError::AddedInstructionFailed { .. } = self {
+            return None;
+        }
+        match self.transaction_error()? {
+            TransactionError::InstructionError(index, error) => Some((*index, error)),
+            _ => None,
+        }
+    }
+
+    pub fn transaction_error(&self) -> Option<&TransactionError> {
+        match self {
+            SendError::Failed { error, .. }
+            | SendError::AddedInstructionFailed { error, .. }
+            | SendError::Budget(BudgetError::SimulationFailed { error, .. }) => Some(error),
+            _ => None,
+        }
+    }
+
+    // undo the shift caused by `offset` prepended compute budget and nonce instructions,
+    // a failure in one of those doesn't belong to any of the caller's instructions
+    fn unshift_instruction_index(self, offset: u8) -> Self {
+        match self {
+            SendError::Failed {
+                signature,
+                error: error @ TransactionError::InstructionError(index, _),
+                logs,
+            } if index < offset => SendError::AddedInstructionFailed {
+                signature: Some(signature),
+                error,
+                logs,
+            },
+            SendError::Budget(BudgetError::SimulationFailed {
+                error: error @ TransactionError::InstructionError(index, _),
+                logs,
+            }) if index < offset => SendError::AddedInstructionFailed {
+                signature: None,
+                error,
+                logs,
+            },
+            err => err.map_instruction_index(|index| index - offset),
+        }
+    }
+
+    // undo the shift caused by memos inserted at `positions`, a failing memo keeps the index
+    // of the instruction it precedes
+    fn unshift_memo_index(self, positions: &[usize]) -> Self {
+        self.map_instruction_index(|index| {
+            // memo number `k` sits at `position + k` once inserted
+            let before = positions
+                .iter()
+                .enumerate()
+                .filter(|(k, position)| *position + k < index as usize)
+                .count();
+            index - before as u8
+        })
+    }
+
+    fn map_instruction_index(mut self, f: impl FnOnce(u8) -> u8) -> Self {
+        if let SendError::Failed { error, .. }
+        | SendError::Budget(BudgetError::SimulationFailed { error, .. }) = &mut self
+            && let TransactionError::InstructionError(index, _) = error
+        {
+            *index = f(*index);
+        }
+        self
+    }
+}
+
+/// A confirmed transaction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SendOutcome {
+    pub signature: Signature,
+    pub slot: u64,
+    /// 1 unless the blockhash expired or the RPC failed along the way
+    pub attempts: u32,
+    /// The stored nonce for durable nonce transactions
+    pub blockhash: Hash,
+    /// `u64::MAX` for durable nonce transactions, they don't expire by block height
+    pub last_valid_block_height: u64,
+    pub elapsed: Duration,
+}
+
+/// What went wrong with one attempt and whether another one may help
+enum AttemptError {
+    Expired(Signature),
+    Transient(ClientError),
+    Fatal(SendError),
+}
+
+/// Send and confirm transactions, re-signing with a fresh blockhash when the previous one
+/// expires and backing off on transient RPC errors
+///
+/// Unless the instructions already set one, a compute budget sized by simulation is put in
+/// front of them; see `crate::budget`. With lookup tables set, v0 transactions are sent.
+/// With a durable nonce set, the nonce is advanced first and used in place of the blockhash.
+/// With a memo set, it is attached to every transaction; see `crate::memo::attach_memo`.
+#[derive(Clone)]
+pub struct TransactionSender {
+    client: Arc<RpcClient>,
+    policy: RetryPolicy,
+    commitment: CommitmentConfig,
+    compute_budget: Option<ComputeBudgetConfig>,
+    lookup_tables: Vec<AddressLookupTableAccount>,
+    durable_nonce: Option<NonceAccount>,
+    memo: Option<Memo>,
+}
+
+impl TransactionSender {
+    pub fn new(client: Arc<RpcClient>) -> Self {
+        let commitment = client.commitment();
+        Self {
+            client,
+            policy: RetryPolicy::default(),
+            commitment,
+            compute_budget: Some(ComputeBudgetConfig::default()),
+            lookup_tables: vec![],
+            durable_nonce: None,
+            memo: None,
+        }
+    }
+
+    /// Attach `memo` to every send, its signers have to be among the signers of every send
+    pub fn with_memo(mut self, memo: Option<Memo>) -> Self {
+        self.memo = memo;
+        self
+    }
+
+    pub fn memo(&self) -> Option<&Memo> {
+        self.memo.as_ref()
+    }
+
+    /// Use the nonce stored in `durable_nonce` instead of a recent blockhash, the nonce
+    /// authority then has to be among the signers of every send
+    pub fn with_durable_nonce(mut self, durable_nonce: Option<NonceAccount>) -> Self {
+        self.durable_nonce = durable_nonce;
+        self
+    }
+
+    /// Compile v0 messages against `lookup_tables`, legacy ones if empty
+    pub fn with_lookup_tables(mut self, lookup_tables: Vec<AddressLookupTableAccount>) -> Self {
+        self.lookup_tables = lookup_tables;
+        self
+    }
+
+    /// `None` sends the instructions as they are
+    pub fn with_compute_budget(mut self, compute_budget: Option<ComputeBudgetConfig>) -> Self {
+        self.compute_budget = compute_budget;
+        self
+    }
+
+    pub fn with_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_commitment(mut self, commitment: CommitmentConfig) -> Self {
+        self.commitment = commitment;
+        self
+    }
+
+    pub fn client(&self) -> &Arc<RpcClient> {
+        &self.client
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// Build a transaction paid by `payer`, sign it with `signers` and wait for confirmation
+    pub async fn send<S: Signers + ?Sized>(
+        &self,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+        signers: &S,
+    ) -> Result<SendOutcome, SendError> {
+        let Some(memo) = &self.memo else {
+            return self.send_prepared(instructions, payer, signers).await;
+        };
+        let positions = memo_positions(instructions);
+        self.send_prepared(&attach_memo(instructions, memo), payer, signers)
+            .await
+            .map_err(|err| err.unshift_memo_index(&positions))
+    }
+
+    async fn send_prepared<S: Signers + ?Sized>(
+        &self,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+        signers: &S,
+    ) -> Result<SendOutcome, SendError> {
+        let (mut instructions, mut offset) = match self.budgeted(instructions, payer).await {
+            Ok(budgeted) => budgeted,
+            Err(err) => return Err(SendError::from(err).unshift_instruction_index(2)),
+        };
+        if let Some(nonce) = &self.durable_nonce {
+            instructions = nonce_instructions(nonce, &instructions);
+            offset += 1;
+        }
+        self.send_budgeted(&instructions, payer, signers)
+            .await
+            .map_err(|err| err.unshift_instruction_index(offset))
+    }
+
+    /// `instructions` with the compute budget in front, and how many instructions were added
+    async fn budgeted(
+        &self,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+    ) -> Result<(Vec<Instruction>, u8), BudgetError> {
+        let Some(config) = self.compute_budget.as_ref() else {
+            return Ok((instructions.to_vec(), 0));
+        };
+        if has_compute_budget(instructions) {
+            return Ok((instructions.to_vec(), 0));
+        }
+        let budget = estimate_compute_budget(
+            &self.client,
+            instructions,
+            payer,
+            &self.lookup_tables,
+            config,
+        )
+        .await?;
+        let budgeted = budget.apply(instructions);
+        let offset = (budgeted.len() - instructions.len()) as u8;
+        Ok((budgeted, offset))
+    }
+
+    async fn send_budgeted<S: Signers + ?Sized>(
+        &self,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+        signers: &S,
+    ) -> Result<SendOutcome, SendError> {
+        let started = Instant::now();
+        let max_attempts = self.policy.max_attempts.max(1);
+        let mut transport_retries = 0;
+
+        for attempt in 1.. {
+            let result = self.attempt(instructions, payer, signers).await;
+            match result {
+                Ok((signature, slot, blockhash, last_valid_block_height)) => {
+                    return Ok(SendOutcome {
+                        signature,
+                        slot,
+                        attempts: attempt,
+                        blockhash,
+                        last_valid_block_height,
+                        elapsed: started.elapsed(),
+                    });
+                }
+                Err(AttemptError::Fatal(err)) => return Err(err),
+                Err(AttemptError::Expired(signature)) => {
+                    if attempt == max_attempts {
+                        return Err(SendError::Expired {
+                            attempts: attempt,
+                            signature,
+                        });
+                    }
+                    println!("blockhash of {} expired, re-signing", signature);
+                }
+                Err(AttemptError::Transient(err)) => {
+                    if attempt == max_attempts {
+                        return Err(SendError::Transport {
+                            attempts: attempt,
+                            signature: None,
+                            source: err,
+                        });
+                    }
+                    transport_retries += 1;
+                    let backoff = self.policy.backoff(transport_retries);
+                    println!("RPC error: {}, retrying in {:?}", err, backoff);
+                    tokio::time::sleep(backoff).await;
+                }
+            }
+        }
+        unreachable!("the attempts loop returns at the last attempt")
+    }
+
+    async fn attempt<S: Signers + ?Sized>(
+        &self,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+        signers: &S,
+    ) -> Result<(Signature, u64, Hash, u64), AttemptError> {
+        let (blockhash, last_valid_block_height) = match &self.durable_nonce {
+            Some(nonce) => (self.stored_nonce(&nonce.address).await?, u64::MAX),
+            None => self
+                .client
+                .get_latest_blockhash_with_commitment(self.commitment)
+                .await
+                .map_err(AttemptError::Transient)?,
+        };
+
+        let message = compile_message(payer, instructions, &self.lookup_tables, blockhash)
+            .map_err(|err| AttemptError::Fatal(err.into()))?;
+        let transaction =
+            sign_message(message, signers).map_err(|err| AttemptError::Fatal(err.into()))?;
+        let signature = transaction.signatures[0];
+
+        let config = RpcSendTransactionConfig {
+            preflight_commitment: Some(self.commitment.commitment),
+            ..RpcSendTransactionConfig::default()
+        };
+        // the node may have taken the transaction despite a transport error, so it is
+        // rebroadcast and confirmed rather than re-signed
+        let delivered = match self
+            .client
+            .send_transaction_with_config(&transaction, config)
+            .await
+        {
+            Ok(_) => true,
+            Err(err) => match classify(signature, err) {
+                AttemptError::Transient(err) => {
+                    println!("RPC error sending {}: {}, rebroadcasting", signature, err);
+                    false
+                }
+                err => return Err(err),
+            },
+        };
+
+        let slot = self
+            .confirm(&transaction, delivered, blockhash, last_valid_block_height)
+            .await?;
+        Ok((signature, slot, blockhash, last_valid_block_height))
+    }
+
+    async fn rebroadcast(&self, transaction: &VersionedTransaction) -> bool {
+        let config = RpcSendTransactionConfig {
+            skip_preflight: true,
+            ..RpcSendTransactionConfig::default()
+        };
+        self.client
+            .send_transaction_with_config(transaction, config)
+            .await
+            .is_ok()
+    }
+
+    async fn stored_nonce(&self, address: &Pubkey) -> Result<Hash, AttemptError> {
+        let account = self
+            .client
+            .get_account_with_commitment(address, self.commitment)
+            .await
+            .map_err(AttemptError::Transient)?
+            .value
+            .ok_or_else(|| {
+                let err = nonce_utils::Error::Client(format!("{} not found", address));
+                AttemptError::Fatal(err.into())
+            })?;
+        let info = nonce_info(address, &account).map_err(|err| AttemptError::Fatal(err.into()))?;
+        Ok(info.blockhash)
+    }
+
+    /// Poll the signature status until it is confirmed, failed, or its blockhash expired
+    ///
+    /// Only a transaction without status whose blockhash expired counts as expired, a
+    /// durable nonce transaction once the nonce moved on without it or after
+    /// `RetryPolicy::nonce_timeout`. RPC errors are retried here, the transaction may
+    /// still land and must not be re-signed.
+    async fn confirm(
+        &self,
+        transaction: &VersionedTransaction,
+        mut delivered: bool,
+        blockhash: Hash,
+        last_valid_block_height: u64,
+    ) -> Result<u64, AttemptError> {
+        let signature = &transaction.signatures[0];
+        let started = Instant::now();
+        let mut expired = false;
+        let mut failures = 0;
+        loop {
+            if !delivered {
+                delivered = self.rebroadcast(transaction).await;
+            }
+            let statuses = match self.client.get_signature_statuses(&[*signature]).await {
+                Ok(statuses) => statuses,
+                Err(err) => {
+                    self.poll_failed(signature, &mut failures, err).await?;
+                    continue;
+                }
+            };
+            failures = 0;
+            if let Some(Some(status)) = statuses.value.into_iter().next() {
+                if let Some(error) = status.err {
+                    return Err(AttemptError::Fatal(SendError::Failed {
+                        signature: *signature,
+                        error,
+                        logs: vec![],
+                    }));
+                }
+                if status.satisfies_commitment(self.commitment) {
+                    return Ok(status.slot);
+                }
+                // landed, wait for the commitment however long the blockhash is valid
+                expired = false;
+                tokio::time::sleep(self.policy.poll_interval).await;
+                continue;
+            }
+
+            if expired {
+                return Err(AttemptError::Expired(*signature));
+            }
+            match self
+                .blockhash_expired(blockhash, last_valid_block_height)
+                .await
+            {
+                // it may have landed just before, check its status once more
+                Ok(true) => {
+                    expired = true;
+                    continue;
+                }
+                // dropped, re-signing with the same nonce gives the same signature
+                Ok(false)
+                    if self.durable_nonce.is_some()
+                        && started.elapsed() > self.policy.nonce_timeout =>
+                {
+                    return Err(AttemptError::Expired(*signature));
+                }
+                Ok(false) => {}
+                Err(AttemptError::Transient(err)) => {
+                    self.poll_failed(signature, &mut failures, err).await?;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            }
+            tokio::time::sleep(self.policy.poll_interval).await;
+        }
+    }
+
+    /// Whether `blockhash`, or the nonce stored as it, can't land a transaction anymore
+    async fn blockhash_expired(
+        &self,
+        blockhash: Hash,
+        last_valid_block_height: u64,
+    ) -> Result<bool, AttemptError> {
+        if let Some(nonce) = &self.durable_nonce {
+            return Ok(self.stored_nonce(&nonce.address).await? != blockhash);
+        }
+        let block_height = self
+            .client
+            .get_block_height_with_commitment(self.commitment)
+            .await
+            .map_err(AttemptError::Transient)?;
+        Ok(block_height > last_valid_block_height)
+    }
+
+    // back off after a failed poll, giving up after `max_attempts` failures in a row
+    async fn poll_failed(
+        &self,
+        signature: &Signature,
+        failures: &mut u32,
+        err: ClientError,
+    ) -> Result<(), AttemptError> {
+        *failures += 1;
+        if *failures >= self.policy.max_attempts {
+            return Err(AttemptError::Fatal(SendError::Transport {
+                attempts: *failures,
+                signature: Some(*signature),
+                source: err,
+            }));
+        }
+        let backoff = self.policy.backoff(*failures);
+        println!(
+            "RPC error while confirming: {}, retrying in {:?}",
+            err, backoff
+        );
+        tokio::time::sleep(backoff).await;
+        Ok(())
+    }
+}
+
+/// Sort a send error into expired, retryable and final
+fn classify(signature: Signature, err: ClientError) -> AttemptError {
+    if let ClientErrorKind::RpcError(RpcError::RpcResponseError {
+        data: RpcResponseErrorData::SendTransactionPreflightFailure(simulation),
+        ..
+    }) = err.kind()
+        && let Some(error) = &simulation.err
+    {
+        return match error {
+            TransactionError::BlockhashNotFound => AttemptError::Expired(signature),
+            error => AttemptError::Fatal(SendError::Failed {
+                signature,
+                error: error.clone(),
+                logs: simulation.logs.clone().unwrap_or_default(),
+            }),
+        };
+    }
+
+    match err.kind() {
+        ClientErrorKind::TransactionError(TransactionError::BlockhashNotFound) => {
+            AttemptError::Expired(signature)
+        }
+        ClientErrorKind::TransactionError(error) => AttemptError::Fatal(SendError::Failed {
+            signature,
+            error: error.clone(),
+            logs: vec![],
+        }),
+        _ => AttemptError::Transient(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common;
+    use crate::config::ClusterConfig;
+    use solana_client::rpc_response::RpcSimulateTransactionResult;
+    use solana_sdk::{
+        native_token::LAMPORTS_PER_SOL,
+        signature::{Keypair, Signer},
+        system_instruction,
+    };
+
+    fn preflight_failure(error: TransactionError) -> ClientError {
+        let simulation = RpcSimulateTransactionResult {
+            err: Some(error),
+            logs: Some(vec!["Program log: boom".to_string()]),
+            accounts: None,
+            units_consumed: None,
+            return_data: None,
+            inner_instructions: None,
+            replacement_blockhash: None,
+        };
+        ClientError::from(ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            code: -32002,
+            message: "Transaction simulation failed".to_string(),
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(simulation),
+        }))
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_secs(1));
+        assert_eq!(policy.backoff(4), Duration::from_secs(4));
+        assert_eq!(policy.backoff(10), Duration::from_secs(8));
+    }
+
+    #[test]
+    fn test_classify() {
+        let signature = Signature::default();
+
+        let instruction_error =
+            TransactionError::InstructionError(1, InstructionError::Custom(6000));
+        match classify(signature, preflight_failure(instruction_error.clone())) {
+            AttemptError::Fatal(err) => {
+                assert_eq!(
+                    err.instruction_error(),
+                    Some((1, &InstructionError::Custom(6000)))
+                );
+                assert!(
+                    matches!(err, SendError::Failed { logs, .. } if logs == ["Program log: boom"])
+                );
+            }
+            _ => panic!("instruction errors are final"),
+        }
+
+        assert!(matches!(
+            classify(
+                signature,
+                preflight_failure(TransactionError::BlockhashNotFound)
+            ),
+            AttemptError::Expired(_)
+        ));
+        assert!(matches!(
+            classify(
+                signature,
+                ClientErrorKind::Io(std::io::ErrorKind::ConnectionReset.into()).into()
+            ),
+            AttemptError::Transient(_)
+        ));
+    }
+
+    #[test]
+    fn test_unshift_memo_index() {
+        let failed_at = |index| SendError::Failed {
+            signature: Signature::default(),
+            error: TransactionError::InstructionError(index, InstructionError::Custom(1)),
+            logs: vec![],
+        };
+        // memos before the instructions 1 and 3 of four, so at 1 and 4 once inserted
+        let positions = [1, 3];
+        let unshifted: Vec<u8> = (0..6)
+            .map(|index| {
+                let err = failed_at(index).unshift_memo_index(&positions);
+                err.instruction_error().unwrap().0
+            })
+            .collect();
+        assert_eq!(unshifted, [0, 1, 1, 2, 3, 3]);
+    }
+
+    #[test]
+    fn test_unshift_instruction_index() {
+        let failed_at = |index| SendError::Failed {
+            signature: Signature::default(),
+            error: TransactionError::InstructionError(
+                index,
+                InstructionError::InvalidInstructionData,
+            ),
+            logs: vec![],
+        };
+        // compute unit limit and price in front of the caller's instructions
+        let err = failed_at(3).unshift_instruction_index(2);
+        assert_eq!(
+            err.instruction_error(),
+            Some((1, &InstructionError::InvalidInstructionData))
+        );
+
+        // a rejected set_compute_unit_limit is none of the caller's instructions
+        let err = failed_at(0).unshift_instruction_index(2);
+        assert!(matches!(
+            err,
+            SendError::AddedInstructionFailed {
+                signature: Some(_),
+                ..
+            }
+        ));
+        assert_eq!(err.instruction_error(), None);
+        assert!(err.is_definitive());
+
+        let simulated = SendError::Budget(BudgetError::SimulationFailed {
+            error: TransactionError::InstructionError(1, InstructionError::InvalidInstructionData),
+            logs: vec![],
+        })
+        .unshift_instruction_index(2);
+        assert!(matches!(
+            simulated,
+            SendError::AddedInstructionFailed {
+                signature: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_definitive_errors() {
+        let signature = Signature::new_unique();
+        let failed = SendError::Failed {
+            signature,
+            error: TransactionError::AccountNotFound,
+            logs: vec![],
+        };
+        assert!(failed.is_definitive());
+        assert!(SendError::Signing(SignerError::NotEnoughSigners).is_definitive());
+
+        let expired = SendError::Expired {
+            attempts: 5,
+            signature,
+        };
+        assert!(!expired.is_definitive());
+        assert_eq!(expired.unresolved_signature(), Some(signature));
+        let transport = SendError::Transport {
+            attempts: 5,
+            signature: Some(signature),
+            source: ClientErrorKind::Custom("timeout".to_string()).into(),
+        };
+        assert!(!transport.is_definitive());
+        assert_eq!(transport.unresolved_signature(), Some(signature));
+    }
+
+    #[tokio::test]
+    async fn test_send_and_failure() -> anyhow::Result<()> {
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let payer = Keypair::new();
+        common::airdrop(&client, &payer, LAMPORTS_PER_SOL).await?;
+        let sender = TransactionSender::new(client.clone());
+
+        let to = Pubkey::new_unique();
+        let transfer = system_instruction::transfer(&payer.pubkey(), &to, LAMPORTS_PER_SOL / 10);
+        let outcome = sender.send(&[transfer], &payer.pubkey(), &[&payer]).await?;
+        assert_eq!(outcome.attempts, 1);
+        assert_eq!(client.get_balance(&to).await?, LAMPORTS_PER_SOL / 10);
+
+        // more than the payer has, fails in the first instruction
+        let too_much = system_instruction::transfer(&payer.pubkey(), &to, LAMPORTS_PER_SOL * 10);
+        let err = sender
+            .send(&[too_much], &payer.pubkey(), &[&payer])
+            .await
+            .unwrap_err();
+        assert_eq!(err.instruction_error().map(|(index, _)| index), Some(0));
+        Ok(())
+    }
+}
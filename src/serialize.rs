@@ -31,6 +31,8 @@ fn deser_option(data: &[u8]) -> COption<Pubkey> {
     opt
 }
 
+#[cfg(test)]
+#[allow(clippy::init_numbered_fields)]
 mod test {
     use crate::serialize::{Primitive, deser_option};
     use borsh::{BorshDeserialize, BorshSerialize};
@@ -0,0 +1,526 @@
+use anyhow::{Result, anyhow, bail};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::{RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig},
+};
+use solana_sdk::{
+    account::Account, bs58, instruction::Instruction, program_pack::Pack, pubkey::Pubkey,
+    transaction::Transaction, transaction::TransactionError,
+};
+use solana_transaction_status_client_types::{UiInnerInstructions, UiInstruction};
+use std::str::FromStr;
+
+/// How a program invocation ended according to the logs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvocationResult {
+    Success,
+    /// The error as logged, e.g. `custom program error: 0x1`
+    Failed(String),
+    /// No success or failure line, the logs were truncated or the runtime aborted
+    Incomplete,
+}
+
+/// One program invocation rebuilt from the logs, with the invocations it made
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invocation {
+    pub program_id: Pubkey,
+    /// 1 for top-level instructions
+    pub depth: usize,
+    /// `Program log:` messages, without the prefix
+    pub logs: Vec<String>,
+    /// `Program data:` events, base64 decoded
+    pub data: Vec<Vec<u8>>,
+    /// Set with `set_return_data`, as logged by `Program return:`
+    pub return_data: Option<Vec<u8>>,
+    pub units_consumed: Option<u64>,
+    pub result: InvocationResult,
+    pub inner: Vec<Invocation>,
+}
+
+impl Invocation {
+    fn new(program_id: Pubkey, depth: usize) -> Self {
+        Self {
+            program_id,
+            depth,
+            logs: vec![],
+            data: vec![],
+            return_data: None,
+            units_consumed: None,
+            result: InvocationResult::Incomplete,
+            inner: vec![],
+        }
+    }
+
+    /// This invocation and everything below it, depth first
+    pub fn walk(&self) -> Vec<&Invocation> {
+        let mut invocations = vec![self];
+        for inner in &self.inner {
+            invocations.extend(inner.walk());
+        }
+        invocations
+    }
+}
+
+/// Rebuild the invocation tree from `invoke`/`success`/`failed`/`consumed` log lines
+///
+/// Lines that belong to no invocation, like `Log truncated`, are dropped.
+pub fn parse_logs(logs: &[String]) -> Vec<Invocation> {
+    let mut roots = vec![];
+    let mut stack: Vec<Invocation> = vec![];
+
+    for line in logs {
+        if let Some(message) = line.strip_prefix("Program log: ") {
+            if let Some(top) = stack.last_mut() {
+                top.logs.push(message.to_string());
+            }
+            continue;
+        }
+        if let Some(data) = line.strip_prefix("Program data: ") {
+            if let Some(top) = stack.last_mut() {
+                top.data.extend(
+                    data.split_whitespace()
+                        .filter_map(|d| STANDARD.decode(d).ok()),
+                );
+            }
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("Program return: ") {
+            if let (Some(top), Some((_, data))) = (stack.last_mut(), rest.split_once(' ')) {
+                top.return_data = STANDARD.decode(data).ok();
+            }
+            continue;
+        }
+
+        let Some(rest) = line.strip_prefix("Program ") else {
+            continue;
+        };
+        let Some((program_id, event)) = rest.split_once(' ') else {
+            continue;
+        };
+        let Ok(program_id) = Pubkey::from_str(program_id) else {
+            continue;
+        };
+
+        if let Some(depth) = event
+            .strip_prefix("invoke [")
+            .and_then(|depth| depth.strip_suffix(']'))
+        {
+            stack.push(Invocation::new(
+                program_id,
+                depth.parse().unwrap_or(stack.len() + 1),
+            ));
+        } else if let Some(consumed) = event.strip_prefix("consumed ") {
+            if let Some(top) = stack.last_mut() {
+                top.units_consumed = consumed
+                    .split_whitespace()
+                    .next()
+                    .and_then(|units| units.parse().ok());
+            }
+        } else if event == "success" || event.starts_with("failed") {
+            let Some(mut finished) = stack.pop() else {
+                continue;
+            };
+            finished.result = match event.strip_prefix("failed: ") {
+                Some(error) => InvocationResult::Failed(error.to_string()),
+                None if event == "success" => InvocationResult::Success,
+                None => InvocationResult::Failed(String::new()),
+            };
+            match stack.last_mut() {
+                Some(parent) => parent.inner.push(finished),
+                None => roots.push(finished),
+            }
+        }
+    }
+
+    // whatever is still open never finished
+    while let Some(unfinished) = stack.pop() {
+        match stack.last_mut() {
+            Some(parent) => parent.inner.push(unfinished),
+            None => roots.push(unfinished),
+        }
+    }
+    roots
+}
+
+/// A CPI made while executing a top-level instruction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InnerInstruction {
+    /// Index of the top-level instruction that made the call
+    pub index: u8,
+    /// 2 for calls made directly by the top-level instruction
+    pub stack_height: Option<u32>,
+    pub program_id: Pubkey,
+    pub accounts: Vec<Pubkey>,
+    pub data: Vec<u8>,
+}
+
+fn resolve_inner_instructions(
+    account_keys: &[Pubkey],
+    inner_instructions: &[UiInnerInstructions],
+) -> Result<Vec<InnerInstruction>> {
+    let key = |index: u8| {
+        account_keys
+            .get(index as usize)
+            .copied()
+            .ok_or_else(|| anyhow!("account index {} out of range", index))
+    };
+
+    let mut resolved = vec![];
+    for inner in inner_instructions {
+        for instruction in &inner.instructions {
+            let UiInstruction::Compiled(compiled) = instruction else {
+                bail!("expected compiled inner instructions");
+            };
+            resolved.push(InnerInstruction {
+                index: inner.index,
+                stack_height: compiled.stack_height,
+                program_id: key(compiled.program_id_index)?,
+                accounts: compiled
+                    .accounts
+                    .iter()
+                    .map(|&index| key(index))
+                    .collect::<Result<_>>()?,
+                data: bs58::decode(&compiled.data).into_vec()?,
+            });
+        }
+    }
+    Ok(resolved)
+}
+
+/// Mint and amount of a token account
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TokenBalance {
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+/// The parts of an account worth comparing before and after
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccountState {
+    pub lamports: u64,
+    pub owner: Pubkey,
+    pub data_len: usize,
+    /// Set for SPL Token and Token-2022 accounts
+    pub token: Option<TokenBalance>,
+}
+
+impl From<&Account> for AccountState {
+    fn from(account: &Account) -> Self {
+        // both programs share the base account layout, extensions come after it
+        let token = if account.owner == spl_token::id() || account.owner == spl_token_2022::id() {
+            account
+                .data
+                .get(..spl_token::state::Account::LEN)
+                .and_then(|data| spl_token::state::Account::unpack(data).ok())
+                .map(|token| TokenBalance {
+                    mint: token.mint,
+                    amount: token.amount,
+                })
+        } else {
+            None
+        };
+        Self {
+            lamports: account.lamports,
+            owner: account.owner,
+            data_len: account.data.len(),
+            token,
+        }
+    }
+}
+
+/// State of a requested account before and after the simulation, `None` if it doesn't exist
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccountDiff {
+    pub address: Pubkey,
+    pub before: Option<AccountState>,
+    pub after: Option<AccountState>,
+}
+
+impl AccountDiff {
+    pub fn lamports_delta(&self) -> i128 {
+        let lamports = |state: &Option<AccountState>| state.as_ref().map_or(0, |s| s.lamports);
+        lamports(&self.after) as i128 - lamports(&self.before) as i128
+    }
+
+    /// Change of the token amount, 0 for accounts that aren't token accounts
+    pub fn token_delta(&self) -> i128 {
+        let amount = |state: &Option<AccountState>| {
+            state
+                .as_ref()
+                .and_then(|s| s.token)
+                .map_or(0, |token| token.amount)
+        };
+        amount(&self.after) as i128 - amount(&self.before) as i128
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.before != self.after
+    }
+}
+
+/// What the return data of a transaction was and which program set it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReturnData {
+    pub program_id: Pubkey,
+    pub data: Vec<u8>,
+}
+
+/// Everything a simulation tells about a transaction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulationReport {
+    /// `None` if the transaction would succeed
+    pub error: Option<TransactionError>,
+    pub units_consumed: Option<u64>,
+    pub logs: Vec<String>,
+    /// Top-level instructions, with their CPIs nested
+    pub invocations: Vec<Invocation>,
+    pub return_data: Option<ReturnData>,
+    pub inner_instructions: Vec<InnerInstruction>,
+    /// In the order the accounts were requested
+    pub account_diffs: Vec<AccountDiff>,
+}
+
+impl SimulationReport {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// The report, or an error carrying the transaction error and the logs
+    pub fn ensure_success(&self) -> Result<&Self> {
+        match &self.error {
+            None => Ok(self),
+            Some(error) => bail!("simulation failed: {}\n{}", error, self.logs.join("\n")),
+        }
+    }
+
+    /// Every invocation of `program_id`, at any depth
+    pub fn invocations_of(&self, program_id: &Pubkey) -> Vec<&Invocation> {
+        self.invocations
+            .iter()
+            .flat_map(Invocation::walk)
+            .filter(|invocation| invocation.program_id == *program_id)
+            .collect()
+    }
+
+    pub fn account_diff(&self, address: &Pubkey) -> Option<&AccountDiff> {
+        self.account_diffs
+            .iter()
+            .find(|diff| diff.address == *address)
+    }
+}
+
+/// Simulate `instructions` paid by `payer` without signing them
+///
+/// The state of `accounts` is fetched before the simulation and returned by it afterwards.
+pub async fn simulate(
+    client: &RpcClient,
+    instructions: &[Instruction],
+    payer: &Pubkey,
+    accounts: &[Pubkey],
+) -> Result<SimulationReport> {
+    let transaction = Transaction::new_with_payer(instructions, Some(payer));
+    simulate_transaction(client, &transaction, accounts).await
+}
+
+/// Simulate a built transaction, signed or not, against the latest blockhash
+pub async fn simulate_transaction(
+    client: &RpcClient,
+    transaction: &Transaction,
+    accounts: &[Pubkey],
+) -> Result<SimulationReport> {
+    let before = client.get_multiple_accounts(accounts).await?;
+    let simulation = client
+        .simulate_transaction_with_config(
+            transaction,
+            RpcSimulateTransactionConfig {
+                sig_verify: false,
+                replace_recent_blockhash: true,
+                commitment: Some(client.commitment()),
+                accounts: Some(RpcSimulateTransactionAccountsConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    addresses: accounts.iter().map(Pubkey::to_string).collect(),
+                }),
+                inner_instructions: true,
+                ..RpcSimulateTransactionConfig::default()
+            },
+        )
+        .await?
+        .value;
+
+    let after = simulation.accounts.unwrap_or_default();
+    let account_diffs = accounts
+        .iter()
+        .enumerate()
+        .map(|(i, address)| AccountDiff {
+            address: *address,
+            before: before[i].as_ref().map(AccountState::from),
+            after: after
+                .get(i)
+                .and_then(Option::as_ref)
+                .and_then(|account| account.decode::<Account>())
+                .map(|account| AccountState::from(&account)),
+        })
+        .collect();
+
+    let return_data = match simulation.return_data {
+        Some(return_data) => Some(ReturnData {
+            program_id: Pubkey::from_str(&return_data.program_id)?,
+            data: STANDARD.decode(&return_data.data.0)?,
+        }),
+        None => None,
+    };
+    let inner_instructions = resolve_inner_instructions(
+        &transaction.message.account_keys,
+        &simulation.inner_instructions.unwrap_or_default(),
+    )?;
+    let logs = simulation.logs.unwrap_or_default();
+
+    Ok(SimulationReport {
+        error: simulation.err,
+        units_consumed: simulation.units_consumed,
+        invocations: parse_logs(&logs),
+        logs,
+        return_data,
+        inner_instructions,
+        account_diffs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common;
+    use crate::config::ClusterConfig;
+    use solana_sdk::{
+        native_token::LAMPORTS_PER_SOL, signature::Keypair, signer::Signer, system_instruction,
+    };
+    use solana_transaction_status_client_types::UiCompiledInstruction;
+
+    fn lines(logs: &str) -> Vec<String> {
+        logs.lines().map(|line| line.trim().to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_logs() {
+        let logs = lines(
+            "Program ComputeBudget111111111111111111111111111111 invoke [1]
+             Program ComputeBudget111111111111111111111111111111 success
+             Program ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL invoke [1]
+             Program log: Create
+             Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA invoke [2]
+             Program log: Instruction: GetAccountDataSize
+             Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA consumed 1595 of 394308 compute units
+             Program return: TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA pQAAAAAAAAA=
+             Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA success
+             Program data: AQID
+             Program ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL consumed 20000 of 400000 compute units
+             Program ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL failed: custom program error: 0x0
+             Program 11111111111111111111111111111111 invoke [1]
+             Log truncated",
+        );
+        let invocations = parse_logs(&logs);
+        assert_eq!(invocations.len(), 3);
+        assert_eq!(invocations[0].result, InvocationResult::Success);
+
+        let ata = &invocations[1];
+        assert_eq!(ata.logs, ["Create"]);
+        assert_eq!(ata.data, [vec![1, 2, 3]]);
+        assert_eq!(ata.units_consumed, Some(20_000));
+        assert_eq!(
+            ata.result,
+            InvocationResult::Failed("custom program error: 0x0".to_string())
+        );
+
+        let token = &ata.inner[0];
+        assert_eq!(token.program_id, spl_token::id());
+        assert_eq!(token.depth, 2);
+        assert_eq!(token.logs, ["Instruction: GetAccountDataSize"]);
+        assert_eq!(token.return_data, Some(165u64.to_le_bytes().to_vec()));
+        assert_eq!(ata.walk().len(), 2);
+
+        assert_eq!(invocations[2].result, InvocationResult::Incomplete);
+    }
+
+    #[test]
+    fn test_inner_instructions_and_diffs() {
+        let keys = [Pubkey::new_unique(), Pubkey::new_unique(), spl_token::id()];
+        let inner = [UiInnerInstructions {
+            index: 1,
+            instructions: vec![UiInstruction::Compiled(UiCompiledInstruction {
+                program_id_index: 2,
+                accounts: vec![0, 1],
+                data: bs58::encode([3, 4]).into_string(),
+                stack_height: Some(2),
+            })],
+        }];
+        let resolved = resolve_inner_instructions(&keys, &inner).unwrap();
+        assert_eq!(resolved[0].index, 1);
+        assert_eq!(resolved[0].program_id, spl_token::id());
+        assert_eq!(resolved[0].accounts, keys[..2]);
+        assert_eq!(resolved[0].data, [3, 4]);
+
+        let mut token_account = spl_token::state::Account {
+            mint: Pubkey::new_unique(),
+            amount: 100,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        };
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account::pack(token_account, &mut data).unwrap();
+        let before = Account {
+            lamports: 10,
+            data: data.clone(),
+            owner: spl_token::id(),
+            ..Account::default()
+        };
+        token_account.amount = 40;
+        spl_token::state::Account::pack(token_account, &mut data).unwrap();
+        let after = Account {
+            lamports: 15,
+            data,
+            ..before.clone()
+        };
+
+        let diff = AccountDiff {
+            address: keys[0],
+            before: Some(AccountState::from(&before)),
+            after: Some(AccountState::from(&after)),
+        };
+        assert!(diff.is_changed());
+        assert_eq!(diff.lamports_delta(), 5);
+        assert_eq!(diff.token_delta(), -60);
+    }
+
+    #[tokio::test]
+    async fn test_simulate_transfer() -> Result<()> {
+        let client = common::get_rpc_client(&ClusterConfig::resolve()?);
+        let payer = Keypair::new();
+        common::airdrop(&client, &payer, LAMPORTS_PER_SOL).await?;
+        let to = Pubkey::new_unique();
+
+        let transfer = system_instruction::transfer(&payer.pubkey(), &to, LAMPORTS_PER_SOL / 10);
+        let report = simulate(&client, &[transfer], &payer.pubkey(), &[to]).await?;
+        report.ensure_success()?;
+        assert!(report.units_consumed.is_some());
+        assert_eq!(
+            report
+                .invocations_of(&solana_sdk::system_program::id())
+                .len(),
+            1
+        );
+        let diff = report.account_diff(&to).unwrap();
+        assert_eq!(diff.before, None);
+        assert_eq!(diff.lamports_delta(), (LAMPORTS_PER_SOL / 10) as i128);
+
+        let too_much = system_instruction::transfer(&payer.pubkey(), &to, LAMPORTS_PER_SOL * 10);
+        let report = simulate(&client, &[too_much], &payer.pubkey(), &[]).await?;
+        assert!(!report.is_success());
+        assert!(matches!(
+            report.invocations[0].result,
+            InvocationResult::Failed(_)
+        ));
+        Ok(())
+    }
+}
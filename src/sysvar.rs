@@ -0,0 +1,245 @@
+use anyhow::{Result, anyhow};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    account::from_account,
+    clock::{Clock, DEFAULT_MS_PER_SLOT},
+    epoch_schedule::EpochSchedule,
+    rent::Rent,
+    slot_hashes::SlotHashes,
+    stake_history::StakeHistory,
+    sysvar::Sysvar,
+};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Fetch a sysvar account and decode it
+pub async fn fetch_sysvar<S: Sysvar>(client: &RpcClient) -> Result<S> {
+    let account = client.get_account(&S::id()).await?;
+    from_account(&account).ok_or_else(|| anyhow!("failed to decode sysvar {}", S::id()))
+}
+
+pub async fn fetch_clock(client: &RpcClient) -> Result<Clock> {
+    fetch_sysvar(client).await
+}
+
+pub async fn fetch_rent(client: &RpcClient) -> Result<Rent> {
+    fetch_sysvar(client).await
+}
+
+pub async fn fetch_epoch_schedule(client: &RpcClient) -> Result<EpochSchedule> {
+    fetch_sysvar(client).await
+}
+
+/// Hashes of the most recent slots, newest first
+pub async fn fetch_slot_hashes(client: &RpcClient) -> Result<SlotHashes> {
+    fetch_sysvar(client).await
+}
+
+pub async fn fetch_stake_history(client: &RpcClient) -> Result<StakeHistory> {
+    fetch_sysvar(client).await
+}
+
+/// Where the chain is within its epoch, as of one `Clock`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChainTime {
+    pub slot: u64,
+    pub epoch: u64,
+    /// Slot within the epoch
+    pub slot_index: u64,
+    pub slots_in_epoch: u64,
+    /// Validators' stake weighted estimate, see `Clock::unix_timestamp`
+    pub unix_timestamp: i64,
+}
+
+impl ChainTime {
+    pub fn new(clock: &Clock, epoch_schedule: &EpochSchedule) -> Self {
+        let (epoch, slot_index) = epoch_schedule.get_epoch_and_slot_index(clock.slot);
+        Self {
+            slot: clock.slot,
+            epoch,
+            slot_index,
+            slots_in_epoch: epoch_schedule.get_slots_in_epoch(epoch),
+            unix_timestamp: clock.unix_timestamp,
+        }
+    }
+
+    /// Share of the epoch's slots that passed, from 0 to 1
+    pub fn epoch_progress(&self) -> f64 {
+        self.slot_index as f64 / self.slots_in_epoch as f64
+    }
+
+    pub fn slots_remaining(&self) -> u64 {
+        self.slots_in_epoch - self.slot_index
+    }
+}
+
+/// Converts between slots and timestamps around a known slot
+///
+/// Assumes a constant slot duration, so estimates drift the further they reach out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlotClock {
+    pub slot: u64,
+    pub unix_timestamp: i64,
+    pub ms_per_slot: u64,
+}
+
+impl SlotClock {
+    pub fn timestamp_at(&self, slot: u64) -> i64 {
+        let slots = slot as i128 - self.slot as i128;
+        let ms = slots * self.ms_per_slot as i128;
+        self.unix_timestamp + (ms / 1000) as i64
+    }
+
+    /// The first slot at or after `unix_timestamp`, slot 0 for anything earlier
+    pub fn slot_at(&self, unix_timestamp: i64) -> u64 {
+        let ms = (unix_timestamp as i128 - self.unix_timestamp as i128) * 1000;
+        let ms_per_slot = self.ms_per_slot.max(1) as i128;
+        // rounded up, a timestamp between two slots belongs to the later one
+        let slots = ms.div_euclid(ms_per_slot) + (ms.rem_euclid(ms_per_slot) > 0) as i128;
+        (self.slot as i128 + slots).max(0) as u64
+    }
+
+    pub fn slot_duration(&self) -> Duration {
+        Duration::from_millis(self.ms_per_slot)
+    }
+}
+
+/// Current slot, epoch and timestamps of a cluster
+///
+/// The epoch schedule is fetched once, it is fixed at genesis.
+pub struct ChainTimeService {
+    client: Arc<RpcClient>,
+    epoch_schedule: RwLock<Option<EpochSchedule>>,
+}
+
+impl ChainTimeService {
+    pub fn new(client: Arc<RpcClient>) -> Self {
+        Self {
+            client,
+            epoch_schedule: RwLock::new(None),
+        }
+    }
+
+    pub async fn epoch_schedule(&self) -> Result<EpochSchedule> {
+        if let Some(epoch_schedule) = self.epoch_schedule.read().unwrap().as_ref() {
+            return Ok(epoch_schedule.clone());
+        }
+        let epoch_schedule = fetch_epoch_schedule(&self.client).await?;
+        *self.epoch_schedule.write().unwrap() = Some(epoch_schedule.clone());
+        Ok(epoch_schedule)
+    }
+
+    pub async fn now(&self) -> Result<ChainTime> {
+        let clock = fetch_clock(&self.client).await?;
+        Ok(ChainTime::new(&clock, &self.epoch_schedule().await?))
+    }
+
+    /// Average slot duration over the node's recent performance samples, one per minute
+    ///
+    /// At least 1, `SlotClock` divides by it.
+    pub async fn ms_per_slot(&self, samples: usize) -> Result<u64> {
+        let samples = self
+            .client
+            .get_recent_performance_samples(Some(samples))
+            .await?;
+        let slots: u64 = samples.iter().map(|sample| sample.num_slots).sum();
+        let secs: u64 = samples
+            .iter()
+            .map(|sample| sample.sample_period_secs as u64)
+            .sum();
+        if slots == 0 {
+            return Ok(DEFAULT_MS_PER_SLOT);
+        }
+        Ok((secs * 1000 / slots).max(1))
+    }
+
+    /// A `SlotClock` anchored at the current clock, paced by the last `samples` minutes
+    pub async fn slot_clock(&self, samples: usize) -> Result<SlotClock> {
+        let clock = fetch_clock(&self.client).await?;
+        Ok(SlotClock {
+            slot: clock.slot,
+            unix_timestamp: clock.unix_timestamp,
+            ms_per_slot: self.ms_per_slot(samples).await?,
+        })
+    }
+
+    /// Estimated time the current epoch ends
+    pub async fn epoch_end_timestamp(&self) -> Result<i64> {
+        let now = self.now().await?;
+        let slot_clock = self.slot_clock(10).await?;
+        Ok(slot_clock.timestamp_at(now.slot + now.slots_remaining()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common;
+    use crate::config::ClusterConfig;
+
+    #[test]
+    fn test_chain_time() {
+        let epoch_schedule = EpochSchedule::without_warmup();
+        let clock = Clock {
+            slot: 432_000 * 3 + 108_000,
+            unix_timestamp: 1_700_000_000,
+            ..Clock::default()
+        };
+        let now = ChainTime::new(&clock, &epoch_schedule);
+        assert_eq!((now.epoch, now.slot_index), (3, 108_000));
+        assert_eq!(now.epoch_progress(), 0.25);
+        assert_eq!(now.slots_remaining(), 324_000);
+    }
+
+    #[test]
+    fn test_slot_clock() {
+        let slot_clock = SlotClock {
+            slot: 1_000,
+            unix_timestamp: 1_700_000_000,
+            ms_per_slot: 400,
+        };
+        assert_eq!(slot_clock.timestamp_at(1_000), 1_700_000_000);
+        assert_eq!(slot_clock.timestamp_at(1_250), 1_700_000_100);
+        assert_eq!(slot_clock.timestamp_at(750), 1_699_999_900);
+        assert_eq!(slot_clock.slot_at(1_700_000_100), 1_250);
+        assert_eq!(slot_clock.slot_at(1_699_999_900), 750);
+        assert_eq!(slot_clock.slot_at(0), 0);
+        // between slots 1_002 and 1_003, 2.5 slots in
+        assert_eq!(slot_clock.slot_at(1_700_000_001), 1_003);
+        assert!(slot_clock.timestamp_at(1_003) >= 1_700_000_001);
+        assert_eq!(slot_clock.slot_at(1_699_999_999), 998);
+
+        // a zero duration doesn't divide by zero
+        let stalled = SlotClock {
+            ms_per_slot: 0,
+            ..slot_clock
+        };
+        assert_eq!(stalled.slot_at(1_700_000_001), 2_000);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_sysvars() -> Result<()> {
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+
+        let clock = fetch_clock(&client).await?;
+        let rent = fetch_rent(&client).await?;
+        assert!(rent.minimum_balance(0) > 0);
+        let slot_hashes = fetch_slot_hashes(&client).await?;
+        assert!(
+            slot_hashes
+                .first()
+                .is_some_and(|(slot, _)| *slot < clock.slot)
+        );
+        fetch_stake_history(&client).await?;
+
+        let service = ChainTimeService::new(client.clone());
+        let now = service.now().await?;
+        assert!(now.slot >= clock.slot);
+        assert_eq!(
+            service.epoch_schedule().await?,
+            fetch_epoch_schedule(&client).await?
+        );
+        assert!(service.ms_per_slot(5).await? > 0);
+        Ok(())
+    }
+}
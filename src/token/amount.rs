@@ -0,0 +1,200 @@
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// More decimals than this can't be represented, 10^20 overflows u64
+pub const MAX_DECIMALS: u8 = 19;
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum AmountError {
+    #[error("empty amount")]
+    Empty,
+    #[error("invalid amount `{0}`")]
+    Invalid(String),
+    #[error("amount `{amount}` has more than {decimals} decimal places")]
+    TooPrecise { amount: String, decimals: u8 },
+    #[error("amount `{0}` does not fit into u64 base units")]
+    Overflow(String),
+    #[error("mint decimals {0} are not supported")]
+    UnsupportedDecimals(u8),
+}
+
+/// Human readable token amount such as "1.25", independent of any mint
+///
+/// Kept as an integer `mantissa` with `scale` fractional digits so no precision is lost
+/// before it is converted into base units of a mint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UiAmount {
+    mantissa: u128,
+    scale: u8,
+}
+
+impl UiAmount {
+    /// The amount represented by `amount` base units of a mint with `decimals`
+    pub fn from_base_units(amount: u64, decimals: u8) -> Result<Self, AmountError> {
+        if decimals > MAX_DECIMALS {
+            return Err(AmountError::UnsupportedDecimals(decimals));
+        }
+        Ok(Self {
+            mantissa: amount as u128,
+            scale: decimals,
+        }
+        .normalized())
+    }
+
+    /// Base units for a mint with `decimals`, fails instead of rounding
+    pub fn to_base_units(&self, decimals: u8) -> Result<u64, AmountError> {
+        if decimals > MAX_DECIMALS {
+            return Err(AmountError::UnsupportedDecimals(decimals));
+        }
+        if self.scale > decimals {
+            return Err(AmountError::TooPrecise {
+                amount: self.to_string(),
+                decimals,
+            });
+        }
+        10u128
+            .checked_pow((decimals - self.scale) as u32)
+            .and_then(|factor| self.mantissa.checked_mul(factor))
+            .and_then(|units| u64::try_from(units).ok())
+            .ok_or_else(|| AmountError::Overflow(self.to_string()))
+    }
+
+    /// Parse `s` and convert it to base units in one step
+    pub fn parse_base_units(s: &str, decimals: u8) -> Result<u64, AmountError> {
+        s.parse::<UiAmount>()?.to_base_units(decimals)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    // drop trailing zeros of the fraction, so "1.50" and "1.5" compare equal
+    fn normalized(mut self) -> Self {
+        while self.scale > 0 && self.mantissa.is_multiple_of(10) {
+            self.mantissa /= 10;
+            self.scale -= 1;
+        }
+        self
+    }
+}
+
+impl FromStr for UiAmount {
+    type Err = AmountError;
+
+    fn from_str(s: &str) -> Result<Self, AmountError> {
+        let trimmed = s.trim().replace('_', "");
+        if trimmed.is_empty() {
+            return Err(AmountError::Empty);
+        }
+        let (whole, fraction) = match trimmed.split_once('.') {
+            Some((whole, fraction)) => (whole, fraction),
+            None => (trimmed.as_str(), ""),
+        };
+        let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+            return Err(AmountError::Invalid(s.to_string()));
+        }
+
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.len() > MAX_DECIMALS as usize {
+            return Err(AmountError::TooPrecise {
+                amount: s.to_string(),
+                decimals: MAX_DECIMALS,
+            });
+        }
+
+        let mut mantissa: u128 = 0;
+        for digit in whole.bytes().chain(fraction.bytes()) {
+            mantissa = mantissa
+                .checked_mul(10)
+                .and_then(|m| m.checked_add((digit - b'0') as u128))
+                .filter(|m| *m <= u64::MAX as u128 * 10u128.pow(MAX_DECIMALS as u32))
+                .ok_or_else(|| AmountError::Overflow(s.to_string()))?;
+        }
+        Ok(Self {
+            mantissa,
+            scale: fraction.len() as u8,
+        })
+    }
+}
+
+impl fmt::Display for UiAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let factor = 10u128.pow(self.scale as u32);
+        let whole = self.mantissa / factor;
+        if self.scale == 0 {
+            write!(f, "{}", whole)
+        } else {
+            let fraction = self.mantissa % factor;
+            write!(
+                f,
+                "{}.{:0width$}",
+                whole,
+                fraction,
+                width = self.scale as usize
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_convert() {
+        assert_eq!(UiAmount::parse_base_units("1.25", 9), Ok(1_250_000_000));
+        assert_eq!(UiAmount::parse_base_units("1.25", 2), Ok(125));
+        assert_eq!(UiAmount::parse_base_units("100", 0), Ok(100));
+        assert_eq!(UiAmount::parse_base_units(".5", 1), Ok(5));
+        assert_eq!(UiAmount::parse_base_units("2.", 3), Ok(2_000));
+        assert_eq!(UiAmount::parse_base_units("1_000.10", 2), Ok(100_010));
+        assert_eq!(UiAmount::parse_base_units("0.000", 0), Ok(0));
+    }
+
+    #[test]
+    fn test_precision_and_overflow() {
+        assert!(matches!(
+            UiAmount::parse_base_units("1.255", 2),
+            Err(AmountError::TooPrecise { .. })
+        ));
+        assert_eq!(
+            UiAmount::parse_base_units("18446744073709551615", 0),
+            Ok(u64::MAX)
+        );
+        assert!(matches!(
+            UiAmount::parse_base_units("18446744073709551616", 0),
+            Err(AmountError::Overflow(_))
+        ));
+        assert!(matches!(
+            UiAmount::parse_base_units("18446744074", 9),
+            Err(AmountError::Overflow(_))
+        ));
+        assert_eq!(
+            UiAmount::parse_base_units("1", 20),
+            Err(AmountError::UnsupportedDecimals(20))
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        for input in ["", "  ", ".", "-1", "1.2.3", "abc", "1e9", "+1"] {
+            assert!(input.parse::<UiAmount>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let amount = UiAmount::from_base_units(1_250_000_000, 9).unwrap();
+        assert_eq!(amount.to_string(), "1.25");
+        assert_eq!(amount, "1.2500".parse().unwrap());
+        assert_eq!(amount.to_base_units(9), Ok(1_250_000_000));
+        assert_eq!(
+            UiAmount::from_base_units(5, 3).unwrap().to_string(),
+            "0.005"
+        );
+        assert_eq!(UiAmount::from_base_units(700, 2).unwrap().to_string(), "7");
+        assert!(UiAmount::from_base_units(0, 9).unwrap().is_zero());
+    }
+}
@@ -1,33 +1,62 @@
-use anyhow::Result;
-use solana_client::nonblocking::rpc_client::RpcClient;
+use crate::token::amount::UiAmount;
+use crate::token::mint_cache::fetch_mint;
+use anyhow::{Result, anyhow};
+use bytemuck::Pod;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, rpc_client::GetConfirmedSignaturesForAddress2Config,
+    rpc_config::RpcTransactionConfig,
+};
 use solana_sdk::{
+    instruction::CompiledInstruction,
     pubkey::Pubkey,
-    signature::{Keypair, Signer},
+    signature::{Keypair, Signature, Signer},
     transaction::Transaction,
 };
+use solana_transaction_status_client_types::{
+    UiTransactionEncoding, option_serializer::OptionSerializer,
+};
 use spl_associated_token_account::{
     get_associated_token_address_with_program_id, instruction::create_associated_token_account,
 };
 use spl_token_2022::{
     extension::{
-        ExtensionType,
-        confidential_transfer::instruction::{PubkeyValidityProofData, configure_account},
+        BaseStateWithExtensions, ExtensionType, StateWithExtensionsOwned,
+        confidential_transfer::{
+            ConfidentialTransferAccount, ConfidentialTransferMint,
+            account_info::{TransferAccountInfo, WithdrawAccountInfo, combine_balances},
+            instruction::{
+                ConfidentialTransferInstruction, PubkeyValidityProofData, TransferInstructionData,
+                TransferWithFeeInstructionData, configure_account,
+            },
+        },
+    },
+    instruction::{TokenInstruction, decode_instruction_data, decode_instruction_type, reallocate},
+    solana_zk_sdk::{
+        encryption::{
+            auth_encryption::{AeCiphertext, AeKey},
+            elgamal::{ElGamalCiphertext, ElGamalKeypair, ElGamalPubkey, ElGamalSecretKey},
+            pod::elgamal::{PodElGamalCiphertext, PodElGamalPubkey},
+        },
+        zk_elgamal_proof_program::proof_data::ZkProofData,
     },
-    instruction::reallocate,
-    solana_zk_sdk::encryption::{auth_encryption::AeKey, elgamal::ElGamalKeypair},
+    state::Account,
 };
 use spl_token_client::{
-    client::{ProgramRpcClient, ProgramRpcClientSendTransaction},
+    client::{ProgramRpcClient, ProgramRpcClientSendTransaction, RpcClientResponse},
     spl_token_2022::id as token_2022_program_id,
-    token::{ExtensionInitializationParams, Token},
+    token::{ExtensionInitializationParams, ProofAccount, ProofAccountWithCiphertext, Token},
 };
 use spl_token_confidential_transfer_proof_extraction::instruction::{ProofData, ProofLocation};
+use spl_token_confidential_transfer_proof_generation::{
+    TRANSFER_AMOUNT_LO_BITS, transfer::TransferProofData, withdraw::WithdrawProofData,
+};
 use std::sync::Arc;
 
 async fn create_confidential_mint(
     rpc_client: Arc<RpcClient>,
     authority: Arc<Keypair>,
     mint: &Keypair,
+    auditor_elgamal_pubkey: Option<&ElGamalPubkey>,
 ) -> Result<()> {
     // Set up program client for Token client
     let program_client = ProgramRpcClient::new(rpc_client, ProgramRpcClientSendTransaction);
@@ -45,13 +74,16 @@ async fn create_confidential_mint(
         authority.clone(),        // Fee payer for transactions (cloning Arc, not keypair)
     );
 
+    // The auditor can decrypt the amount of every confidential transfer of this mint
+    let auditor_elgamal_pubkey = auditor_elgamal_pubkey.map(|pubkey| (*pubkey).into());
+
     // Create extension initialization parameters
     // The ConfidentialTransferMint extension enables confidential (private) transfers of tokens
     let extension_initialization_params =
         vec![ExtensionInitializationParams::ConfidentialTransferMint {
             authority: Some(authority.pubkey()), // Authority that can modify confidential transfer settings
             auto_approve_new_accounts: true,     // Automatically approve new confidential accounts
-            auditor_elgamal_pubkey: None,        // Optional auditor ElGamal public key
+            auditor_elgamal_pubkey,              // Optional auditor ElGamal public key
         }];
 
     // Create and initialize the mint with the ConfidentialTransferMint extension
@@ -73,23 +105,6 @@ async fn create_confidential_mint(
     Ok(())
 }
 
-// Load the keypair from the default Solana CLI keypair path (~/.config/solana/id.json)
-// This enables using the same wallet as the Solana CLI tools
-fn load_keypair() -> Result<Keypair> {
-    // Get the default keypair path
-    let keypair_path = "/home/gidon/.config/solana/id.json".to_string();
-    // Read the keypair file directly into bytes using serde_json
-    // The keypair file is a JSON array of bytes
-    let file = std::fs::File::open(&keypair_path)?;
-    let keypair_bytes: Vec<u8> = serde_json::from_reader(file)?;
-
-    // Create keypair from the loaded bytes
-    // This converts the byte array into a keypair
-    let keypair = Keypair::from_bytes(&keypair_bytes)?;
-
-    Ok(keypair)
-}
-
 async fn create_confidential_token_account(
     rpc_client: Arc<RpcClient>,
     wallet: Arc<Keypair>,
@@ -193,7 +208,9 @@ pub async fn transfer_public_balance_to_confidential_pending_balance(
 ) -> Result<()> {
     // Set up program client for Token client
     let program_client = ProgramRpcClient::new(rpc_client.clone(), ProgramRpcClientSendTransaction);
-    let decimals = 9;
+    let decimals = fetch_mint(&rpc_client, &mint.pubkey()).await?.decimals;
+    // 100 tokens with decimal precision
+    let amount = UiAmount::parse_base_units("100", decimals)?;
 
     // Create a token client for the Token-2022 program
     // This provides high-level methods for token operations
@@ -209,10 +226,10 @@ pub async fn transfer_public_balance_to_confidential_pending_balance(
     // This gives the account some tokens to work with
     let mint_signature = token
         .mint_to(
-            &token_account_pubkey,            // Destination account
-            &authority.pubkey(),              // Mint authority
-            100 * 10u64.pow(decimals as u32), // Amount (100 tokens with decimal precision)
-            &[&authority],                    // Signers
+            &token_account_pubkey, // Destination account
+            &authority.pubkey(),   // Mint authority
+            amount,                // Amount (100 tokens with decimal precision)
+            &[&authority],         // Signers
         )
         .await?;
 
@@ -223,11 +240,11 @@ pub async fn transfer_public_balance_to_confidential_pending_balance(
     println!("Deposit tokens to confidential state pending balance");
     let deposit_signature = token
         .confidential_transfer_deposit(
-            &token_account_pubkey,            // The token account
-            &token_owner.pubkey(),            // Authority (owner) of the account
-            100 * 10u64.pow(decimals as u32), // Amount to deposit (100 tokens)
-            decimals,                         // Decimals of the token
-            &[&token_owner],                  // Signers (owner must sign)
+            &token_account_pubkey, // The token account
+            &token_owner.pubkey(), // Authority (owner) of the account
+            amount,                // Amount to deposit (100 tokens)
+            decimals,              // Decimals of the token
+            &[&token_owner],       // Signers (owner must sign)
         )
         .await?;
 
@@ -250,7 +267,7 @@ pub async fn transfer_confidential_pending_balance_to_available_balance(
     let program_client = ProgramRpcClient::new(rpc_client.clone(), ProgramRpcClientSendTransaction);
 
     // Number of decimals for the mint
-    let decimals = 9;
+    let decimals = fetch_mint(&rpc_client, &mint.pubkey()).await?.decimals;
 
     // Create a token client for the Token-2022 program
     // This provides high-level methods for token operations
@@ -272,7 +289,7 @@ pub async fn transfer_confidential_pending_balance_to_available_balance(
     println!("Apply pending balance to available balance");
     let apply_signature = token
         .confidential_transfer_apply_pending_balance(
-            &token_account_pubkey,         // The token account
+            token_account_pubkey,          // The token account
             &token_account_owner.pubkey(), // Authority (owner) of the account
             None,                          // Optional new decryptable available balance
             elgamal_keypair.secret(), // ElGamal keypair for public-key cryptography (decryption and ZK proofs)
@@ -291,29 +308,771 @@ pub async fn transfer_confidential_pending_balance_to_available_balance(
     Ok(())
 }
 
+/// Zero-knowledge proof context state accounts created for a single transfer or withdraw
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProofContextAccounts {
+    pub equality: Pubkey,
+    /// Only a transfer needs a ciphertext validity proof
+    pub ciphertext_validity: Option<Pubkey>,
+    pub range: Pubkey,
+}
+
+impl ProofContextAccounts {
+    pub fn addresses(&self) -> Vec<Pubkey> {
+        let mut addresses = vec![self.equality];
+        addresses.extend(self.ciphertext_validity);
+        addresses.push(self.range);
+        addresses
+    }
+}
+
+fn confidential_token(
+    rpc_client: Arc<RpcClient>,
+    payer: Arc<Keypair>,
+    mint: &Pubkey,
+    decimals: u8,
+) -> Token<ProgramRpcClientSendTransaction> {
+    let program_client = ProgramRpcClient::new(rpc_client, ProgramRpcClientSendTransaction);
+    Token::new(
+        Arc::new(program_client),
+        &token_2022_program_id(), // Use the Token-2022 program (newer version with extensions)
+        mint,                     // Address of the token mint
+        Some(decimals),           // Number of decimal places
+        payer,                    // Fee payer for transactions
+    )
+}
+
+/// The ElGamal keypair and AES key of a token account, derived from its owner's signature
+fn derive_confidential_keys(
+    owner: &Keypair,
+    token_account_pubkey: &Pubkey,
+) -> Result<(ElGamalKeypair, AeKey)> {
+    let elgamal_keypair = ElGamalKeypair::new_from_signer(owner, &token_account_pubkey.to_bytes())
+        .map_err(|e| anyhow!("Failed to create ElGamal keypair: {}", e))?;
+    let aes_key = AeKey::new_from_signer(owner, &token_account_pubkey.to_bytes())
+        .map_err(|e| anyhow!("Failed to create AES key: {}", e))?;
+    Ok((elgamal_keypair, aes_key))
+}
+
+fn response_signature(response: RpcClientResponse) -> Result<Signature> {
+    match response {
+        RpcClientResponse::Signature(signature) => Ok(signature),
+        _ => Err(anyhow!("transaction was not sent")),
+    }
+}
+
+/// Verify `proof_data` into a new context state account owned by the payer
+async fn create_proof_context_account<ZK, U>(
+    token: &Token<ProgramRpcClientSendTransaction>,
+    payer: &Keypair,
+    proof_data: &ZK,
+    split_account_creation_and_proof_verification: bool,
+) -> Result<Pubkey>
+where
+    ZK: Pod + ZkProofData<U>,
+    U: Pod,
+{
+    let context_state_account = Keypair::new();
+    // Range proofs are too big to share a transaction with the account creation
+    let split = split_account_creation_and_proof_verification;
+    token
+        .confidential_transfer_create_context_state_account(
+            &context_state_account.pubkey(), // Context state account
+            &payer.pubkey(),                 // Context state authority, may close the account
+            proof_data,                      // Proof to verify
+            split,                           // Create the account in its own transaction
+            &[&context_state_account],       // New account must sign its creation
+        )
+        .await?;
+    Ok(context_state_account.pubkey())
+}
+
+/// Record a newly created context state account, or close the ones `created` before it
+/// when its creation failed, so a failed transfer or withdraw doesn't keep their rent
+async fn keep_or_close(
+    rpc_client: &Arc<RpcClient>,
+    payer: &Arc<Keypair>,
+    mint: &Pubkey,
+    created: &mut Vec<Pubkey>,
+    result: Result<Pubkey>,
+) -> Result<Pubkey> {
+    match result {
+        Ok(context_state_account) => {
+            created.push(context_state_account);
+            Ok(context_state_account)
+        }
+        Err(err) => {
+            if let Err(close_err) =
+                close_proof_context_accounts(rpc_client.clone(), payer.clone(), mint, created).await
+            {
+                eprintln!("Error closing proof context accounts: {}", close_err);
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Close proof context state accounts and return their rent to the payer
+pub async fn close_proof_context_accounts(
+    rpc_client: Arc<RpcClient>,
+    payer: Arc<Keypair>,
+    mint: &Pubkey,
+    context_state_accounts: &[Pubkey],
+) -> Result<()> {
+    let decimals = fetch_mint(&rpc_client, mint).await?.decimals;
+    let token = confidential_token(rpc_client, payer.clone(), mint, decimals);
+    for context_state_account in context_state_accounts {
+        let close_signature = token
+            .confidential_transfer_close_context_state_account(
+                context_state_account, // Context state account
+                &payer.pubkey(),       // Lamport destination
+                &payer.pubkey(),       // Context state authority
+                &[&payer],             // Signers
+            )
+            .await?;
+        println!(
+            "Close Proof Context Account {} Signature: {}",
+            context_state_account, close_signature
+        );
+    }
+    Ok(())
+}
+
+/// Transfer `amount` from the available balance of `sender_token_account_pubkey` to the
+/// pending balance of `recipient_token_account_pubkey`
+///
+/// The equality, ciphertext validity and range proofs together are too big for one
+/// transaction, so each one is verified into its own context state account first. Those
+/// accounts are closed again afterwards, also when the transfer itself fails. A failed
+/// close is only logged, it doesn't hide the transfer signature.
+pub async fn confidential_transfer(
+    rpc_client: Arc<RpcClient>,
+    payer: Arc<Keypair>,
+    mint: &Pubkey,
+    sender: &Keypair,
+    sender_token_account_pubkey: &Pubkey,
+    recipient_token_account_pubkey: &Pubkey,
+    amount: u64,
+) -> Result<Signature> {
+    println!("\nConfidential transfer of {} base units", amount);
+    let decimals = fetch_mint(&rpc_client, mint).await?.decimals;
+    let token = confidential_token(rpc_client.clone(), payer.clone(), mint, decimals);
+
+    // The recipient's ElGamal public key encrypts the amount for them,
+    // the auditor's (if the mint has one) for the auditor
+    let recipient_account = token
+        .get_account_info(recipient_token_account_pubkey)
+        .await?;
+    let recipient_elgamal_pubkey: ElGamalPubkey = recipient_account
+        .get_extension::<ConfidentialTransferAccount>()?
+        .elgamal_pubkey
+        .try_into()?;
+    let mint_state = token.get_mint_info().await?;
+    let auditor_elgamal_pubkey = Option::<PodElGamalPubkey>::from(
+        mint_state
+            .get_extension::<ConfidentialTransferMint>()?
+            .auditor_elgamal_pubkey,
+    )
+    .map(ElGamalPubkey::try_from)
+    .transpose()?;
+
+    let (sender_elgamal_keypair, sender_aes_key) =
+        derive_confidential_keys(sender, sender_token_account_pubkey)?;
+    let sender_account = token.get_account_info(sender_token_account_pubkey).await?;
+    let transfer_account_info =
+        TransferAccountInfo::new(sender_account.get_extension::<ConfidentialTransferAccount>()?);
+
+    // Generate the proofs client-side
+    let TransferProofData {
+        equality_proof_data,
+        ciphertext_validity_proof_data_with_ciphertext,
+        range_proof_data,
+    } = transfer_account_info.generate_split_transfer_proof_data(
+        amount,
+        &sender_elgamal_keypair,
+        &sender_aes_key,
+        &recipient_elgamal_pubkey,
+        auditor_elgamal_pubkey.as_ref(),
+    )?;
+
+    let mut created = vec![];
+    let equality = create_proof_context_account(&token, &payer, &equality_proof_data, false).await;
+    let equality = keep_or_close(&rpc_client, &payer, mint, &mut created, equality).await?;
+    let ciphertext_validity = create_proof_context_account(
+        &token,
+        &payer,
+        &ciphertext_validity_proof_data_with_ciphertext.proof_data,
+        false,
+    )
+    .await;
+    let ciphertext_validity =
+        keep_or_close(&rpc_client, &payer, mint, &mut created, ciphertext_validity).await?;
+    let range = create_proof_context_account(&token, &payer, &range_proof_data, true).await;
+    let range = keep_or_close(&rpc_client, &payer, mint, &mut created, range).await?;
+    let context_accounts = ProofContextAccounts {
+        equality,
+        ciphertext_validity: Some(ciphertext_validity),
+        range,
+    };
+    println!("Proof Context Accounts: {:?}", context_accounts);
+
+    let equality_proof_account = ProofAccount::ContextAccount(context_accounts.equality);
+    let range_proof_account = ProofAccount::ContextAccount(context_accounts.range);
+    let ciphertext_validity_proof_account = ProofAccountWithCiphertext {
+        proof_account: ProofAccount::ContextAccount(context_accounts.ciphertext_validity.unwrap()),
+        ciphertext_lo: ciphertext_validity_proof_data_with_ciphertext.ciphertext_lo,
+        ciphertext_hi: ciphertext_validity_proof_data_with_ciphertext.ciphertext_hi,
+    };
+    let transfer_result = token
+        .confidential_transfer_transfer(
+            sender_token_account_pubkey,              // Source token account
+            recipient_token_account_pubkey,           // Destination token account
+            &sender.pubkey(),                         // Owner of the source account
+            Some(&equality_proof_account),            // Equality proof
+            Some(&ciphertext_validity_proof_account), // Ciphertext validity proof
+            Some(&range_proof_account),               // Range proof
+            amount,                                   // Amount in base units
+            Some(transfer_account_info),              // Source balances the proofs were made for
+            &sender_elgamal_keypair,                  // Source ElGamal keypair
+            &sender_aes_key,                          // Source AES key
+            &recipient_elgamal_pubkey,                // Destination ElGamal public key
+            auditor_elgamal_pubkey.as_ref(),          // Auditor ElGamal public key
+            &[sender],                                // Signers
+        )
+        .await;
+
+    // the transfer outcome wins over a failed cleanup, which only leaves rent behind
+    let transfer_signature = transfer_result
+        .map_err(Into::into)
+        .and_then(response_signature);
+    if let Err(close_err) =
+        close_proof_context_accounts(rpc_client, payer, mint, &context_accounts.addresses()).await
+    {
+        eprintln!("Error closing proof context accounts: {}", close_err);
+    }
+    let transfer_signature = transfer_signature?;
+    println!("Confidential Transfer Signature: {}", transfer_signature);
+    Ok(transfer_signature)
+}
+
+/// Move `amount` from the confidential available balance back to the public balance
+pub async fn withdraw_confidential_balance(
+    rpc_client: Arc<RpcClient>,
+    payer: Arc<Keypair>,
+    mint: &Pubkey,
+    token_account_owner: &Keypair,
+    token_account_pubkey: &Pubkey,
+    amount: u64,
+) -> Result<Signature> {
+    println!("\nWithdraw {} base units from confidential balance", amount);
+    let decimals = fetch_mint(&rpc_client, mint).await?.decimals;
+    let token = confidential_token(rpc_client.clone(), payer.clone(), mint, decimals);
+
+    let (elgamal_keypair, aes_key) =
+        derive_confidential_keys(token_account_owner, token_account_pubkey)?;
+    let account = token.get_account_info(token_account_pubkey).await?;
+    let withdraw_account_info =
+        WithdrawAccountInfo::new(account.get_extension::<ConfidentialTransferAccount>()?);
+
+    let WithdrawProofData {
+        equality_proof_data,
+        range_proof_data,
+    } = withdraw_account_info.generate_proof_data(amount, &elgamal_keypair, &aes_key)?;
+
+    let mut created = vec![];
+    let equality = create_proof_context_account(&token, &payer, &equality_proof_data, false).await;
+    let equality = keep_or_close(&rpc_client, &payer, mint, &mut created, equality).await?;
+    let range = create_proof_context_account(&token, &payer, &range_proof_data, true).await;
+    let range = keep_or_close(&rpc_client, &payer, mint, &mut created, range).await?;
+    let context_accounts = ProofContextAccounts {
+        equality,
+        ciphertext_validity: None,
+        range,
+    };
+    println!("Proof Context Accounts: {:?}", context_accounts);
+
+    let equality_proof_account = ProofAccount::ContextAccount(context_accounts.equality);
+    let range_proof_account = ProofAccount::ContextAccount(context_accounts.range);
+    let withdraw_result = token
+        .confidential_transfer_withdraw(
+            token_account_pubkey,          // Token account
+            &token_account_owner.pubkey(), // Owner of the account
+            Some(&equality_proof_account), // Equality proof
+            Some(&range_proof_account),    // Range proof
+            amount,                        // Amount in base units
+            decimals,                      // Decimals of the token
+            Some(withdraw_account_info),   // Balances the proofs were made for
+            &elgamal_keypair,              // ElGamal keypair
+            &aes_key,                      // AES key
+            &[token_account_owner],        // Signers
+        )
+        .await;
+
+    // the withdraw outcome wins over a failed cleanup, which only leaves rent behind
+    let withdraw_signature = withdraw_result
+        .map_err(Into::into)
+        .and_then(response_signature);
+    if let Err(close_err) =
+        close_proof_context_accounts(rpc_client, payer, mint, &context_accounts.addresses()).await
+    {
+        eprintln!("Error closing proof context accounts: {}", close_err);
+    }
+    let withdraw_signature = withdraw_signature?;
+    println!("Confidential Withdraw Signature: {}", withdraw_signature);
+    Ok(withdraw_signature)
+}
+
+/// Decrypted state of a confidential token account
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConfidentialBalance {
+    /// Spendable balance, decrypted from the decryptable available balance
+    pub available: u64,
+    /// Received balance waiting for `ApplyPendingBalance`
+    pub pending: u64,
+    pub pending_lo: u64,
+    pub pending_hi: u64,
+    /// Deposits and transfers that credited the pending balance since the last apply
+    pub pending_balance_credit_counter: u64,
+    pub maximum_pending_balance_credit_counter: u64,
+}
+
+impl ConfidentialBalance {
+    /// Pending funds can only be spent after they are applied
+    pub fn needs_apply(&self) -> bool {
+        self.pending_balance_credit_counter > 0
+    }
+
+    /// Further credits fail once the counter reaches the maximum
+    pub fn is_pending_full(&self) -> bool {
+        self.pending_balance_credit_counter >= self.maximum_pending_balance_credit_counter
+    }
+}
+
+/// Decrypt the balances of a `ConfidentialTransferAccount` extension
+pub fn decrypt_confidential_balance(
+    account: &ConfidentialTransferAccount,
+    elgamal_secret_key: &ElGamalSecretKey,
+    aes_key: &AeKey,
+) -> Result<ConfidentialBalance> {
+    let decryptable_available_balance: AeCiphertext = account
+        .decryptable_available_balance
+        .try_into()
+        .map_err(|_| anyhow!("malformed decryptable available balance"))?;
+    let available = aes_key
+        .decrypt(&decryptable_available_balance)
+        .ok_or_else(|| anyhow!("failed to decrypt available balance, wrong AES key?"))?;
+
+    // lo holds 16 and hi 48 bits of the pending balance, small enough to decrypt
+    let decrypt_pending = |ciphertext: PodElGamalCiphertext, part: &str| -> Result<u64> {
+        let ciphertext: ElGamalCiphertext = ciphertext
+            .try_into()
+            .map_err(|_| anyhow!("malformed pending balance {}", part))?;
+        elgamal_secret_key
+            .decrypt_u32(&ciphertext)
+            .ok_or_else(|| anyhow!("failed to decrypt pending balance {}", part))
+    };
+    let pending_lo = decrypt_pending(account.pending_balance_lo, "lo")?;
+    let pending_hi = decrypt_pending(account.pending_balance_hi, "hi")?;
+    let pending = combine_balances(pending_lo, pending_hi)
+        .ok_or_else(|| anyhow!("pending balance overflows"))?;
+
+    Ok(ConfidentialBalance {
+        available,
+        pending,
+        pending_lo,
+        pending_hi,
+        pending_balance_credit_counter: account.pending_balance_credit_counter.into(),
+        maximum_pending_balance_credit_counter: account
+            .maximum_pending_balance_credit_counter
+            .into(),
+    })
+}
+
+/// Fetch a confidential token account and decrypt it with the keys derived from its owner
+pub async fn get_confidential_balance(
+    rpc_client: &RpcClient,
+    token_account_owner: &Keypair,
+    token_account_pubkey: &Pubkey,
+) -> Result<ConfidentialBalance> {
+    let data = rpc_client.get_account_data(token_account_pubkey).await?;
+    let account = StateWithExtensionsOwned::<Account>::unpack(data)?;
+    let confidential_account = account
+        .get_extension::<ConfidentialTransferAccount>()
+        .map_err(|_| {
+            anyhow!(
+                "token account {} is not configured for confidential transfers",
+                token_account_pubkey
+            )
+        })?;
+
+    let (elgamal_keypair, aes_key) =
+        derive_confidential_keys(token_account_owner, token_account_pubkey)?;
+    decrypt_confidential_balance(confidential_account, elgamal_keypair.secret(), &aes_key)
+}
+
+/// ElGamal keypair of a mint auditor, derived from the auditor's signature over the mint
+/// address so it doesn't have to be stored separately
+pub fn auditor_elgamal_keypair(auditor: &Keypair, mint: &Pubkey) -> Result<ElGamalKeypair> {
+    ElGamalKeypair::new_from_signer(auditor, &mint.to_bytes())
+        .map_err(|e| anyhow!("Failed to create auditor ElGamal keypair: {}", e))
+}
+
+/// Set, rotate or (with `None`) remove the auditor ElGamal public key of a mint
+pub async fn update_confidential_mint_auditor(
+    rpc_client: Arc<RpcClient>,
+    authority: Arc<Keypair>,
+    mint: &Pubkey,
+    auditor_elgamal_pubkey: Option<&ElGamalPubkey>,
+) -> Result<()> {
+    let decimals = fetch_mint(&rpc_client, mint).await?.decimals;
+    let token = confidential_token(rpc_client, authority.clone(), mint, decimals);
+
+    // update_mint sets both fields, keep the current approval policy
+    let mint_state = token.get_mint_info().await?;
+    let auto_approve_new_accounts = mint_state
+        .get_extension::<ConfidentialTransferMint>()?
+        .auto_approve_new_accounts
+        .into();
+
+    let auditor_elgamal_pubkey = auditor_elgamal_pubkey.map(|pubkey| (*pubkey).into());
+    let update_signature = token
+        .confidential_transfer_update_mint(
+            &authority.pubkey(),       // Confidential transfer authority
+            auto_approve_new_accounts, // Unchanged approval policy
+            auditor_elgamal_pubkey,    // New auditor ElGamal public key
+            &[&authority],             // Signers
+        )
+        .await?;
+
+    println!("Update Mint Auditor Signature: {}", update_signature);
+    Ok(())
+}
+
+/// A confidential transfer as seen by the mint auditor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AuditedTransfer {
+    /// Position of the transfer among the transaction's top-level instructions
+    pub instruction_index: usize,
+    pub source: Pubkey,
+    pub destination: Pubkey,
+    /// `None` when the auditor key can't decrypt it, e.g. a transfer made before the
+    /// auditor was rotated or while the mint had no auditor
+    pub amount: Option<u64>,
+    pub with_fee: bool,
+}
+
+/// The audited transfers of one transaction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditedTransaction {
+    pub signature: Signature,
+    pub slot: u64,
+    pub transfers: Vec<AuditedTransfer>,
+}
+
+/// `None` for ciphertexts the key doesn't decrypt, and for the zeroed ones of a mint
+/// without auditor, which would decrypt to 0 under any key
+fn decrypt_auditor_amount(
+    auditor_elgamal_secret_key: &ElGamalSecretKey,
+    ciphertext_lo: &PodElGamalCiphertext,
+    ciphertext_hi: &PodElGamalCiphertext,
+) -> Option<u64> {
+    let is_zeroed = |ciphertext: &PodElGamalCiphertext| {
+        bytemuck::bytes_of(ciphertext).iter().all(|byte| *byte == 0)
+    };
+    if is_zeroed(ciphertext_lo) && is_zeroed(ciphertext_hi) {
+        return None;
+    }
+    let decrypt = |ciphertext: &PodElGamalCiphertext| -> Option<u64> {
+        let ciphertext: ElGamalCiphertext = (*ciphertext).try_into().ok()?;
+        auditor_elgamal_secret_key.decrypt_u32(&ciphertext)
+    };
+    let amount_lo = decrypt(ciphertext_lo)?;
+    let amount_hi = decrypt(ciphertext_hi)?;
+    Some(amount_lo + (amount_hi << TRANSFER_AMOUNT_LO_BITS))
+}
+
+/// Decrypt the auditor ciphertexts of the confidential transfers of `mint` among
+/// `instructions`
+///
+/// Only top-level instructions are looked at; transfers made through CPI aren't found.
+/// Transfers the key can't decrypt are kept with `amount: None`.
+pub fn decode_audited_transfers(
+    account_keys: &[Pubkey],
+    instructions: &[CompiledInstruction],
+    mint: &Pubkey,
+    auditor_elgamal_secret_key: &ElGamalSecretKey,
+) -> Vec<AuditedTransfer> {
+    let mut transfers = vec![];
+    for (instruction_index, instruction) in instructions.iter().enumerate() {
+        let key = |position: usize| -> Option<Pubkey> {
+            let index = *instruction.accounts.get(position)?;
+            account_keys.get(index as usize).copied()
+        };
+        if account_keys.get(instruction.program_id_index as usize) != Some(&token_2022_program_id())
+        {
+            continue;
+        }
+        let data = &instruction.data;
+        if !matches!(
+            TokenInstruction::unpack(data),
+            Ok(TokenInstruction::ConfidentialTransferExtension)
+        ) {
+            continue;
+        }
+        // Transfer and TransferWithFee both take source, mint, destination first
+        if key(1) != Some(*mint) {
+            continue;
+        }
+        let (Some(source), Some(destination)) = (key(0), key(2)) else {
+            continue;
+        };
+
+        let (ciphertext_lo, ciphertext_hi, with_fee) =
+            match decode_instruction_type::<ConfidentialTransferInstruction>(&data[1..]) {
+                Ok(ConfidentialTransferInstruction::Transfer) => {
+                    let Ok(transfer) =
+                        decode_instruction_data::<TransferInstructionData>(&data[1..])
+                    else {
+                        continue;
+                    };
+                    (
+                        transfer.transfer_amount_auditor_ciphertext_lo,
+                        transfer.transfer_amount_auditor_ciphertext_hi,
+                        false,
+                    )
+                }
+                Ok(ConfidentialTransferInstruction::TransferWithFee) => {
+                    let Ok(transfer) =
+                        decode_instruction_data::<TransferWithFeeInstructionData>(&data[1..])
+                    else {
+                        continue;
+                    };
+                    (
+                        transfer.transfer_amount_auditor_ciphertext_lo,
+                        transfer.transfer_amount_auditor_ciphertext_hi,
+                        true,
+                    )
+                }
+                _ => continue,
+            };
+
+        transfers.push(AuditedTransfer {
+            instruction_index,
+            source,
+            destination,
+            amount: decrypt_auditor_amount(
+                auditor_elgamal_secret_key,
+                &ciphertext_lo,
+                &ciphertext_hi,
+            ),
+            with_fee,
+        });
+    }
+    transfers
+}
+
+/// Fetch a transaction and decrypt the confidential transfers of `mint` in it
+pub async fn audit_transaction(
+    rpc_client: &RpcClient,
+    signature: &Signature,
+    mint: &Pubkey,
+    auditor_elgamal_keypair: &ElGamalKeypair,
+) -> Result<AuditedTransaction> {
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        commitment: Some(rpc_client.commitment()),
+        max_supported_transaction_version: Some(0),
+    };
+    let confirmed = rpc_client
+        .get_transaction_with_config(signature, config)
+        .await?;
+    let transaction = confirmed
+        .transaction
+        .transaction
+        .decode()
+        .ok_or_else(|| anyhow!("failed to decode transaction {}", signature))?;
+
+    // v0 transactions may load accounts from lookup tables, those come after the static keys
+    let mut account_keys = transaction.message.static_account_keys().to_vec();
+    if let Some(meta) = confirmed.transaction.meta
+        && let OptionSerializer::Some(loaded) = meta.loaded_addresses
+    {
+        for address in loaded.writable.iter().chain(loaded.readonly.iter()) {
+            account_keys.push(address.parse()?);
+        }
+    }
+
+    let transfers = decode_audited_transfers(
+        &account_keys,
+        transaction.message.instructions(),
+        mint,
+        auditor_elgamal_keypair.secret(),
+    );
+    Ok(AuditedTransaction {
+        signature: *signature,
+        slot: confirmed.slot,
+        transfers,
+    })
+}
+
+/// Audit the most recent `limit` transactions that reference `mint`, newest first
+pub async fn audit_mint_transfers(
+    rpc_client: &RpcClient,
+    mint: &Pubkey,
+    auditor_elgamal_keypair: &ElGamalKeypair,
+    limit: usize,
+) -> Result<Vec<AuditedTransaction>> {
+    let config = GetConfirmedSignaturesForAddress2Config {
+        limit: Some(limit),
+        ..GetConfirmedSignaturesForAddress2Config::default()
+    };
+    let statuses = rpc_client
+        .get_signatures_for_address_with_config(mint, config)
+        .await?;
+
+    let mut audited = vec![];
+    for status in statuses.into_iter().filter(|status| status.err.is_none()) {
+        let signature: Signature = status.signature.parse()?;
+        let transaction =
+            audit_transaction(rpc_client, &signature, mint, auditor_elgamal_keypair).await?;
+        if !transaction.transfers.is_empty() {
+            audited.push(transaction);
+        }
+    }
+    Ok(audited)
+}
+
+#[cfg(test)]
 pub mod test {
     use solana_sdk::native_token::LAMPORTS_PER_SOL;
 
     use crate::common;
+    use crate::config::ClusterConfig;
 
     use super::*;
+    use solana_sdk::message::Message;
+    use spl_token_2022::extension::confidential_transfer::instruction::transfer;
+    use spl_token_confidential_transfer_proof_generation::try_split_u64;
+
+    #[test]
+    fn test_decode_audited_transfers() {
+        let auditor = ElGamalKeypair::new_rand();
+        let mint = Pubkey::new_unique();
+        let source = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let context_account = Pubkey::new_unique();
+
+        let amount = 70_000_u64;
+        let (amount_lo, amount_hi) = try_split_u64(amount, TRANSFER_AMOUNT_LO_BITS).unwrap();
+        let transfer_instructions = transfer(
+            &token_2022_program_id(),
+            &source,
+            &mint,
+            &destination,
+            &AeKey::new_rand().encrypt(0).into(),
+            &auditor.pubkey().encrypt(amount_lo).into(),
+            &auditor.pubkey().encrypt(amount_hi).into(),
+            &owner,
+            &[],
+            ProofLocation::ContextStateAccount(&context_account),
+            ProofLocation::ContextStateAccount(&context_account),
+            ProofLocation::ContextStateAccount(&context_account),
+        )
+        .unwrap();
+        let message = Message::new(&transfer_instructions, Some(&owner));
+
+        let transfers = decode_audited_transfers(
+            &message.account_keys,
+            &message.instructions,
+            &mint,
+            auditor.secret(),
+        );
+        let audited = AuditedTransfer {
+            instruction_index: 0,
+            source,
+            destination,
+            amount: Some(amount),
+            with_fee: false,
+        };
+        assert_eq!(transfers, vec![audited]);
+
+        // transfers of other mints are skipped
+        let other_mint = Pubkey::new_unique();
+        let none = decode_audited_transfers(
+            &message.account_keys,
+            &message.instructions,
+            &other_mint,
+            auditor.secret(),
+        );
+        assert!(none.is_empty());
+
+        // a wrong key, e.g. of a rotated auditor, leaves the amount out
+        let wrong_key = decode_audited_transfers(
+            &message.account_keys,
+            &message.instructions,
+            &mint,
+            ElGamalKeypair::new_rand().secret(),
+        );
+        let undecrypted = AuditedTransfer {
+            amount: None,
+            ..audited
+        };
+        assert_eq!(wrong_key, vec![undecrypted]);
+
+        // so does a transfer on a mint without auditor, its ciphertexts are zeroed
+        let zeroed = PodElGamalCiphertext::default();
+        assert_eq!(
+            decrypt_auditor_amount(auditor.secret(), &zeroed, &zeroed),
+            None
+        );
+    }
+
+    #[test]
+    fn test_decrypt_confidential_balance() {
+        let elgamal_keypair = ElGamalKeypair::new_rand();
+        let aes_key = AeKey::new_rand();
+        // pending balance of 0x1_0005 split into its low 16 and high 48 bits
+        let account = ConfidentialTransferAccount {
+            elgamal_pubkey: (*elgamal_keypair.pubkey()).into(),
+            pending_balance_lo: elgamal_keypair.pubkey().encrypt(5_u64).into(),
+            pending_balance_hi: elgamal_keypair.pubkey().encrypt(1_u64).into(),
+            decryptable_available_balance: aes_key.encrypt(700).into(),
+            pending_balance_credit_counter: 2.into(),
+            maximum_pending_balance_credit_counter: 2.into(),
+            ..ConfidentialTransferAccount::default()
+        };
+
+        let balance =
+            decrypt_confidential_balance(&account, elgamal_keypair.secret(), &aes_key).unwrap();
+        assert_eq!(balance.available, 700);
+        assert_eq!((balance.pending_lo, balance.pending_hi), (5, 1));
+        assert_eq!(balance.pending, 0x1_0005);
+        assert!(balance.needs_apply());
+        assert!(balance.is_pending_full());
+
+        assert!(
+            decrypt_confidential_balance(&account, elgamal_keypair.secret(), &AeKey::new_rand())
+                .is_err()
+        );
+    }
 
     #[tokio::test]
     pub async fn test_create_confidential_mint() -> Result<()> {
-        let clent = common::get_rpc_client();
-        let authority = Arc::new(load_keypair()?);
+        let clent = common::get_rpc_client(&ClusterConfig::resolve()?);
+        let authority = Arc::new(common::get_local_key_pair()?);
         let mint = Keypair::new();
-        create_confidential_mint(Arc::new(clent), authority, &mint).await?;
+        create_confidential_mint(Arc::new(clent), authority, &mint, None).await?;
         Ok(())
     }
 
     #[tokio::test]
     pub async fn test_create_confidential_token_account() -> Result<()> {
-        let client = Arc::new(common::get_rpc_client());
-        let authority = Arc::new(load_keypair()?);
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let authority = Arc::new(common::get_local_key_pair()?);
         let mint = Keypair::new();
 
-        create_confidential_mint(Arc::clone(&client), Arc::clone(&authority), &mint).await?;
+        create_confidential_mint(Arc::clone(&client), Arc::clone(&authority), &mint, None).await?;
 
         let wallet = Arc::new(Keypair::new());
         common::airdrop2(Arc::clone(&client), &wallet.pubkey(), LAMPORTS_PER_SOL * 3).await?;
@@ -328,11 +1087,11 @@ pub mod test {
 
     // #[actix_rt::test]
     pub async fn test_transfer_public_balance_to_confidential_pending_balance() -> Result<()> {
-        let client = Arc::new(common::get_rpc_client());
-        let authority = Arc::new(load_keypair()?);
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let authority = Arc::new(common::get_local_key_pair()?);
         let mint = Keypair::new();
 
-        create_confidential_mint(Arc::clone(&client), Arc::clone(&authority), &mint).await?;
+        create_confidential_mint(Arc::clone(&client), Arc::clone(&authority), &mint, None).await?;
 
         let wallet = Arc::clone(&authority); //Arc::new(Keypair::new());
 
@@ -357,4 +1116,110 @@ pub mod test {
 
         Ok(())
     }
+
+    #[tokio::test]
+    pub async fn test_confidential_transfer_and_withdraw() -> Result<()> {
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let authority = Arc::new(common::get_local_key_pair()?);
+        let mint = Keypair::new();
+        let mint_pubkey = mint.pubkey();
+        let auditor = auditor_elgamal_keypair(&Keypair::new(), &mint_pubkey)?;
+
+        create_confidential_mint(
+            Arc::clone(&client),
+            Arc::clone(&authority),
+            &mint,
+            Some(auditor.pubkey()),
+        )
+        .await?;
+
+        let sender = Arc::new(Keypair::new());
+        let recipient = Arc::new(Keypair::new());
+        common::airdrop2(Arc::clone(&client), &sender.pubkey(), LAMPORTS_PER_SOL * 3).await?;
+        common::airdrop2(
+            Arc::clone(&client),
+            &recipient.pubkey(),
+            LAMPORTS_PER_SOL * 3,
+        )
+        .await?;
+        let sender_account = create_confidential_token_account(
+            Arc::clone(&client),
+            Arc::clone(&sender),
+            &mint_pubkey,
+        )
+        .await?;
+        let recipient_account = create_confidential_token_account(
+            Arc::clone(&client),
+            Arc::clone(&recipient),
+            &mint_pubkey,
+        )
+        .await?;
+
+        // 100 tokens are deposited and applied to the sender's available balance
+        transfer_public_balance_to_confidential_pending_balance(
+            Arc::clone(&client),
+            Arc::clone(&authority),
+            mint.insecure_clone(),
+            Arc::clone(&sender),
+            sender_account,
+        )
+        .await?;
+        transfer_confidential_pending_balance_to_available_balance(
+            Arc::clone(&client),
+            Arc::clone(&authority),
+            mint.insecure_clone(),
+            &sender,
+            &sender_account,
+        )
+        .await?;
+
+        let amount = UiAmount::parse_base_units("40", 9)?;
+        let transfer_signature = confidential_transfer(
+            Arc::clone(&client),
+            Arc::clone(&authority),
+            &mint_pubkey,
+            &sender,
+            &sender_account,
+            &recipient_account,
+            amount,
+        )
+        .await?;
+        let audited =
+            audit_transaction(&client, &transfer_signature, &mint_pubkey, &auditor).await?;
+        assert_eq!(audited.transfers.len(), 1);
+        assert_eq!(audited.transfers[0].amount, Some(amount));
+        assert_eq!(audited.transfers[0].destination, recipient_account);
+
+        let received = get_confidential_balance(&client, &recipient, &recipient_account).await?;
+        assert_eq!((received.available, received.pending), (0, amount));
+        assert!(received.needs_apply());
+
+        transfer_confidential_pending_balance_to_available_balance(
+            Arc::clone(&client),
+            Arc::clone(&authority),
+            mint,
+            &recipient,
+            &recipient_account,
+        )
+        .await?;
+        let applied = get_confidential_balance(&client, &recipient, &recipient_account).await?;
+        assert_eq!(applied.available, amount);
+        assert!(!applied.needs_apply());
+
+        withdraw_confidential_balance(
+            Arc::clone(&client),
+            Arc::clone(&authority),
+            &mint_pubkey,
+            &recipient,
+            &recipient_account,
+            amount,
+        )
+        .await?;
+
+        let recipient_state = StateWithExtensionsOwned::<Account>::unpack(
+            client.get_account_data(&recipient_account).await?,
+        )?;
+        assert_eq!(recipient_state.base.amount, amount);
+        Ok(())
+    }
 }
@@ -1,4 +1,3 @@
-use crate::common;
 use anyhow::Result;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::pubkey::Pubkey;
@@ -31,10 +30,10 @@ async fn create_mint_account(
     // Initialize mint instruction
     let initialize_mint_instruction = initialize_mint(
         &token_2022_program_id(),
-        &mint.pubkey(),          // mint address
-        authority_pubkey,        // mint authority
-        Some(&authority_pubkey), // freeze authority
-        9,                       // decimals
+        &mint.pubkey(),         // mint address
+        authority_pubkey,       // mint authority
+        Some(authority_pubkey), // freeze authority
+        9,                      // decimals
     )?;
 
     // Create transaction and add instructions
@@ -55,11 +54,15 @@ async fn create_mint_account(
     Ok(())
 }
 
+#[cfg(test)]
+#[allow(clippy::let_unit_value)]
 mod tests {
     use super::*;
+    use crate::common;
+    use crate::config::ClusterConfig;
     #[tokio::test]
     async fn test_create_mint_account() -> Result<()> {
-        let client = common::get_rpc_client();
+        let client = common::get_rpc_client(&ClusterConfig::resolve()?);
         let fee_payer = common::get_local_key_pair().unwrap();
         let mint = Keypair::new();
         let _x = create_mint_account(&client, &fee_payer, &mint, &fee_payer.pubkey()).await?;
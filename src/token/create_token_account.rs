@@ -1,3 +1,4 @@
+use crate::token::program::detect_token_program;
 use anyhow::Result;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::{
@@ -7,9 +8,7 @@ use solana_sdk::{
     system_instruction::create_account,
     transaction::Transaction,
 };
-use spl_token_2022::{
-    id as token_2022_program_id, instruction::initialize_account, state::Account,
-};
+use spl_token_2022::{instruction::initialize_account, state::Account};
 
 async fn crate_token_account(
     client: &RpcClient,
@@ -18,24 +17,27 @@ async fn crate_token_account(
     token_account: &Keypair,
     mint_address: &Pubkey,
 ) -> Result<Signature> {
+    // The token account must be owned by the same program as the mint
+    let program = detect_token_program(client, mint_address).await?;
+
     // Get token account size (in bytes)
     let token_account_space = Account::LEN;
     let token_account_rent = client
         .get_minimum_balance_for_rent_exemption(token_account_space)
         .await?;
 
-    // Instruction to create new account for token account (token 2022 program)
+    // Instruction to create new account for token account (owned by the mint's program)
     let create_token_account_instruction = create_account(
         &payer.pubkey(),            // payer
         &token_account.pubkey(),    // new account (token account)
         token_account_rent,         // lamports
         token_account_space as u64, // space
-        &token_2022_program_id(),   // program id
+        &program.id(),              // program id
     );
 
     // Instruction to initialize token account data
     let initialize_token_account_instruction = initialize_account(
-        &token_2022_program_id(),
+        &program.id(),
         &token_account.pubkey(), // account
         mint_address,            // mint
         owner_pubkey,            // owner
@@ -58,15 +60,17 @@ async fn crate_token_account(
     Ok(transaction_signature)
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
     use crate::common;
+    use crate::config::ClusterConfig;
     use crate::token::comm;
     use std::str::FromStr;
 
     #[tokio::test]
     async fn test_crate_token_account_for_other_wallet() -> Result<()> {
-        let client = common::get_rpc_client();
+        let client = common::get_rpc_client(&ClusterConfig::resolve()?);
         let payer = common::get_local_key_pair().unwrap();
         let owner = Keypair::new();
         let mint: Pubkey = Pubkey::from_str(comm::MINT_PUBKEY).unwrap();
@@ -85,7 +89,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_crate_token_account_for_myself() -> Result<()> {
-        let client = common::get_rpc_client();
+        let client = common::get_rpc_client(&ClusterConfig::resolve()?);
         let payer = common::get_local_key_pair().unwrap();
         let mint: Pubkey = Pubkey::from_str(comm::MINT_PUBKEY).unwrap();
         let token_account: Keypair = Keypair::new();
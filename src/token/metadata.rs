@@ -0,0 +1,301 @@
+use crate::token::mint_builder::{MintBuilder, MintExtension};
+use crate::token::service::TokenService;
+use anyhow::{Result, anyhow};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_instruction,
+    transaction::Transaction,
+};
+use spl_token_2022::{
+    extension::{
+        BaseStateWithExtensions, StateWithExtensionsOwned, metadata_pointer::MetadataPointer,
+    },
+    id as token_2022_program_id,
+    state::Mint,
+};
+use spl_token_metadata_interface::{instruction as metadata_instruction, state::TokenMetadata};
+
+pub use spl_token_metadata_interface::state::Field;
+
+/// Name, symbol, URI and extra key/value pairs of a new token
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetadataFields {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub additional_metadata: Vec<(String, String)>,
+}
+
+/// TokenMetadata stored in a Token-2022 mint
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MintMetadata {
+    pub mint: Pubkey,
+    /// `None` once the metadata was made immutable
+    pub update_authority: Option<Pubkey>,
+    /// Where the MetadataPointer points, the mint itself for metadata read from the mint
+    pub metadata_address: Option<Pubkey>,
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub additional_metadata: Vec<(String, String)>,
+}
+
+impl MintMetadata {
+    /// Value of an additional key
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.additional_metadata
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Decode the TokenMetadata of raw mint account data
+pub fn decode_metadata(data: &[u8]) -> Result<MintMetadata> {
+    let state = StateWithExtensionsOwned::<Mint>::unpack(data.to_vec())?;
+    let metadata = state
+        .get_variable_len_extension::<TokenMetadata>()
+        .map_err(|_| anyhow!("mint has no TokenMetadata extension"))?;
+    let metadata_address = state
+        .get_extension::<MetadataPointer>()
+        .ok()
+        .and_then(|pointer| Option::<Pubkey>::from(pointer.metadata_address));
+
+    Ok(MintMetadata {
+        mint: metadata.mint,
+        update_authority: metadata.update_authority.into(),
+        metadata_address,
+        name: metadata.name,
+        symbol: metadata.symbol,
+        uri: metadata.uri,
+        additional_metadata: metadata.additional_metadata,
+    })
+}
+
+pub async fn get_metadata(client: &RpcClient, mint_pubkey: &Pubkey) -> Result<MintMetadata> {
+    let data = client.get_account_data(mint_pubkey).await?;
+    decode_metadata(&data)
+}
+
+/// Create a Token-2022 mint whose MetadataPointer points at itself and holds `fields`
+///
+/// The service payer pays and becomes mint, freeze and metadata update authority.
+pub async fn create_mint_with_metadata(
+    service: &TokenService,
+    mint: &Keypair,
+    decimals: u8,
+    fields: MetadataFields,
+) -> Result<Signature> {
+    println!("----------------------begin create_mint_with_metadata------------------------------");
+    let authority = service.payer().pubkey();
+    let builder = MintBuilder::new(&authority, &mint.pubkey(), &authority, decimals)
+        .freeze_authority(Some(&authority))
+        .extension(MintExtension::MetadataPointer {
+            authority: Some(authority),
+            metadata_address: Some(mint.pubkey()),
+        })
+        .extension(MintExtension::TokenMetadata {
+            update_authority: authority,
+            name: fields.name,
+            symbol: fields.symbol,
+            uri: fields.uri,
+            additional_metadata: fields.additional_metadata,
+        });
+    let transaction_signature = service
+        .send_mint_builder(builder, mint, &[])
+        .await?
+        .signature;
+
+    println!("Mint Address: {}", mint.pubkey());
+    println!("Mint Transaction Signature: {}", transaction_signature);
+    println!("----------------------end create_mint_with_metadata------------------------------\n");
+    Ok(transaction_signature)
+}
+
+/// Set name, symbol, uri or an additional key, adding rent if the metadata grows
+pub async fn update_metadata_field(
+    client: &RpcClient,
+    update_authority: &Keypair,
+    mint_pubkey: &Pubkey,
+    field: Field,
+    value: &str,
+) -> Result<Signature> {
+    let account = client.get_account(mint_pubkey).await?;
+    let state = StateWithExtensionsOwned::<Mint>::unpack(account.data.clone())?;
+    let mut metadata = state.get_variable_len_extension::<TokenMetadata>()?;
+    metadata.update(field.clone(), value.to_string());
+    let new_len = state.try_get_new_account_len_for_variable_len_extension(&metadata)?;
+
+    let mut instructions = vec![];
+    // the token program reallocates the mint but doesn't fund the extra space
+    let rent = client
+        .get_minimum_balance_for_rent_exemption(new_len)
+        .await?;
+    if rent > account.lamports {
+        instructions.push(system_instruction::transfer(
+            &update_authority.pubkey(),
+            mint_pubkey,
+            rent - account.lamports,
+        ));
+    }
+    instructions.push(metadata_instruction::update_field(
+        &token_2022_program_id(),   // program id
+        mint_pubkey,                // metadata
+        &update_authority.pubkey(), // update authority
+        field,                      // field
+        value.to_string(),          // value
+    ));
+
+    send(client, update_authority, &instructions).await
+}
+
+/// Remove an additional key, fails if the key isn't there
+pub async fn remove_metadata_key(
+    client: &RpcClient,
+    update_authority: &Keypair,
+    mint_pubkey: &Pubkey,
+    key: &str,
+) -> Result<Signature> {
+    let instruction = metadata_instruction::remove_key(
+        &token_2022_program_id(),   // program id
+        mint_pubkey,                // metadata
+        &update_authority.pubkey(), // update authority
+        key.to_string(),            // key
+        false,                      // idempotent
+    );
+    send(client, update_authority, &[instruction]).await
+}
+
+/// Hand the metadata to `new_authority`, or make it immutable with `None`
+pub async fn update_metadata_authority(
+    client: &RpcClient,
+    update_authority: &Keypair,
+    mint_pubkey: &Pubkey,
+    new_authority: Option<&Pubkey>,
+) -> Result<Signature> {
+    let instruction = metadata_instruction::update_authority(
+        &token_2022_program_id(),           // program id
+        mint_pubkey,                        // metadata
+        &update_authority.pubkey(),         // current update authority
+        new_authority.copied().try_into()?, // new update authority
+    );
+    send(client, update_authority, &[instruction]).await
+}
+
+async fn send(
+    client: &RpcClient,
+    update_authority: &Keypair,
+    instructions: &[Instruction],
+) -> Result<Signature> {
+    let recent_blockhash = client.get_latest_blockhash().await?;
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&update_authority.pubkey()),
+        &[update_authority],
+        recent_blockhash,
+    );
+    let transaction_signature = client.send_and_confirm_transaction(&transaction).await?;
+    println!("Metadata Transaction Signature: {}", transaction_signature);
+    Ok(transaction_signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common;
+    use crate::config::ClusterConfig;
+    use solana_sdk::native_token::LAMPORTS_PER_SOL;
+    use spl_token_2022::extension::{
+        BaseStateWithExtensionsMut, ExtensionType, StateWithExtensionsMut,
+    };
+    use std::sync::Arc;
+
+    fn fields() -> MetadataFields {
+        MetadataFields {
+            name: "Example".to_string(),
+            symbol: "EXM".to_string(),
+            uri: "https://example.com/exm.json".to_string(),
+            additional_metadata: vec![("tier".to_string(), "gold".to_string())],
+        }
+    }
+
+    #[test]
+    fn test_decode_metadata() {
+        let mint = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let fields = fields();
+        let token_metadata = TokenMetadata {
+            update_authority: Some(authority).try_into().unwrap(),
+            mint,
+            name: fields.name.clone(),
+            symbol: fields.symbol.clone(),
+            uri: fields.uri.clone(),
+            additional_metadata: fields.additional_metadata.clone(),
+        };
+
+        let len =
+            ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::MetadataPointer])
+                .unwrap()
+                + token_metadata.tlv_size_of().unwrap();
+        let mut data = vec![0; len];
+        let mut state = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+        let pointer = state.init_extension::<MetadataPointer>(true).unwrap();
+        pointer.metadata_address = Some(mint).try_into().unwrap();
+        state
+            .init_variable_len_extension(&token_metadata, false)
+            .unwrap();
+        state.base = Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Mint::default()
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+
+        let metadata = decode_metadata(&data).unwrap();
+        assert_eq!(metadata.mint, mint);
+        assert_eq!(metadata.update_authority, Some(authority));
+        assert_eq!(metadata.metadata_address, Some(mint));
+        assert_eq!(metadata.symbol, "EXM");
+        assert_eq!(metadata.get("tier"), Some("gold"));
+        assert_eq!(metadata.get("missing"), None);
+    }
+
+    #[tokio::test]
+    async fn test_metadata_lifecycle() -> Result<()> {
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let authority = Arc::new(Keypair::new());
+        common::airdrop(&client, &authority, LAMPORTS_PER_SOL * 2).await?;
+        let service = TokenService::new(client.clone(), authority.clone());
+        let mint = Keypair::new();
+
+        create_mint_with_metadata(&service, &mint, 6, fields()).await?;
+        let metadata = get_metadata(&client, &mint.pubkey()).await?;
+        assert_eq!(metadata.name, "Example");
+        assert_eq!(metadata.metadata_address, Some(mint.pubkey()));
+
+        update_metadata_field(&client, &authority, &mint.pubkey(), Field::Name, "Renamed").await?;
+        update_metadata_field(
+            &client,
+            &authority,
+            &mint.pubkey(),
+            Field::Key("website".to_string()),
+            "https://example.com",
+        )
+        .await?;
+        remove_metadata_key(&client, &authority, &mint.pubkey(), "tier").await?;
+
+        let metadata = get_metadata(&client, &mint.pubkey()).await?;
+        assert_eq!(metadata.name, "Renamed");
+        assert_eq!(metadata.get("website"), Some("https://example.com"));
+        assert_eq!(metadata.get("tier"), None);
+
+        update_metadata_authority(&client, &authority, &mint.pubkey(), None).await?;
+        let metadata = get_metadata(&client, &mint.pubkey()).await?;
+        assert_eq!(metadata.update_authority, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,490 @@
+use crate::token::program::TokenProgram;
+use anyhow::{Result, anyhow};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, system_instruction::create_account};
+use spl_token_2022::{
+    extension::{
+        ExtensionType, default_account_state, group_pointer, interest_bearing_mint,
+        metadata_pointer, transfer_fee, transfer_hook,
+    },
+    instruction::{
+        initialize_mint, initialize_mint_close_authority, initialize_non_transferable_mint,
+        initialize_permanent_delegate,
+    },
+    state::{AccountState, Mint},
+};
+use spl_token_metadata_interface::state::{Field, TokenMetadata};
+
+/// A Token-2022 mint extension together with its initialization parameters
+#[derive(Clone, Debug, PartialEq)]
+pub enum MintExtension {
+    TransferFeeConfig {
+        transfer_fee_config_authority: Option<Pubkey>,
+        withdraw_withheld_authority: Option<Pubkey>,
+        transfer_fee_basis_points: u16,
+        maximum_fee: u64,
+    },
+    InterestBearingConfig {
+        rate_authority: Option<Pubkey>,
+        /// basis points per year
+        rate: i16,
+    },
+    MintCloseAuthority {
+        close_authority: Option<Pubkey>,
+    },
+    PermanentDelegate {
+        delegate: Pubkey,
+    },
+    NonTransferable,
+    DefaultAccountState {
+        state: AccountState,
+    },
+    MetadataPointer {
+        authority: Option<Pubkey>,
+        metadata_address: Option<Pubkey>,
+    },
+    /// Metadata stored in the mint itself, needs a `MetadataPointer` to the mint
+    TokenMetadata {
+        update_authority: Pubkey,
+        name: String,
+        symbol: String,
+        uri: String,
+        additional_metadata: Vec<(String, String)>,
+    },
+    TransferHook {
+        authority: Option<Pubkey>,
+        program_id: Option<Pubkey>,
+    },
+    GroupPointer {
+        authority: Option<Pubkey>,
+        group_address: Option<Pubkey>,
+    },
+}
+
+impl MintExtension {
+    pub fn extension_type(&self) -> ExtensionType {
+        match self {
+            MintExtension::TransferFeeConfig { .. } => ExtensionType::TransferFeeConfig,
+            MintExtension::InterestBearingConfig { .. } => ExtensionType::InterestBearingConfig,
+            MintExtension::MintCloseAuthority { .. } => ExtensionType::MintCloseAuthority,
+            MintExtension::PermanentDelegate { .. } => ExtensionType::PermanentDelegate,
+            MintExtension::NonTransferable => ExtensionType::NonTransferable,
+            MintExtension::DefaultAccountState { .. } => ExtensionType::DefaultAccountState,
+            MintExtension::MetadataPointer { .. } => ExtensionType::MetadataPointer,
+            MintExtension::TokenMetadata { .. } => ExtensionType::TokenMetadata,
+            MintExtension::TransferHook { .. } => ExtensionType::TransferHook,
+            MintExtension::GroupPointer { .. } => ExtensionType::GroupPointer,
+        }
+    }
+
+    // TokenMetadata is variable length and written after `initialize_mint`
+    fn is_fixed_size(&self) -> bool {
+        !matches!(self, MintExtension::TokenMetadata { .. })
+    }
+
+    /// Instruction that has to run before `initialize_mint`
+    fn pre_initialize_instruction(
+        &self,
+        program_id: &Pubkey,
+        mint: &Pubkey,
+    ) -> Result<Option<Instruction>> {
+        let instruction = match self {
+            MintExtension::TransferFeeConfig {
+                transfer_fee_config_authority,
+                withdraw_withheld_authority,
+                transfer_fee_basis_points,
+                maximum_fee,
+            } => transfer_fee::instruction::initialize_transfer_fee_config(
+                program_id,
+                mint,
+                transfer_fee_config_authority.as_ref(),
+                withdraw_withheld_authority.as_ref(),
+                *transfer_fee_basis_points,
+                *maximum_fee,
+            )?,
+            MintExtension::InterestBearingConfig {
+                rate_authority,
+                rate,
+            } => interest_bearing_mint::instruction::initialize(
+                program_id,
+                mint,
+                *rate_authority,
+                *rate,
+            )?,
+            MintExtension::MintCloseAuthority { close_authority } => {
+                initialize_mint_close_authority(program_id, mint, close_authority.as_ref())?
+            }
+            MintExtension::PermanentDelegate { delegate } => {
+                initialize_permanent_delegate(program_id, mint, delegate)?
+            }
+            MintExtension::NonTransferable => initialize_non_transferable_mint(program_id, mint)?,
+            MintExtension::DefaultAccountState { state } => {
+                default_account_state::instruction::initialize_default_account_state(
+                    program_id, mint, state,
+                )?
+            }
+            MintExtension::MetadataPointer {
+                authority,
+                metadata_address,
+            } => metadata_pointer::instruction::initialize(
+                program_id,
+                mint,
+                *authority,
+                *metadata_address,
+            )?,
+            MintExtension::TransferHook {
+                authority,
+                program_id: hook_program_id,
+            } => transfer_hook::instruction::initialize(
+                program_id,
+                mint,
+                *authority,
+                *hook_program_id,
+            )?,
+            MintExtension::GroupPointer {
+                authority,
+                group_address,
+            } => group_pointer::instruction::initialize(
+                program_id,
+                mint,
+                *authority,
+                *group_address,
+            )?,
+            MintExtension::TokenMetadata { .. } => return Ok(None),
+        };
+        Ok(Some(instruction))
+    }
+}
+
+/// Builds the instructions that create a mint with a set of extensions
+///
+/// The account is created with room for the fixed-size extensions; the lamports also cover
+/// the variable-size `TokenMetadata`, which the token program reallocates into when it is
+/// initialized after `initialize_mint`.
+#[derive(Clone, Debug)]
+pub struct MintBuilder {
+    program: TokenProgram,
+    payer: Pubkey,
+    mint: Pubkey,
+    mint_authority: Pubkey,
+    freeze_authority: Option<Pubkey>,
+    decimals: u8,
+    extensions: Vec<MintExtension>,
+}
+
+impl MintBuilder {
+    pub fn new(payer: &Pubkey, mint: &Pubkey, mint_authority: &Pubkey, decimals: u8) -> Self {
+        Self {
+            program: TokenProgram::Token2022,
+            payer: *payer,
+            mint: *mint,
+            mint_authority: *mint_authority,
+            freeze_authority: None,
+            decimals,
+            extensions: vec![],
+        }
+    }
+
+    /// SPL Token mints can't carry extensions, so this only makes sense without any
+    pub fn program(mut self, program: TokenProgram) -> Self {
+        self.program = program;
+        self
+    }
+
+    pub fn freeze_authority(mut self, freeze_authority: Option<&Pubkey>) -> Self {
+        self.freeze_authority = freeze_authority.copied();
+        self
+    }
+
+    pub fn extension(mut self, extension: MintExtension) -> Self {
+        self.extensions.push(extension);
+        self
+    }
+
+    pub fn extensions(mut self, extensions: impl IntoIterator<Item = MintExtension>) -> Self {
+        self.extensions.extend(extensions);
+        self
+    }
+
+    pub fn mint(&self) -> &Pubkey {
+        &self.mint
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.decimals
+    }
+
+    pub fn extension_types(&self) -> Vec<ExtensionType> {
+        self.extensions.iter().map(|e| e.extension_type()).collect()
+    }
+
+    /// Size of the account at creation, without the variable-size TokenMetadata
+    pub fn space(&self) -> Result<usize> {
+        let fixed: Vec<ExtensionType> = self
+            .extensions
+            .iter()
+            .filter(|e| e.is_fixed_size())
+            .map(|e| e.extension_type())
+            .collect();
+        Ok(ExtensionType::try_calculate_account_len::<Mint>(&fixed)?)
+    }
+
+    /// Size of the account once the TokenMetadata (if any) is written
+    pub fn final_space(&self) -> Result<usize> {
+        let mut space = self.space()?;
+        if let Some(metadata) = self.token_metadata()? {
+            space += metadata.tlv_size_of()?;
+        }
+        Ok(space)
+    }
+
+    pub async fn rent(&self, client: &RpcClient) -> Result<u64> {
+        Ok(client
+            .get_minimum_balance_for_rent_exemption(self.final_space()?)
+            .await?)
+    }
+
+    /// create_account, the extension initializers, initialize_mint, then TokenMetadata
+    pub fn instructions(&self, lamports: u64) -> Result<Vec<Instruction>> {
+        self.validate()?;
+        let program_id = self.program.id();
+
+        let mut instructions = vec![create_account(
+            &self.payer,
+            &self.mint,
+            lamports,
+            self.space()? as u64,
+            &program_id,
+        )];
+        for extension in &self.extensions {
+            if let Some(instruction) =
+                extension.pre_initialize_instruction(&program_id, &self.mint)?
+            {
+                instructions.push(instruction);
+            }
+        }
+        instructions.push(initialize_mint(
+            &program_id,
+            &self.mint,
+            &self.mint_authority,
+            self.freeze_authority.as_ref(),
+            self.decimals,
+        )?);
+        instructions.extend(self.metadata_instructions()?);
+        Ok(instructions)
+    }
+
+    pub fn payer(&self) -> &Pubkey {
+        &self.payer
+    }
+
+    fn validate(&self) -> Result<()> {
+        let types = self.extension_types();
+        if self.program == TokenProgram::SplToken && !types.is_empty() {
+            return Err(anyhow!("SPL Token mints don't support extensions"));
+        }
+        for (i, extension_type) in types.iter().enumerate() {
+            if types[..i].contains(extension_type) {
+                return Err(anyhow!("extension {:?} given twice", extension_type));
+            }
+        }
+        ExtensionType::check_for_invalid_mint_extension_combinations(&types)?;
+
+        if self.token_metadata()?.is_some() {
+            let points_to_mint = self.extensions.iter().any(|e| {
+                matches!(e, MintExtension::MetadataPointer { metadata_address: Some(address), .. }
+                    if *address == self.mint)
+            });
+            if !points_to_mint {
+                return Err(anyhow!(
+                    "TokenMetadata needs a MetadataPointer with the mint as metadata address"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn token_metadata(&self) -> Result<Option<TokenMetadata>> {
+        let Some(MintExtension::TokenMetadata {
+            update_authority,
+            name,
+            symbol,
+            uri,
+            additional_metadata,
+        }) = self
+            .extensions
+            .iter()
+            .find(|e| matches!(e, MintExtension::TokenMetadata { .. }))
+        else {
+            return Ok(None);
+        };
+        // the default pubkey encodes "no authority", metadata created without one can't be set
+        if *update_authority == Pubkey::default() {
+            return Err(anyhow!("TokenMetadata needs an update authority"));
+        }
+        Ok(Some(TokenMetadata {
+            update_authority: Some(*update_authority).try_into()?,
+            mint: self.mint,
+            name: name.clone(),
+            symbol: symbol.clone(),
+            uri: uri.clone(),
+            additional_metadata: additional_metadata.clone(),
+        }))
+    }
+
+    fn metadata_instructions(&self) -> Result<Vec<Instruction>> {
+        let Some(metadata) = self.token_metadata()? else {
+            return Ok(vec![]);
+        };
+        let update_authority = Option::<Pubkey>::from(metadata.update_authority).unwrap();
+        let program_id = self.program.id();
+
+        let mut instructions = vec![spl_token_metadata_interface::instruction::initialize(
+            &program_id,
+            &self.mint,
+            &update_authority,
+            &self.mint,
+            &self.mint_authority,
+            metadata.name,
+            metadata.symbol,
+            metadata.uri,
+        )];
+        for (key, value) in metadata.additional_metadata {
+            instructions.push(spl_token_metadata_interface::instruction::update_field(
+                &program_id,
+                &self.mint,
+                &update_authority,
+                Field::Key(key),
+                value,
+            ));
+        }
+        Ok(instructions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common;
+    use crate::config::ClusterConfig;
+    use crate::token::service::TokenService;
+    use solana_sdk::native_token::LAMPORTS_PER_SOL;
+    use solana_sdk::signature::{Keypair, Signer};
+    use spl_token_2022::extension::{BaseStateWithExtensions, StateWithExtensionsOwned};
+    use std::sync::Arc;
+
+    fn metadata_extensions(mint: &Pubkey, authority: &Pubkey) -> Vec<MintExtension> {
+        vec![
+            MintExtension::MetadataPointer {
+                authority: Some(*authority),
+                metadata_address: Some(*mint),
+            },
+            MintExtension::TokenMetadata {
+                update_authority: *authority,
+                name: "Example".to_string(),
+                symbol: "EXM".to_string(),
+                uri: "https://example.com/exm.json".to_string(),
+                additional_metadata: vec![("tier".to_string(), "gold".to_string())],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_instruction_order_and_space() {
+        let payer = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let builder = MintBuilder::new(&payer, &mint, &payer, 6)
+            .extension(MintExtension::TransferFeeConfig {
+                transfer_fee_config_authority: Some(payer),
+                withdraw_withheld_authority: Some(payer),
+                transfer_fee_basis_points: 50,
+                maximum_fee: 5_000,
+            })
+            .extension(MintExtension::MintCloseAuthority {
+                close_authority: Some(payer),
+            })
+            .extensions(metadata_extensions(&mint, &payer));
+
+        let expected_space = ExtensionType::try_calculate_account_len::<Mint>(&[
+            ExtensionType::TransferFeeConfig,
+            ExtensionType::MintCloseAuthority,
+            ExtensionType::MetadataPointer,
+        ])
+        .unwrap();
+        assert_eq!(builder.space().unwrap(), expected_space);
+        assert!(builder.final_space().unwrap() > expected_space);
+
+        let instructions = builder.instructions(1).unwrap();
+        // create_account, 3 extension initializers, initialize_mint, metadata init + 1 field
+        assert_eq!(instructions.len(), 7);
+        assert_eq!(instructions[0].program_id, solana_sdk::system_program::id());
+        let initialize_mint_ix =
+            initialize_mint(&spl_token_2022::id(), &mint, &payer, None, 6).unwrap();
+        assert_eq!(instructions[4], initialize_mint_ix);
+        assert!(
+            instructions[1..]
+                .iter()
+                .all(|ix| ix.program_id == spl_token_2022::id())
+        );
+    }
+
+    #[test]
+    fn test_invalid_combinations() {
+        let payer = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+
+        let duplicate = MintBuilder::new(&payer, &mint, &payer, 0)
+            .extension(MintExtension::NonTransferable)
+            .extension(MintExtension::NonTransferable);
+        assert!(duplicate.instructions(0).is_err());
+
+        let metadata_without_pointer = MintBuilder::new(&payer, &mint, &payer, 0)
+            .extension(metadata_extensions(&mint, &payer).remove(1));
+        assert!(metadata_without_pointer.instructions(0).is_err());
+
+        let metadata_without_authority = MintBuilder::new(&payer, &mint, &payer, 0)
+            .extensions(metadata_extensions(&mint, &Pubkey::default()));
+        assert!(metadata_without_authority.instructions(0).is_err());
+
+        let spl_token_with_extension = MintBuilder::new(&payer, &mint, &payer, 0)
+            .program(TokenProgram::SplToken)
+            .extension(MintExtension::NonTransferable);
+        assert!(spl_token_with_extension.instructions(0).is_err());
+
+        let plain = MintBuilder::new(&payer, &mint, &payer, 0).program(TokenProgram::SplToken);
+        assert_eq!(plain.instructions(0).unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_create_mint_with_extensions() -> Result<()> {
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let payer = Arc::new(Keypair::new());
+        common::airdrop(&client, &payer, LAMPORTS_PER_SOL * 2).await?;
+        let service = TokenService::new(client.clone(), payer.clone());
+        let mint = Keypair::new();
+
+        let builder = MintBuilder::new(&payer.pubkey(), &mint.pubkey(), &payer.pubkey(), 9)
+            .freeze_authority(Some(&payer.pubkey()))
+            .extension(MintExtension::InterestBearingConfig {
+                rate_authority: Some(payer.pubkey()),
+                rate: 500,
+            })
+            .extension(MintExtension::DefaultAccountState {
+                state: AccountState::Initialized,
+            })
+            .extension(MintExtension::PermanentDelegate {
+                delegate: payer.pubkey(),
+            })
+            .extensions(metadata_extensions(&mint.pubkey(), &payer.pubkey()));
+        service.send_mint_builder(builder, &mint, &[]).await?;
+
+        let data = client.get_account_data(&mint.pubkey()).await?;
+        let state = StateWithExtensionsOwned::<Mint>::unpack(data)?;
+        let types = state.get_extension_types()?;
+        assert!(types.contains(&ExtensionType::InterestBearingConfig));
+        assert!(types.contains(&ExtensionType::TokenMetadata));
+        let metadata = state.get_variable_len_extension::<TokenMetadata>()?;
+        assert_eq!(metadata.symbol, "EXM");
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+use crate::token::program::TokenProgram;
+use anyhow::Result;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use spl_token_2022::{extension::StateWithExtensionsOwned, state::Mint};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Mint state together with the program that owns it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MintInfo {
+    pub program: TokenProgram,
+    pub state: Mint,
+}
+
+/// Fetch and unpack a mint of either token program, with or without extensions
+pub async fn fetch_mint_info(client: &RpcClient, mint: &Pubkey) -> Result<MintInfo> {
+    let account = client.get_account(mint).await?;
+    let program = TokenProgram::of_account(mint, &account)?;
+    let state = StateWithExtensionsOwned::<Mint>::unpack(account.data)?.base;
+    Ok(MintInfo { program, state })
+}
+
+pub async fn fetch_mint(client: &RpcClient, mint: &Pubkey) -> Result<Mint> {
+    Ok(fetch_mint_info(client, mint).await?.state)
+}
+
+/// Caches mint state so decimals and the owning program are fetched once per mint
+///
+/// Decimals and the owner never change after `initialize_mint`; the cached supply and
+/// authorities do, so use `refresh` when those matter.
+#[derive(Debug, Default)]
+pub struct MintCache {
+    mints: RwLock<HashMap<Pubkey, MintInfo>>,
+}
+
+impl MintCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn get(&self, client: &RpcClient, mint: &Pubkey) -> Result<MintInfo> {
+        if let Some(info) = self.mints.read().unwrap().get(mint) {
+            return Ok(*info);
+        }
+        self.refresh(client, mint).await
+    }
+
+    pub async fn decimals(&self, client: &RpcClient, mint: &Pubkey) -> Result<u8> {
+        Ok(self.get(client, mint).await?.state.decimals)
+    }
+
+    pub async fn program(&self, client: &RpcClient, mint: &Pubkey) -> Result<TokenProgram> {
+        Ok(self.get(client, mint).await?.program)
+    }
+
+    /// Fetch the mint again and replace the cached state
+    pub async fn refresh(&self, client: &RpcClient, mint: &Pubkey) -> Result<MintInfo> {
+        let info = fetch_mint_info(client, mint).await?;
+        self.insert(*mint, info);
+        Ok(info)
+    }
+
+    pub fn insert(&self, mint: Pubkey, info: MintInfo) {
+        self.mints.write().unwrap().insert(mint, info);
+    }
+
+    pub fn invalidate(&self, mint: &Pubkey) {
+        self.mints.write().unwrap().remove(mint);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common;
+    use crate::config::ClusterConfig;
+    use crate::token::comm;
+
+    #[tokio::test]
+    async fn test_mint_cache() -> Result<()> {
+        let client = common::get_rpc_client(&ClusterConfig::resolve()?);
+        let mint = Pubkey::from_str_const(comm::MINT_PUBKEY);
+        let cache = MintCache::new();
+
+        let decimals = cache.decimals(&client, &mint).await?;
+        assert_eq!(decimals, fetch_mint(&client, &mint).await?.decimals);
+        assert_eq!(
+            cache.program(&client, &mint).await?,
+            TokenProgram::from_owner(&client.get_account(&mint).await?.owner)?
+        );
+
+        cache.invalidate(&mint);
+        assert_eq!(cache.decimals(&client, &mint).await?, decimals);
+        Ok(())
+    }
+}
@@ -1,12 +1,12 @@
+use crate::token::program::detect_token_program;
 use anyhow::Result;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::{
-    program_pack::Pack,
     signature::{Keypair, Signer},
     transaction::Transaction,
 };
-use spl_token_2022::{id as token_2022_program_id, instruction::mint_to};
+use spl_token_2022::instruction::mint_to;
 
 async fn mint_token(
     client: &RpcClient,
@@ -15,11 +15,14 @@ async fn mint_token(
     mint_authority: &Keypair,
     amount: u64,
 ) -> Result<()> {
+    // The mint may belong to SPL Token or Token-2022
+    let program = detect_token_program(client, mint_pubkey).await?;
+
     // Get the latest blockhash for the mint transaction
     let recent_blockhash = client.get_latest_blockhash().await?;
     // Create mint_to instruction to mint tokens to the associated token account
     let mint_to_instruction = mint_to(
-        &token_2022_program_id(),
+        &program.id(),
         mint_pubkey,                 // mint
         account_pubkey,              // destination
         &mint_authority.pubkey(),    // authority
@@ -51,11 +54,12 @@ async fn mint_token(
 mod tests {
     use super::*;
     use crate::common;
+    use crate::config::ClusterConfig;
     use crate::token::comm;
 
     #[tokio::test]
     async fn test_mint_token() -> Result<()> {
-        let client = common::get_rpc_client();
+        let client = common::get_rpc_client(&ClusterConfig::resolve()?);
         let mint_pubkey = Pubkey::from_str_const(comm::MINT_PUBKEY);
         let mint_authority = common::get_local_key_pair().unwrap();
 
@@ -0,0 +1,94 @@
+use anyhow::{Result, anyhow};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{account::Account, pubkey::Pubkey};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use std::fmt;
+
+/// The two token programs a mint can belong to
+///
+/// The base instructions and account layouts of Token-2022 are a superset of SPL Token,
+/// so the `spl_token_2022` instruction builders and `StateWithExtensions` unpacking work for
+/// both as long as the right program id is passed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TokenProgram {
+    SplToken,
+    Token2022,
+}
+
+impl TokenProgram {
+    pub fn id(&self) -> Pubkey {
+        match self {
+            TokenProgram::SplToken => spl_token::id(),
+            TokenProgram::Token2022 => spl_token_2022::id(),
+        }
+    }
+
+    /// Map an account owner to its token program
+    pub fn from_owner(owner: &Pubkey) -> Result<Self> {
+        if *owner == spl_token::id() {
+            Ok(TokenProgram::SplToken)
+        } else if *owner == spl_token_2022::id() {
+            Ok(TokenProgram::Token2022)
+        } else {
+            Err(anyhow!("{} is not a token program", owner))
+        }
+    }
+
+    /// Token program owning a mint or token account that was already fetched
+    pub fn of_account(address: &Pubkey, account: &Account) -> Result<Self> {
+        Self::from_owner(&account.owner)
+            .map_err(|_| anyhow!("account {} is not owned by a token program", address))
+    }
+
+    pub fn associated_token_address(&self, owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+        get_associated_token_address_with_program_id(owner, mint, &self.id())
+    }
+}
+
+impl fmt::Display for TokenProgram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenProgram::SplToken => write!(f, "spl-token"),
+            TokenProgram::Token2022 => write!(f, "spl-token-2022"),
+        }
+    }
+}
+
+/// Read the owner of a mint (or token account) to find out which program it belongs to
+pub async fn detect_token_program(client: &RpcClient, address: &Pubkey) -> Result<TokenProgram> {
+    let account = client.get_account(address).await?;
+    TokenProgram::of_account(address, &account)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::system_program;
+
+    #[test]
+    fn test_from_owner() {
+        assert_eq!(
+            TokenProgram::from_owner(&spl_token::id()).unwrap(),
+            TokenProgram::SplToken
+        );
+        assert_eq!(
+            TokenProgram::from_owner(&spl_token_2022::id()).unwrap(),
+            TokenProgram::Token2022
+        );
+        assert!(TokenProgram::from_owner(&system_program::id()).is_err());
+    }
+
+    #[test]
+    fn test_associated_token_address_differs_per_program() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        assert_ne!(
+            TokenProgram::SplToken.associated_token_address(&owner, &mint),
+            TokenProgram::Token2022.associated_token_address(&owner, &mint)
+        );
+        assert_eq!(
+            TokenProgram::SplToken.associated_token_address(&owner, &mint),
+            spl_associated_token_account::get_associated_token_address(&owner, &mint)
+        );
+    }
+}
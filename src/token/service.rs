@@ -0,0 +1,591 @@
+use anyhow::{Result, bail};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+};
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use spl_token_2022::{
+    extension::StateWithExtensionsOwned,
+    instruction::{
+        AuthorityType, approve_checked, burn_checked, close_account, freeze_account, mint_to,
+        revoke, set_authority, thaw_account, transfer_checked,
+    },
+    state::{Account, Mint},
+};
+use std::sync::Arc;
+
+use crate::memo::{Memo, requires_memo};
+use crate::sender::TransactionSender;
+use crate::token::amount::UiAmount;
+use crate::token::mint_builder::{MintBuilder, MintExtension};
+use crate::token::mint_cache::MintCache;
+use crate::token::program::{TokenProgram, detect_token_program};
+
+/// Associated token account returned by `TokenService::create_ata`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AtaAccount {
+    pub address: Pubkey,
+    /// `None` when the account already existed and nothing was sent
+    pub signature: Option<Signature>,
+}
+
+/// Mint returned by `TokenService::create_mint`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CreatedMint {
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub signature: Signature,
+}
+
+/// SPL Token and Token-2022 operations paid for by one fee payer
+///
+/// The program is looked up from the owner of the mint (or token account) each call
+/// touches, so one service handles mints of both programs.
+pub struct TokenService {
+    client: Arc<RpcClient>,
+    payer: Arc<Keypair>,
+    mints: MintCache,
+    mint_program: TokenProgram,
+    sender: TransactionSender,
+}
+
+impl TokenService {
+    pub fn new(client: Arc<RpcClient>, payer: Arc<Keypair>) -> Self {
+        Self {
+            sender: TransactionSender::new(client.clone()),
+            client,
+            payer,
+            mints: MintCache::new(),
+            mint_program: TokenProgram::Token2022,
+        }
+    }
+
+    /// Replace the default sender, e.g. to change the retry policy
+    pub fn with_sender(mut self, sender: TransactionSender) -> Self {
+        self.sender = sender;
+        self
+    }
+
+    /// Program used by `create_mint`, Token-2022 unless set otherwise
+    pub fn with_mint_program(mut self, program: TokenProgram) -> Self {
+        self.mint_program = program;
+        self
+    }
+
+    pub fn client(&self) -> &Arc<RpcClient> {
+        &self.client
+    }
+
+    pub fn payer(&self) -> &Arc<Keypair> {
+        &self.payer
+    }
+
+    /// Program owning `mint`, cached together with the mint state
+    pub async fn token_program(&self, mint: &Pubkey) -> Result<TokenProgram> {
+        self.mints.program(&self.client, mint).await
+    }
+
+    /// Program owning a token account (or any other token program account)
+    pub async fn account_program(&self, address: &Pubkey) -> Result<TokenProgram> {
+        detect_token_program(&self.client, address).await
+    }
+
+    pub async fn get_associated_token_address(
+        &self,
+        owner: &Pubkey,
+        mint: &Pubkey,
+    ) -> Result<Pubkey> {
+        let program = self.token_program(mint).await?;
+        Ok(program.associated_token_address(owner, mint))
+    }
+
+    /// Current on-chain state of the mint, also refreshes the cached copy
+    pub async fn get_mint(&self, mint: &Pubkey) -> Result<Mint> {
+        Ok(self.mints.refresh(&self.client, mint).await?.state)
+    }
+
+    /// Decimals of the mint, fetched once and cached afterwards
+    pub async fn decimals(&self, mint: &Pubkey) -> Result<u8> {
+        self.mints.decimals(&self.client, mint).await
+    }
+
+    /// Convert a human readable amount such as "1.25" into base units of `mint`
+    pub async fn to_base_units(&self, mint: &Pubkey, amount: &UiAmount) -> Result<u64> {
+        Ok(amount.to_base_units(self.decimals(mint).await?)?)
+    }
+
+    pub async fn to_ui_amount(&self, mint: &Pubkey, amount: u64) -> Result<UiAmount> {
+        Ok(UiAmount::from_base_units(
+            amount,
+            self.decimals(mint).await?,
+        )?)
+    }
+
+    pub async fn get_token_account(&self, account: &Pubkey) -> Result<Account> {
+        let info = self.client.get_account(account).await?;
+        TokenProgram::of_account(account, &info)?;
+        Ok(StateWithExtensionsOwned::<Account>::unpack(info.data)?.base)
+    }
+
+    pub async fn create_mint(
+        &self,
+        mint: &Keypair,
+        mint_authority: &Pubkey,
+        freeze_authority: Option<&Pubkey>,
+        decimals: u8,
+    ) -> Result<CreatedMint> {
+        let builder = MintBuilder::new(
+            &self.payer.pubkey(),
+            &mint.pubkey(),
+            mint_authority,
+            decimals,
+        )
+        .program(self.mint_program)
+        .freeze_authority(freeze_authority);
+        self.send_mint_builder(builder, mint, &[]).await
+    }
+
+    /// Create a Token-2022 mint with `extensions`, `signers` must hold the mint authority
+    /// when TokenMetadata is among them
+    pub async fn create_mint_with_extensions(
+        &self,
+        mint: &Keypair,
+        mint_authority: &Pubkey,
+        freeze_authority: Option<&Pubkey>,
+        decimals: u8,
+        extensions: Vec<MintExtension>,
+        signers: &[&Keypair],
+    ) -> Result<CreatedMint> {
+        let builder = MintBuilder::new(
+            &self.payer.pubkey(),
+            &mint.pubkey(),
+            mint_authority,
+            decimals,
+        )
+        .freeze_authority(freeze_authority)
+        .extensions(extensions);
+        self.send_mint_builder(builder, mint, signers).await
+    }
+
+    /// Create the mint described by `builder`, which must be paid for by this service's payer
+    ///
+    /// `signers` must hold the mint authority when the mint gets TokenMetadata.
+    pub async fn send_mint_builder(
+        &self,
+        builder: MintBuilder,
+        mint: &Keypair,
+        signers: &[&Keypair],
+    ) -> Result<CreatedMint> {
+        if *builder.payer() != self.payer.pubkey() {
+            bail!(
+                "the mint builder is paid for by {}, not the service payer",
+                builder.payer()
+            );
+        }
+        let rent = builder.rent(&self.client).await?;
+        let instructions = builder.instructions(rent)?;
+
+        let mut all_signers = vec![mint];
+        all_signers.extend_from_slice(signers);
+        let signature = self.send(&instructions, &all_signers).await?;
+
+        Ok(CreatedMint {
+            mint: mint.pubkey(),
+            decimals: builder.decimals(),
+            signature,
+        })
+    }
+
+    /// Create the associated token account of `owner` unless it already exists
+    pub async fn create_ata(&self, owner: &Pubkey, mint: &Pubkey) -> Result<AtaAccount> {
+        let program = self.token_program(mint).await?;
+        let address = program.associated_token_address(owner, mint);
+        if self.account_exists(&address).await? {
+            return Ok(AtaAccount {
+                address,
+                signature: None,
+            });
+        }
+
+        let instruction = create_associated_token_account_idempotent(
+            &self.payer.pubkey(),
+            owner,
+            mint,
+            &program.id(),
+        );
+        let signature = self.send(&[instruction], &[]).await?;
+
+        Ok(AtaAccount {
+            address,
+            signature: Some(signature),
+        })
+    }
+
+    pub async fn mint_to(
+        &self,
+        mint: &Pubkey,
+        destination: &Pubkey,
+        mint_authority: &Keypair,
+        amount: u64,
+    ) -> Result<Signature> {
+        let instruction = mint_to(
+            &self.token_program(mint).await?.id(),
+            mint,
+            destination,
+            &mint_authority.pubkey(),
+            &[],
+            amount,
+        )?;
+        self.send(&[instruction], &[mint_authority]).await
+    }
+
+    pub async fn mint_to_ui(
+        &self,
+        mint: &Pubkey,
+        destination: &Pubkey,
+        mint_authority: &Keypair,
+        amount: &UiAmount,
+    ) -> Result<Signature> {
+        let amount = self.to_base_units(mint, amount).await?;
+        self.mint_to(mint, destination, mint_authority, amount)
+            .await
+    }
+
+    pub async fn transfer(
+        &self,
+        source: &Pubkey,
+        mint: &Pubkey,
+        destination: &Pubkey,
+        owner: &Keypair,
+        amount: u64,
+    ) -> Result<Signature> {
+        self.transfer_via(&self.sender, source, mint, destination, owner, amount)
+            .await
+    }
+
+    /// `transfer` with `memo` attached, signed by `owner`
+    pub async fn transfer_with_memo(
+        &self,
+        source: &Pubkey,
+        mint: &Pubkey,
+        destination: &Pubkey,
+        owner: &Keypair,
+        amount: u64,
+        memo: &str,
+    ) -> Result<Signature> {
+        let memo = Memo::new(memo).signed_by(owner.pubkey());
+        let sender = self.sender.clone().with_memo(Some(memo));
+        self.transfer_via(&sender, source, mint, destination, owner, amount)
+            .await
+    }
+
+    async fn transfer_via(
+        &self,
+        sender: &TransactionSender,
+        source: &Pubkey,
+        mint: &Pubkey,
+        destination: &Pubkey,
+        owner: &Keypair,
+        amount: u64,
+    ) -> Result<Signature> {
+        let decimals = self.decimals(mint).await?;
+        let program = self.token_program(mint).await?;
+        // fail before sending rather than with the program's bare NoMemo error
+        if program == TokenProgram::Token2022
+            && sender.memo().is_none()
+            && requires_memo(&self.client, destination).await?
+        {
+            bail!("{} requires a memo on incoming transfers", destination);
+        }
+        let instruction = transfer_checked(
+            &program.id(),
+            source,
+            mint,
+            destination,
+            &owner.pubkey(),
+            &[],
+            amount,
+            decimals,
+        )?;
+        self.send_via(sender, &[instruction], &[owner]).await
+    }
+
+    pub async fn transfer_ui(
+        &self,
+        source: &Pubkey,
+        mint: &Pubkey,
+        destination: &Pubkey,
+        owner: &Keypair,
+        amount: &UiAmount,
+    ) -> Result<Signature> {
+        let amount = self.to_base_units(mint, amount).await?;
+        self.transfer(source, mint, destination, owner, amount)
+            .await
+    }
+
+    pub async fn burn(
+        &self,
+        account: &Pubkey,
+        mint: &Pubkey,
+        owner: &Keypair,
+        amount: u64,
+    ) -> Result<Signature> {
+        let decimals = self.decimals(mint).await?;
+        let instruction = burn_checked(
+            &self.token_program(mint).await?.id(),
+            account,
+            mint,
+            &owner.pubkey(),
+            &[],
+            amount,
+            decimals,
+        )?;
+        self.send(&[instruction], &[owner]).await
+    }
+
+    pub async fn burn_ui(
+        &self,
+        account: &Pubkey,
+        mint: &Pubkey,
+        owner: &Keypair,
+        amount: &UiAmount,
+    ) -> Result<Signature> {
+        let amount = self.to_base_units(mint, amount).await?;
+        self.burn(account, mint, owner, amount).await
+    }
+
+    pub async fn approve(
+        &self,
+        source: &Pubkey,
+        mint: &Pubkey,
+        delegate: &Pubkey,
+        owner: &Keypair,
+        amount: u64,
+    ) -> Result<Signature> {
+        let decimals = self.decimals(mint).await?;
+        let instruction = approve_checked(
+            &self.token_program(mint).await?.id(),
+            source,
+            mint,
+            delegate,
+            &owner.pubkey(),
+            &[],
+            amount,
+            decimals,
+        )?;
+        self.send(&[instruction], &[owner]).await
+    }
+
+    pub async fn revoke(&self, source: &Pubkey, owner: &Keypair) -> Result<Signature> {
+        let program = self.account_program(source).await?;
+        let instruction = revoke(&program.id(), source, &owner.pubkey(), &[])?;
+        self.send(&[instruction], &[owner]).await
+    }
+
+    pub async fn freeze(
+        &self,
+        account: &Pubkey,
+        mint: &Pubkey,
+        freeze_authority: &Keypair,
+    ) -> Result<Signature> {
+        let instruction = freeze_account(
+            &self.token_program(mint).await?.id(),
+            account,
+            mint,
+            &freeze_authority.pubkey(),
+            &[],
+        )?;
+        self.send(&[instruction], &[freeze_authority]).await
+    }
+
+    pub async fn thaw(
+        &self,
+        account: &Pubkey,
+        mint: &Pubkey,
+        freeze_authority: &Keypair,
+    ) -> Result<Signature> {
+        let instruction = thaw_account(
+            &self.token_program(mint).await?.id(),
+            account,
+            mint,
+            &freeze_authority.pubkey(),
+            &[],
+        )?;
+        self.send(&[instruction], &[freeze_authority]).await
+    }
+
+    /// Close an empty token account and send its rent to `destination`
+    pub async fn close_account(
+        &self,
+        account: &Pubkey,
+        destination: &Pubkey,
+        owner: &Keypair,
+    ) -> Result<Signature> {
+        let instruction = close_account(
+            &self.account_program(account).await?.id(),
+            account,
+            destination,
+            &owner.pubkey(),
+            &[],
+        )?;
+        self.send(&[instruction], &[owner]).await
+    }
+
+    /// `owned` is a mint or a token account, `None` removes the authority for good
+    pub async fn set_authority(
+        &self,
+        owned: &Pubkey,
+        new_authority: Option<&Pubkey>,
+        authority_type: AuthorityType,
+        current_authority: &Keypair,
+    ) -> Result<Signature> {
+        let instruction = set_authority(
+            &self.account_program(owned).await?.id(),
+            owned,
+            new_authority,
+            authority_type,
+            &current_authority.pubkey(),
+            &[],
+        )?;
+        self.send(&[instruction], &[current_authority]).await
+    }
+
+    async fn account_exists(&self, address: &Pubkey) -> Result<bool> {
+        let account = self
+            .client
+            .get_account_with_commitment(address, self.client.commitment())
+            .await?
+            .value;
+        Ok(account.is_some())
+    }
+
+    /// Sign with the payer plus `signers` (duplicates of the payer are dropped) and send
+    async fn send(&self, instructions: &[Instruction], signers: &[&Keypair]) -> Result<Signature> {
+        self.send_via(&self.sender, instructions, signers).await
+    }
+
+    async fn send_via(
+        &self,
+        sender: &TransactionSender,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<Signature> {
+        let mut all_signers: Vec<&Keypair> = vec![&self.payer];
+        for signer in signers {
+            if !all_signers.iter().any(|s| s.pubkey() == signer.pubkey()) {
+                all_signers.push(signer);
+            }
+        }
+
+        let outcome = sender
+            .send(instructions, &self.payer.pubkey(), &all_signers)
+            .await?;
+        Ok(outcome.signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common;
+    use crate::config::ClusterConfig;
+    use solana_sdk::native_token::LAMPORTS_PER_SOL;
+    use solana_sdk::program_option::COption;
+    use spl_token_2022::state::AccountState;
+
+    async fn token_service_lifecycle(program: TokenProgram) -> Result<()> {
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let payer = Arc::new(Keypair::new());
+        common::airdrop(&client, &payer, LAMPORTS_PER_SOL * 2).await?;
+        let service =
+            TokenService::new(Arc::clone(&client), Arc::clone(&payer)).with_mint_program(program);
+
+        let mint = Keypair::new();
+        let created = service
+            .create_mint(&mint, &payer.pubkey(), Some(&payer.pubkey()), 9)
+            .await?;
+        assert_eq!(created.mint, mint.pubkey());
+        assert_eq!(service.token_program(&mint.pubkey()).await?, program);
+
+        let wallet = Keypair::new();
+        let source = service.create_ata(&payer.pubkey(), &mint.pubkey()).await?;
+        let dest = service.create_ata(&wallet.pubkey(), &mint.pubkey()).await?;
+        assert!(source.signature.is_some());
+        // creating it again is a no-op
+        let again = service.create_ata(&wallet.pubkey(), &mint.pubkey()).await?;
+        assert_eq!(again.address, dest.address);
+        assert!(again.signature.is_none());
+
+        service
+            .mint_to(&mint.pubkey(), &source.address, &payer, 1_000)
+            .await?;
+        service
+            .transfer(&source.address, &mint.pubkey(), &dest.address, &payer, 400)
+            .await?;
+        // 9 decimals, so "0.0000001" is 100 base units
+        service
+            .transfer_ui(
+                &source.address,
+                &mint.pubkey(),
+                &dest.address,
+                &payer,
+                &"0.0000001".parse()?,
+            )
+            .await?;
+        service
+            .burn(&source.address, &mint.pubkey(), &payer, 100)
+            .await?;
+        assert_eq!(
+            service.get_token_account(&source.address).await?.amount,
+            400
+        );
+        assert_eq!(service.get_token_account(&dest.address).await?.amount, 500);
+        assert_eq!(service.get_mint(&mint.pubkey()).await?.supply, 900);
+
+        service
+            .approve(
+                &source.address,
+                &mint.pubkey(),
+                &wallet.pubkey(),
+                &payer,
+                50,
+            )
+            .await?;
+        let account = service.get_token_account(&source.address).await?;
+        assert_eq!(account.delegate, COption::Some(wallet.pubkey()));
+        service.revoke(&source.address, &payer).await?;
+
+        service
+            .freeze(&dest.address, &mint.pubkey(), &payer)
+            .await?;
+        let account = service.get_token_account(&dest.address).await?;
+        assert_eq!(account.state, AccountState::Frozen);
+        service.thaw(&dest.address, &mint.pubkey(), &payer).await?;
+
+        service
+            .burn(&source.address, &mint.pubkey(), &payer, 400)
+            .await?;
+        service
+            .close_account(&source.address, &payer.pubkey(), &payer)
+            .await?;
+
+        service
+            .set_authority(&mint.pubkey(), None, AuthorityType::FreezeAccount, &payer)
+            .await?;
+        let mint_state = service.get_mint(&mint.pubkey()).await?;
+        assert_eq!(mint_state.freeze_authority, COption::None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_token_service_lifecycle_token_2022() -> Result<()> {
+        token_service_lifecycle(TokenProgram::Token2022).await
+    }
+
+    #[tokio::test]
+    async fn test_token_service_lifecycle_spl_token() -> Result<()> {
+        token_service_lifecycle(TokenProgram::SplToken).await
+    }
+}
@@ -1,23 +1,38 @@
-use anyhow::{Ok, Result};
-use solana_client::nonblocking::rpc_client::RpcClient;
+use crate::sender::TransactionSender;
+use crate::token::amount::UiAmount;
+use crate::token::mint_cache::fetch_mint_info;
+use crate::token::program::{TokenProgram, detect_token_program};
+use anyhow::{Ok, Result, anyhow};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
 use solana_sdk::{
     program_pack::Pack,
     pubkey::Pubkey,
-    signature::{Keypair, Signer},
+    signature::{Keypair, Signature, Signer},
     system_instruction::create_account,
-    transaction::Transaction,
 };
 use spl_associated_token_account::{
     get_associated_token_address_with_program_id, instruction::create_associated_token_account,
 };
 use spl_token_2022::{
+    extension::{
+        BaseStateWithExtensions, StateWithExtensionsOwned,
+        transfer_fee::{
+            TransferFeeAmount, TransferFeeConfig, instruction as transfer_fee_instruction,
+        },
+    },
     id as token_2022_program_id,
     instruction::{initialize_mint, mint_to, transfer_checked},
-    state::Mint,
+    state::{Account, Mint},
 };
+use std::fmt;
 
 async fn only_create_account(
-    client: &RpcClient,
+    sender: &TransactionSender,
     authority: &Keypair,
     account: &Keypair,
     owner: &Pubkey,
@@ -26,7 +41,8 @@ async fn only_create_account(
 
     // Get default mint account size (in bytes), no extensions enabled
     let mint_space = Mint::LEN;
-    let mint_rent = client
+    let mint_rent = sender
+        .client()
         .get_minimum_balance_for_rent_exemption(mint_space)
         .await?;
 
@@ -39,18 +55,15 @@ async fn only_create_account(
         owner,               // program id
     );
 
-    let recent_blockhash = client.get_latest_blockhash().await?;
-
-    // Create transaction and add instructions
-    let transaction = Transaction::new_signed_with_payer(
-        &[create_account_instruction],
-        Some(&authority.pubkey()),
-        &[&authority, &account],
-        recent_blockhash,
-    );
-
     // Send and confirm transaction
-    let transaction_signature = client.send_and_confirm_transaction(&transaction).await?;
+    let transaction_signature = sender
+        .send(
+            &[create_account_instruction],
+            &authority.pubkey(),
+            &[authority, account],
+        )
+        .await?
+        .signature;
     println!("create account : {}", &account.pubkey());
     println!("authority account : {}", &authority.pubkey());
     println!(
@@ -62,14 +75,15 @@ async fn only_create_account(
 }
 
 async fn create_mint_account(
-    client: &RpcClient,
+    sender: &TransactionSender,
     authority: &Keypair,
     mint: &Keypair,
 ) -> Result<()> {
     println!("----------------------begin create_mint_account------------------------------");
     // Get default mint account size (in bytes), no extensions enabled
     let mint_space = Mint::LEN;
-    let mint_rent = client
+    let mint_rent = sender
+        .client()
         .get_minimum_balance_for_rent_exemption(mint_space)
         .await?;
 
@@ -91,18 +105,15 @@ async fn create_mint_account(
         2,                         // decimals
     )?;
 
-    let recent_blockhash = client.get_latest_blockhash().await?;
-
-    // Create transaction and add instructions
-    let transaction = Transaction::new_signed_with_payer(
-        &[create_account_instruction, initialize_mint_instruction],
-        Some(&authority.pubkey()),
-        &[&authority, &mint],
-        recent_blockhash,
-    );
-
     // Send and confirm transaction
-    let transaction_signature = client.send_and_confirm_transaction(&transaction).await?;
+    let transaction_signature = sender
+        .send(
+            &[create_account_instruction, initialize_mint_instruction],
+            &authority.pubkey(),
+            &[authority, mint],
+        )
+        .await?
+        .signature;
 
     println!(
         "Mint account transaction signature: {}",
@@ -113,18 +124,19 @@ async fn create_mint_account(
 }
 
 async fn mint_to_ata(
-    client: &RpcClient,
+    sender: &TransactionSender,
     mint_pubkey: &Pubkey,
     authority: &Keypair,
     account_pubkey: &Pubkey,
     amount: u64,
 ) -> Result<()> {
     println!("----------------------begin mint_to_ata------------------------------");
-    // Get the latest blockhash for the mint transaction
-    let recent_blockhash = client.get_latest_blockhash().await?;
+    // The mint may belong to SPL Token or Token-2022
+    let program = detect_token_program(sender.client(), mint_pubkey).await?;
+
     // Create mint_to instruction to mint tokens to the associated token account
     let mint_to_instruction = mint_to(
-        &token_2022_program_id(),
+        &program.id(),
         mint_pubkey,            // mint
         account_pubkey,         // destination
         &authority.pubkey(),    // authority
@@ -132,16 +144,11 @@ async fn mint_to_ata(
         amount,                 // amount
     )?;
 
-    // Create transaction for minting tokens
-    let transaction = Transaction::new_signed_with_payer(
-        &[mint_to_instruction],
-        Some(&authority.pubkey()),
-        &[&authority],
-        recent_blockhash,
-    );
-
     // Send and confirm transaction
-    let transaction_signature = client.send_and_confirm_transaction(&transaction).await?;
+    let transaction_signature = sender
+        .send(&[mint_to_instruction], &authority.pubkey(), &[authority])
+        .await?
+        .signature;
     println!(
         "Successfully minted {} tokens to the associated token account",
         amount
@@ -151,35 +158,35 @@ async fn mint_to_ata(
     Ok(())
 }
 
-async fn create_ata(client: &RpcClient, wallet: &Keypair, mint_pubkey: &Pubkey) -> Result<Pubkey> {
+async fn create_ata(
+    sender: &TransactionSender,
+    wallet: &Keypair,
+    mint_pubkey: &Pubkey,
+) -> Result<Pubkey> {
     println!("----------------------begin create_ata------------------------------");
+    // The ATA address depends on the program owning the mint
+    let program = detect_token_program(sender.client(), mint_pubkey).await?;
+
     // Calculate the associated token account address for fee_payer
     let token_address: Pubkey = get_associated_token_address_with_program_id(
-        &wallet.pubkey(),         // owner
-        mint_pubkey,              // mint
-        &token_2022_program_id(), // program_id
+        &wallet.pubkey(), // owner
+        mint_pubkey,      // mint
+        &program.id(),    // program_id
     );
 
     // Instruction to create associated token account for fee_payer
     let create_ata_instruction = create_associated_token_account(
-        &wallet.pubkey(),         // funding address
-        &wallet.pubkey(),         // wallet address
-        mint_pubkey,              // mint address
-        &token_2022_program_id(), // program id
-    );
-
-    let recent_blockhash = client.get_latest_blockhash().await?;
-
-    // Create transaction and add instructions
-    let transaction = Transaction::new_signed_with_payer(
-        &[create_ata_instruction],
-        Some(&wallet.pubkey()),
-        &[&wallet],
-        recent_blockhash,
+        &wallet.pubkey(), // funding address
+        &wallet.pubkey(), // wallet address
+        mint_pubkey,      // mint address
+        &program.id(),    // program id
     );
 
     // Send and confirm transaction
-    let transaction_signature = client.send_and_confirm_transaction(&transaction).await?;
+    let transaction_signature = sender
+        .send(&[create_ata_instruction], &wallet.pubkey(), &[wallet])
+        .await?
+        .signature;
 
     println!(
         "create_ata transaction signature: {}",
@@ -190,7 +197,7 @@ async fn create_ata(client: &RpcClient, wallet: &Keypair, mint_pubkey: &Pubkey)
 }
 
 async fn token_transfer(
-    client: &RpcClient,
+    sender: &TransactionSender,
     authority: &Keypair,
     mint_pubkey: &Pubkey,
     source_pubkey: &Pubkey,
@@ -198,31 +205,27 @@ async fn token_transfer(
     amount: u64,
 ) -> Result<()> {
     println!("----------------------begin token_transfer------------------------------\n");
-    // Get the latest blockhash for the transfer transaction
-    let recent_blockhash = client.get_latest_blockhash().await?;
+    // transfer_checked fails unless the decimals match the mint,
+    // and the mint may belong to SPL Token or Token-2022
+    let mint_info = fetch_mint_info(sender.client(), mint_pubkey).await?;
 
     // Create transfer_checked instruction to send tokens from source to destination
     let transfer_instruction = transfer_checked(
-        &token_2022_program_id(), // program id
+        &mint_info.program.id(),  // program id
         source_pubkey,            // source
         mint_pubkey,              // mint
         destination_pubkey,       // destination
         &authority.pubkey(),      // owner of source
         &[&authority.pubkey()],   // signers
         amount,                   // amount
-        2,                        // decimals
+        mint_info.state.decimals, // decimals
     )?;
 
-    // Create transaction for transferring tokens
-    let transaction = Transaction::new_signed_with_payer(
-        &[transfer_instruction],
-        Some(&authority.pubkey()),
-        &[&authority],
-        recent_blockhash,
-    );
-
     // Send and confirm transaction
-    let transaction_signature = client.send_and_confirm_transaction(&transaction).await?;
+    let transaction_signature = sender
+        .send(&[transfer_instruction], &authority.pubkey(), &[authority])
+        .await?
+        .signature;
 
     println!(
         "token_transfer_example transaction signature: {:?}",
@@ -232,7 +235,378 @@ async fn token_transfer(
     Ok(())
 }
 
-async fn init_mint(client: &RpcClient, authority: &Keypair, mint: &Keypair) -> Result<()> {
+/// Accounts per harvest/withdraw instruction, keeps the transaction under the size limit
+const FEE_ACCOUNTS_PER_INSTRUCTION: usize = 20;
+
+/// Amounts of a transfer on a mint with a TransferFeeConfig, in base units
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeTransfer {
+    /// debited from the source
+    pub gross: u64,
+    /// withheld in the destination account
+    pub fee: u64,
+    /// credited to the destination
+    pub net: u64,
+    pub decimals: u8,
+    pub signature: Signature,
+}
+
+impl fmt::Display for FeeTransfer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // decimals UiAmount can't represent fall back to base units
+        let ui = |amount| match UiAmount::from_base_units(amount, self.decimals) {
+            Result::Ok(ui) => ui.to_string(),
+            Err(_) => amount.to_string(),
+        };
+        write!(
+            f,
+            "gross {} = net {} + fee {}",
+            ui(self.gross),
+            ui(self.net),
+            ui(self.fee)
+        )
+    }
+}
+
+/// One fee instruction: the token accounts it covers, the fees they held and its transaction
+#[derive(Debug)]
+pub struct FeeChunk {
+    pub sources: Vec<Pubkey>,
+    pub amount: u64,
+    pub result: Result<Signature>,
+}
+
+/// Withheld fees moved to the fee receiver
+#[derive(Debug)]
+pub struct FeeWithdrawal {
+    /// Fees of the chunks whose transaction confirmed
+    pub amount: u64,
+    pub chunks: Vec<FeeChunk>,
+}
+
+impl FeeWithdrawal {
+    fn from_chunks(chunks: Vec<FeeChunk>) -> Self {
+        let amount = chunks
+            .iter()
+            .filter(|chunk| chunk.result.is_ok())
+            .map(|chunk| chunk.amount)
+            .sum();
+        Self { amount, chunks }
+    }
+
+    /// Signatures of the confirmed chunks
+    pub fn signatures(&self) -> Vec<Signature> {
+        self.chunks
+            .iter()
+            .filter_map(|chunk| chunk.result.as_ref().ok().copied())
+            .collect()
+    }
+
+    /// Chunks whose transaction failed, their fees are still withheld
+    pub fn failed(&self) -> impl Iterator<Item = &FeeChunk> {
+        self.chunks.iter().filter(|chunk| chunk.result.is_err())
+    }
+}
+
+/// Fee charged on `amount` in `epoch`, zero when the mint has no TransferFeeConfig
+fn epoch_fee(config: Option<&TransferFeeConfig>, epoch: u64, amount: u64) -> Result<u64> {
+    match config {
+        Some(config) => config
+            .calculate_epoch_fee(epoch, amount)
+            .ok_or_else(|| anyhow!("transfer fee of {} overflows", amount)),
+        None => Ok(0),
+    }
+}
+
+async fn fetch_fee_mint(
+    client: &RpcClient,
+    mint_pubkey: &Pubkey,
+) -> Result<(TokenProgram, u8, Option<TransferFeeConfig>)> {
+    let account = client.get_account(mint_pubkey).await?;
+    let program = TokenProgram::of_account(mint_pubkey, &account)?;
+    let state = StateWithExtensionsOwned::<Mint>::unpack(account.data)?;
+    let config = state.get_extension::<TransferFeeConfig>().ok().copied();
+    Ok((program, state.base.decimals, config))
+}
+
+/// Fee the mint charges for `amount` in the current epoch
+pub async fn expected_transfer_fee(
+    client: &RpcClient,
+    mint_pubkey: &Pubkey,
+    amount: u64,
+) -> Result<u64> {
+    let (_, _, config) = fetch_fee_mint(client, mint_pubkey).await?;
+    let epoch = client.get_epoch_info().await?.epoch;
+    epoch_fee(config.as_ref(), epoch, amount)
+}
+
+/// Like `token_transfer`, but asserts the fee so the transfer fails if the schedule changed
+pub async fn transfer_checked_with_fee(
+    sender: &TransactionSender,
+    authority: &Keypair,
+    mint_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    amount: u64,
+) -> Result<FeeTransfer> {
+    println!("----------------------begin transfer_checked_with_fee------------------------------");
+    let (program, decimals, config) = fetch_fee_mint(sender.client(), mint_pubkey).await?;
+    if program != TokenProgram::Token2022 {
+        return Err(anyhow!(
+            "mint {} has no transfer fees, it is {}",
+            mint_pubkey,
+            program
+        ));
+    }
+
+    // The fee schedule switches to the newer fee at a given epoch
+    let epoch = sender.client().get_epoch_info().await?.epoch;
+    let fee = epoch_fee(config.as_ref(), epoch, amount)?;
+
+    let transfer_instruction = transfer_fee_instruction::transfer_checked_with_fee(
+        &program.id(),          // program id
+        source_pubkey,          // source
+        mint_pubkey,            // mint
+        destination_pubkey,     // destination
+        &authority.pubkey(),    // owner of source
+        &[&authority.pubkey()], // signers
+        amount,                 // amount
+        decimals,               // decimals
+        fee,                    // expected fee
+    )?;
+
+    let signature = sender
+        .send(&[transfer_instruction], &authority.pubkey(), &[authority])
+        .await?
+        .signature;
+
+    let report = FeeTransfer {
+        gross: amount,
+        fee,
+        net: amount - fee,
+        decimals,
+        signature,
+    };
+    println!("transfer_checked_with_fee: {}", report);
+    println!("transaction signature: {}", signature);
+    println!("----------------------end transfer_checked_with_fee------------------------------\n");
+    Ok(report)
+}
+
+/// Token accounts of `mint_pubkey` that hold withheld fees, with the withheld amount
+pub async fn accounts_with_withheld_fees(
+    client: &RpcClient,
+    mint_pubkey: &Pubkey,
+) -> Result<Vec<(Pubkey, u64)>> {
+    let config = RpcProgramAccountsConfig {
+        // the mint is the first field of a token account
+        filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+            0,
+            mint_pubkey.as_ref(),
+        ))]),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let accounts = client
+        .get_program_accounts_with_config(&token_2022_program_id(), config)
+        .await?;
+
+    let mut withheld = vec![];
+    for (address, account) in accounts {
+        let Result::Ok(state) = StateWithExtensionsOwned::<Account>::unpack(account.data) else {
+            continue;
+        };
+        if let Result::Ok(fee_amount) = state.get_extension::<TransferFeeAmount>() {
+            let amount = u64::from(fee_amount.withheld_amount);
+            if amount > 0 {
+                withheld.push((address, amount));
+            }
+        }
+    }
+    Ok(withheld)
+}
+
+/// Withheld fee of each of `sources`, in the same order
+async fn withheld_amounts(client: &RpcClient, sources: &[Pubkey]) -> Result<Vec<u64>> {
+    let mut amounts = Vec::with_capacity(sources.len());
+    // getMultipleAccounts takes at most 100 keys
+    for chunk in sources.chunks(100) {
+        for (source, data) in chunk.iter().zip(client.get_multiple_accounts(chunk).await?) {
+            let data = data.ok_or_else(|| anyhow!("token account {} not found", source))?;
+            let state = StateWithExtensionsOwned::<Account>::unpack(data.data)?;
+            amounts.push(match state.get_extension::<TransferFeeAmount>() {
+                Result::Ok(fee_amount) => u64::from(fee_amount.withheld_amount),
+                Err(_) => 0,
+            });
+        }
+    }
+    Ok(amounts)
+}
+
+/// Move withheld fees from token accounts into the mint, anyone may do this
+///
+/// Every chunk of `FEE_ACCOUNTS_PER_INSTRUCTION` accounts is sent on its own, a failed
+/// chunk doesn't stop the later ones and is reported with its error.
+pub async fn harvest_withheld_tokens_to_mint(
+    sender: &TransactionSender,
+    payer: &Keypair,
+    mint_pubkey: &Pubkey,
+    sources: &[Pubkey],
+) -> Result<Vec<FeeChunk>> {
+    println!(
+        "----------------------begin harvest_withheld_tokens_to_mint------------------------------"
+    );
+    let amounts = withheld_amounts(sender.client(), sources).await?;
+
+    let mut chunks = vec![];
+    for (chunk, amounts) in sources
+        .chunks(FEE_ACCOUNTS_PER_INSTRUCTION)
+        .zip(amounts.chunks(FEE_ACCOUNTS_PER_INSTRUCTION))
+    {
+        let accounts: Vec<&Pubkey> = chunk.iter().collect();
+        let harvest_instruction = transfer_fee_instruction::harvest_withheld_tokens_to_mint(
+            &token_2022_program_id(), // program id
+            mint_pubkey,              // mint
+            &accounts,                // token accounts
+        )?;
+
+        let result = sender
+            .send(&[harvest_instruction], &payer.pubkey(), &[payer])
+            .await
+            .map(|outcome| outcome.signature)
+            .map_err(anyhow::Error::from);
+        match &result {
+            Result::Ok(signature) => println!(
+                "harvested {} accounts, signature: {}",
+                chunk.len(),
+                signature
+            ),
+            Err(err) => println!("harvesting {} accounts failed: {}", chunk.len(), err),
+        }
+        chunks.push(FeeChunk {
+            sources: chunk.to_vec(),
+            amount: amounts.iter().sum(),
+            result,
+        });
+    }
+    println!(
+        "----------------------end harvest_withheld_tokens_to_mint------------------------------\n"
+    );
+    Ok(chunks)
+}
+
+/// Withdraw the fees harvested into the mint to `destination_pubkey`
+pub async fn withdraw_withheld_tokens_from_mint(
+    sender: &TransactionSender,
+    withdraw_authority: &Keypair,
+    mint_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+) -> Result<FeeWithdrawal> {
+    println!(
+        "----------------------begin withdraw_withheld_tokens_from_mint------------------------------"
+    );
+    let (_, _, config) = fetch_fee_mint(sender.client(), mint_pubkey).await?;
+    let config =
+        config.ok_or_else(|| anyhow!("mint {} has no transfer fee config", mint_pubkey))?;
+    let amount = u64::from(config.withheld_amount);
+
+    let withdraw_instruction = transfer_fee_instruction::withdraw_withheld_tokens_from_mint(
+        &token_2022_program_id(),        // program id
+        mint_pubkey,                     // mint
+        destination_pubkey,              // fee receiver
+        &withdraw_authority.pubkey(),    // withdraw withheld authority
+        &[&withdraw_authority.pubkey()], // signers
+    )?;
+
+    let signature = sender
+        .send(
+            &[withdraw_instruction],
+            &withdraw_authority.pubkey(),
+            &[withdraw_authority],
+        )
+        .await?
+        .signature;
+    println!("withdrew {} from mint, signature: {}", amount, signature);
+    println!(
+        "----------------------end withdraw_withheld_tokens_from_mint------------------------------\n"
+    );
+    Ok(FeeWithdrawal {
+        amount,
+        chunks: vec![FeeChunk {
+            sources: vec![],
+            amount,
+            result: Ok(signature),
+        }],
+    })
+}
+
+/// Withdraw withheld fees straight from token accounts to `destination_pubkey`
+///
+/// Chunks are sent like in `harvest_withheld_tokens_to_mint`, the returned amount only
+/// counts the fees of the chunks that confirmed.
+pub async fn withdraw_withheld_tokens_from_accounts(
+    sender: &TransactionSender,
+    withdraw_authority: &Keypair,
+    mint_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    sources: &[Pubkey],
+) -> Result<FeeWithdrawal> {
+    println!(
+        "----------------------begin withdraw_withheld_tokens_from_accounts------------------------------"
+    );
+    let amounts = withheld_amounts(sender.client(), sources).await?;
+
+    let mut chunks = vec![];
+    for (chunk, amounts) in sources
+        .chunks(FEE_ACCOUNTS_PER_INSTRUCTION)
+        .zip(amounts.chunks(FEE_ACCOUNTS_PER_INSTRUCTION))
+    {
+        let accounts: Vec<&Pubkey> = chunk.iter().collect();
+        let withdraw_instruction =
+            transfer_fee_instruction::withdraw_withheld_tokens_from_accounts(
+                &token_2022_program_id(),        // program id
+                mint_pubkey,                     // mint
+                destination_pubkey,              // fee receiver
+                &withdraw_authority.pubkey(),    // withdraw withheld authority
+                &[&withdraw_authority.pubkey()], // signers
+                &accounts,                       // token accounts
+            )?;
+
+        let result = sender
+            .send(
+                &[withdraw_instruction],
+                &withdraw_authority.pubkey(),
+                &[withdraw_authority],
+            )
+            .await
+            .map(|outcome| outcome.signature)
+            .map_err(anyhow::Error::from);
+        match &result {
+            Result::Ok(signature) => println!(
+                "withdrew from {} accounts, signature: {}",
+                chunk.len(),
+                signature
+            ),
+            Err(err) => println!("withdrawing from {} accounts failed: {}", chunk.len(), err),
+        }
+        chunks.push(FeeChunk {
+            sources: chunk.to_vec(),
+            amount: amounts.iter().sum(),
+            result,
+        });
+    }
+    let withdrawal = FeeWithdrawal::from_chunks(chunks);
+    println!("withdrew {} in total", withdrawal.amount);
+    println!(
+        "----------------------end withdraw_withheld_tokens_from_accounts------------------------------\n"
+    );
+    Ok(withdrawal)
+}
+
+async fn init_mint(sender: &TransactionSender, authority: &Keypair, mint: &Keypair) -> Result<()> {
     println!("----------------------begin init_mint------------------------------\n");
     // Instruction to initialize mint account data
     let initialize_mint_instruction = initialize_mint(
@@ -243,34 +617,38 @@ async fn init_mint(client: &RpcClient, authority: &Keypair, mint: &Keypair) -> R
         2,                         // decimals
     )?;
 
-    let recent_blockhash = client.get_latest_blockhash().await?;
-
-    // Create transaction and add instructions
-    let transaction = Transaction::new_signed_with_payer(
-        &[initialize_mint_instruction],
-        Some(&authority.pubkey()),
-        &[&authority],
-        recent_blockhash,
-    );
-
     // Send and confirm transaction
-    let transaction_signature = client.send_and_confirm_transaction(&transaction).await?;
+    let transaction_signature = sender
+        .send(
+            &[initialize_mint_instruction],
+            &authority.pubkey(),
+            &[authority],
+        )
+        .await?
+        .signature;
 
     println!("Mint init transaction signature: {}", transaction_signature);
     println!("----------------------end init_mint------------------------------\n");
     Ok(())
 }
 
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
     use crate::common;
+    use crate::config::ClusterConfig;
+    use crate::token::mint_builder::{MintBuilder, MintExtension};
+    use crate::token::service::TokenService;
     use solana_sdk::{native_token::LAMPORTS_PER_SOL, program_option::COption};
-    use spl_token_2022::state::Account;
+    use spl_token_2022::extension::transfer_fee::TransferFee;
     use spl_token_2022::state::AccountState;
+    use std::sync::Arc;
 
     #[tokio::test]
     async fn test_create_mint_account() -> Result<()> {
-        let client = common::get_rpc_client();
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let sender = TransactionSender::new(client.clone());
         let authority = Keypair::new(); //common::get_local_key_pair().unwrap();
         let mint = Keypair::new();
 
@@ -279,7 +657,7 @@ mod tests {
         println!("authority account is : {:?}", &authority.pubkey());
 
         let before_balance = client.get_balance(&authority.pubkey()).await?;
-        create_mint_account(&client, &authority, &mint).await?;
+        create_mint_account(&sender, &authority, &mint).await?;
         let after_balance = client.get_balance(&authority.pubkey()).await?;
 
         println!(
@@ -321,18 +699,19 @@ mod tests {
 
     #[tokio::test]
     async fn test_create_ata() -> Result<()> {
-        let client = common::get_rpc_client();
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let sender = TransactionSender::new(client.clone());
         let authority = common::get_local_key_pair().unwrap();
         let mint: Keypair = Keypair::new();
         println!("authority account : {:?}", &authority.pubkey());
         common::airdrop(&client, &authority, LAMPORTS_PER_SOL * 10).await?;
-        create_mint_account(&client, &authority, &mint).await?;
+        create_mint_account(&sender, &authority, &mint).await?;
         println!("create mint account : {:?}", &mint.pubkey());
 
         let wallet = Keypair::new();
         common::airdrop(&client, &wallet, LAMPORTS_PER_SOL * 3).await?;
         println!("wallet is : {:?}\n", wallet.pubkey());
-        let ata = create_ata(&client, &wallet, &mint.pubkey()).await?;
+        let ata = create_ata(&sender, &wallet, &mint.pubkey()).await?;
         println!("ata is {:?}", &ata);
 
         let ata_data = client.get_account_data(&ata).await?;
@@ -353,20 +732,21 @@ mod tests {
 
     #[tokio::test]
     async fn test_mint_to_ata() -> Result<()> {
-        let client = common::get_rpc_client();
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let sender = TransactionSender::new(client.clone());
         let authority = common::get_local_key_pair().unwrap();
         let mint = Keypair::new();
         println!("authority account is : {:?}", &authority.pubkey());
-        create_mint_account(&client, &authority, &mint).await?;
+        create_mint_account(&sender, &authority, &mint).await?;
         println!("mint accout is : {:?}", &mint.pubkey());
 
         let wallet = Keypair::new();
         common::airdrop(&client, &wallet, LAMPORTS_PER_SOL * 2).await?;
-        let ata = create_ata(&client, &wallet, &mint.pubkey()).await?;
+        let ata = create_ata(&sender, &wallet, &mint.pubkey()).await?;
         println!("ata is {:?}", &ata);
 
         let mint_amount = 1000;
-        mint_to_ata(&client, &mint.pubkey(), &authority, &ata, mint_amount).await?;
+        mint_to_ata(&sender, &mint.pubkey(), &authority, &ata, mint_amount).await?;
 
         let ata_data = client.get_account_data(&ata).await?;
         let ata_data_account = Account::unpack_from_slice(&ata_data).unwrap();
@@ -377,12 +757,13 @@ mod tests {
 
     #[tokio::test]
     async fn test_token_transfer() -> Result<()> {
-        let client = common::get_rpc_client();
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let sender = TransactionSender::new(client.clone());
 
         let authority = common::get_local_key_pair().unwrap();
         let mint = Keypair::new();
         println!("authority account is : {:?}", &authority.pubkey());
-        create_mint_account(&client, &authority, &mint).await?;
+        create_mint_account(&sender, &authority, &mint).await?;
 
         println!("authority account is : {:?}", &authority.pubkey());
         println!("mint account is : {:?}", &mint.pubkey());
@@ -395,26 +776,26 @@ mod tests {
         println!("source_wallet account is : {:?}", &source_wallet.pubkey());
         println!("dest_wallet account is : {:?}", &dest_wallet.pubkey());
 
-        let source_ata = create_ata(&client, &source_wallet, &mint.pubkey()).await?;
-        let dest_ata = create_ata(&client, &dest_wallet, &mint.pubkey()).await?;
+        let source_ata = create_ata(&sender, &source_wallet, &mint.pubkey()).await?;
+        let dest_ata = create_ata(&sender, &dest_wallet, &mint.pubkey()).await?;
 
         let mint_amount = 1000;
         mint_to_ata(
-            &client,
+            &sender,
             &mint.pubkey(),
             &authority,
             &source_ata,
             mint_amount,
         )
         .await?;
-        mint_to_ata(&client, &mint.pubkey(), &authority, &dest_ata, mint_amount).await?;
+        mint_to_ata(&sender, &mint.pubkey(), &authority, &dest_ata, mint_amount).await?;
 
         println!("source_ata account is : {:?}", &source_ata);
         println!("dest_ata account is : {:?}", &dest_ata);
 
         let transfer_amount = 200;
         token_transfer(
-            &client,
+            &sender,
             &source_wallet,
             &mint.pubkey(),
             &source_ata,
@@ -437,28 +818,178 @@ mod tests {
 
     #[tokio::test]
     async fn test_only_create_account() -> Result<()> {
-        let client = common::get_rpc_client();
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let sender = TransactionSender::new(client.clone());
         let authority = common::get_local_key_pair().unwrap();
         let account = Keypair::new();
         let owner = token_2022_program_id();
         println!("authority account is : {:?}", &authority.pubkey());
-        only_create_account(&client, &authority, &account, &owner).await?;
+        only_create_account(&sender, &authority, &account, &owner).await?;
 
         Ok(())
     }
 
     #[tokio::test]
     async fn test_init_mint() -> Result<()> {
-        let client = common::get_rpc_client();
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let sender = TransactionSender::new(client.clone());
         let authority = common::get_local_key_pair().unwrap();
         let account = Keypair::new();
         println!("authority account is : {:?}", &authority.pubkey());
 
         let owner = token_2022_program_id();
-        only_create_account(&client, &authority, &account, &owner).await?;
+        only_create_account(&sender, &authority, &account, &owner).await?;
+
+        init_mint(&sender, &authority, &account).await?;
+
+        Ok(())
+    }
 
-        init_mint(&client, &authority, &account).await?;
+    #[test]
+    fn test_epoch_fee() {
+        let fee = |epoch: u64, basis_points: u16, maximum_fee: u64| TransferFee {
+            epoch: epoch.into(),
+            transfer_fee_basis_points: basis_points.into(),
+            maximum_fee: maximum_fee.into(),
+        };
+        let config = TransferFeeConfig {
+            older_transfer_fee: fee(0, 100, 1_000),
+            newer_transfer_fee: fee(10, 50, 20),
+            ..TransferFeeConfig::default()
+        };
+
+        assert_eq!(epoch_fee(None, 0, 1_000).unwrap(), 0);
+        // 1% before epoch 10, rounded up
+        assert_eq!(epoch_fee(Some(&config), 9, 1_001).unwrap(), 11);
+        // 0.5% from epoch 10, capped at 20
+        assert_eq!(epoch_fee(Some(&config), 10, 1_000).unwrap(), 5);
+        assert_eq!(epoch_fee(Some(&config), 10, 100_000).unwrap(), 20);
+    }
+
+    #[test]
+    fn test_fee_transfer_display() {
+        let transfer = FeeTransfer {
+            gross: 1_050,
+            fee: 50,
+            net: 1_000,
+            decimals: 2,
+            signature: Signature::default(),
+        };
+        assert_eq!(transfer.to_string(), "gross 10.5 = net 10 + fee 0.5");
+
+        let transfer = FeeTransfer {
+            decimals: 20,
+            ..transfer
+        };
+        assert_eq!(transfer.to_string(), "gross 1050 = net 1000 + fee 50");
+    }
+
+    #[test]
+    fn test_fee_withdrawal_counts_confirmed_chunks() {
+        let withdrawal = FeeWithdrawal::from_chunks(vec![
+            FeeChunk {
+                sources: vec![Pubkey::new_unique()],
+                amount: 10,
+                result: Ok(Signature::default()),
+            },
+            FeeChunk {
+                sources: vec![Pubkey::new_unique()],
+                amount: 20,
+                result: Err(anyhow!("blockhash not found")),
+            },
+        ]);
+        assert_eq!(withdrawal.amount, 10);
+        assert_eq!(withdrawal.signatures(), vec![Signature::default()]);
+        assert_eq!(
+            withdrawal.failed().map(|chunk| chunk.amount).sum::<u64>(),
+            20
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transfer_fee_lifecycle() -> Result<()> {
+        let client = Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?));
+        let authority = Arc::new(Keypair::new());
+        common::airdrop(&client, &authority, LAMPORTS_PER_SOL * 2).await?;
+        let service = TokenService::new(client.clone(), authority.clone());
+        let sender = TransactionSender::new(client.clone());
+
+        let mint = Keypair::new();
+        let builder = MintBuilder::new(&authority.pubkey(), &mint.pubkey(), &authority.pubkey(), 2)
+            .extension(MintExtension::TransferFeeConfig {
+                transfer_fee_config_authority: Some(authority.pubkey()),
+                withdraw_withheld_authority: Some(authority.pubkey()),
+                transfer_fee_basis_points: 100,
+                maximum_fee: 1_000,
+            });
+        service.send_mint_builder(builder, &mint, &[]).await?;
+
+        let source_wallet = Keypair::new();
+        let dest_wallet = Keypair::new();
+        common::airdrop(&client, &source_wallet, LAMPORTS_PER_SOL).await?;
+        common::airdrop(&client, &dest_wallet, LAMPORTS_PER_SOL).await?;
+        let source_ata = create_ata(&sender, &source_wallet, &mint.pubkey()).await?;
+        let dest_ata = create_ata(&sender, &dest_wallet, &mint.pubkey()).await?;
+        let treasury_ata = create_ata(&sender, &authority, &mint.pubkey()).await?;
+        mint_to_ata(&sender, &mint.pubkey(), &authority, &source_ata, 10_000).await?;
+
+        let first = transfer_checked_with_fee(
+            &sender,
+            &source_wallet,
+            &mint.pubkey(),
+            &source_ata,
+            &dest_ata,
+            1_000,
+        )
+        .await?;
+        assert_eq!((first.gross, first.fee, first.net), (1_000, 10, 990));
+        let second = transfer_checked_with_fee(
+            &sender,
+            &source_wallet,
+            &mint.pubkey(),
+            &source_ata,
+            &dest_ata,
+            2_000,
+        )
+        .await?;
+
+        let withheld = accounts_with_withheld_fees(&client, &mint.pubkey()).await?;
+        assert_eq!(withheld, vec![(dest_ata, first.fee + second.fee)]);
+
+        // half the way: harvest into the mint, then withdraw from the mint
+        let harvested =
+            harvest_withheld_tokens_to_mint(&sender, &authority, &mint.pubkey(), &[dest_ata])
+                .await?;
+        assert!(harvested.iter().all(|chunk| chunk.result.is_ok()));
+        let from_mint =
+            withdraw_withheld_tokens_from_mint(&sender, &authority, &mint.pubkey(), &treasury_ata)
+                .await?;
+        assert_eq!(from_mint.amount, first.fee + second.fee);
+
+        // the other way: withdraw straight from the token account
+        let third = transfer_checked_with_fee(
+            &sender,
+            &source_wallet,
+            &mint.pubkey(),
+            &source_ata,
+            &dest_ata,
+            500,
+        )
+        .await?;
+        let from_accounts = withdraw_withheld_tokens_from_accounts(
+            &sender,
+            &authority,
+            &mint.pubkey(),
+            &treasury_ata,
+            &[dest_ata],
+        )
+        .await?;
+        assert_eq!(from_accounts.amount, third.fee);
 
+        let treasury = StateWithExtensionsOwned::<Account>::unpack(
+            client.get_account_data(&treasury_ata).await?,
+        )?;
+        assert_eq!(treasury.base.amount, first.fee + second.fee + third.fee);
         Ok(())
     }
 }
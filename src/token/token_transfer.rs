@@ -1,12 +1,12 @@
+use crate::token::mint_cache::fetch_mint_info;
 use anyhow::{Ok, Result};
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::{
-    program_pack::Pack,
     pubkey::Pubkey,
     signature::{Keypair, Signer},
     transaction::Transaction,
 };
-use spl_token_2022::{id as token_2022_program_id, instruction::transfer_checked};
+use spl_token_2022::instruction::transfer_checked;
 
 async fn token_transfer(
     client: &RpcClient,
@@ -17,19 +17,23 @@ async fn token_transfer(
     amount: u64,
 ) -> Result<()> {
     println!("----------------------begin token_transfer------------------------------\n");
+    // transfer_checked fails unless the decimals match the mint,
+    // and the mint may belong to SPL Token or Token-2022
+    let mint_info = fetch_mint_info(client, mint_pubkey).await?;
+
     // Get the latest blockhash for the transfer transaction
     let recent_blockhash = client.get_latest_blockhash().await?;
 
     // Create transfer_checked instruction to send tokens from source to destination
     let transfer_instruction = transfer_checked(
-        &token_2022_program_id(), // program id
+        &mint_info.program.id(),  // program id
         source_pubkey,            // source
         mint_pubkey,              // mint
         destination_pubkey,       // destination
         &authority.pubkey(),      // owner of source
         &[&authority.pubkey()],   // signers
         amount,                   // amount
-        2,                        // decimals
+        mint_info.state.decimals, // decimals
     )?;
 
     // Create transaction for transferring tokens
@@ -51,15 +55,18 @@ async fn token_transfer(
     Ok(())
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
     use crate::common;
+    use crate::config::ClusterConfig;
     use crate::token::comm;
+    use solana_sdk::program_pack::Pack;
     use spl_token_2022::state::Account;
 
     #[tokio::test]
     async fn test_token_transfer() -> Result<()> {
-        let client = common::get_rpc_client();
+        let client = common::get_rpc_client(&ClusterConfig::resolve()?);
         let authority = common::get_local_key_pair().unwrap();
         let mint_pubkey = Pubkey::from_str_const(comm::MINT_PUBKEY);
         let source_ata = Pubkey::from_str_const(comm::SOURCE_ATA_PUBKEY);
@@ -1,34 +1,36 @@
+use crate::budget::{ComputeBudgetConfig, estimate_compute_budget};
+use crate::memo::Memo;
+use crate::sender::{SendOutcome, TransactionSender};
+use crate::simulate::simulate_transaction;
+use anyhow::anyhow;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::{
-    compute_budget, signature::Keypair, signer::Signer, system_instruction::transfer,
-    transaction::Transaction,
+    signature::Keypair, signer::Signer, system_instruction::transfer, transaction::Transaction,
 };
-use spl_memo::build_memo;
 
+/// Transfer lamports, retrying with a fresh blockhash until confirmed or failed
 pub async fn send_sol(
-    client: &RpcClient,
+    sender: &TransactionSender,
     from_keypair: &Keypair,
     to_pub_key: &Pubkey,
     lamport: u64,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<SendOutcome> {
     let transfer_ix = transfer(&from_keypair.pubkey(), to_pub_key, lamport);
-    let mut transaction = Transaction::new_with_payer(&[transfer_ix], Some(&from_keypair.pubkey()));
-    transaction.sign(&[&from_keypair], client.get_latest_blockhash().await?);
-    match client.send_and_confirm_transaction(&transaction).await {
-        Ok(signature) => println!("Transaction Signature: {}", signature),
-        Err(err) => eprintln!("Error sending transaction: {}", err),
-    }
-    Ok(())
+    let outcome = sender
+        .send(&[transfer_ix], &from_keypair.pubkey(), &[from_keypair])
+        .await?;
+    println!("Transaction Signature: {}", outcome.signature);
+    Ok(outcome)
 }
 
 async fn estimate_cu_used(client: &RpcClient, tx: &Transaction) -> anyhow::Result<u64> {
-    let sim_res = client.simulate_transaction(tx).await?;
+    let report = simulate_transaction(client, tx, &[]).await?;
+    report.ensure_success()?;
 
-    let units_consumed = sim_res
-        .value
+    let units_consumed = report
         .units_consumed
-        .expect("couldn't estimate CUs used");
+        .ok_or_else(|| anyhow!("simulation didn't report units consumed"))?;
 
     println!("Simulated units consumed: {}", units_consumed);
 
@@ -40,7 +42,9 @@ async fn add_memo_to_transaction(
     signer_keypair: &Keypair,
     memo: &str,
 ) -> anyhow::Result<Transaction> {
-    let memo_ix = build_memo(memo.as_bytes(), &[&signer_keypair.pubkey()]);
+    let memo_ix = Memo::new(memo)
+        .signed_by(signer_keypair.pubkey())
+        .instruction();
     let mut transaction = Transaction::new_with_payer(&[memo_ix], Some(&signer_keypair.pubkey()));
     transaction.sign(&[&signer_keypair], client.get_latest_blockhash().await?);
     Ok(transaction)
@@ -52,31 +56,40 @@ async fn add_priority_fees_to_transaction(
     to_keypair: &Keypair,
     lamport: u64,
 ) -> anyhow::Result<Transaction> {
-    let modify_cu_ix = compute_budget::ComputeBudgetInstruction::set_compute_unit_limit(1_000_000);
-    let add_priority_fee_ix = compute_budget::ComputeBudgetInstruction::set_compute_unit_price(1);
-
-    let transfer_ix = transfer(&signer_keypair.pubkey(), &to_keypair.pubkey(), lamport);
-
-    let mut transaction = Transaction::new_with_payer(
-        &[modify_cu_ix, add_priority_fee_ix, transfer_ix],
-        Some(&signer_keypair.pubkey()),
-    );
+    let instructions = [transfer(
+        &signer_keypair.pubkey(),
+        &to_keypair.pubkey(),
+        lamport,
+    )];
+    let budget = estimate_compute_budget(
+        client,
+        &instructions,
+        &signer_keypair.pubkey(),
+        &[],
+        &ComputeBudgetConfig::default(),
+    )
+    .await?;
+
+    let mut transaction =
+        Transaction::new_with_payer(&budget.apply(&instructions), Some(&signer_keypair.pubkey()));
     transaction.sign(&[&signer_keypair], client.get_latest_blockhash().await?);
 
     Ok(transaction)
 }
 
 #[cfg(test)]
+#[allow(clippy::identity_op)]
 mod test {
     use super::*;
     use crate::common;
+    use crate::config::ClusterConfig;
     use solana_sdk::native_token::LAMPORTS_PER_SOL;
+    use std::sync::Arc;
 
     #[tokio::test]
     async fn test_sol_transfer() -> anyhow::Result<()> {
-        let client = crate::common::get_rpc_client();
-        let keypair_path = "/home/gidon/.config/solana/id.json".to_string();
-        let from = common::get_key_pair_from_local_json(&keypair_path).unwrap();
+        let client = common::get_rpc_client(&ClusterConfig::resolve()?);
+        let from = common::get_local_key_pair()?;
         let pub_key_str = "Cw1Q5ugnmkqhkeGu9y9QaGi1b837HiZtMrXFfNimxYXe";
         let to_pub_key = Pubkey::from_str_const(pub_key_str);
 
@@ -84,9 +97,9 @@ mod test {
 
         let before_balance = client.get_balance(&to_pub_key).await?;
 
-        send_sol(&client, &from, &to_pub_key, lamports)
-            .await
-            .unwrap();
+        let sender =
+            TransactionSender::new(Arc::new(common::get_rpc_client(&ClusterConfig::resolve()?)));
+        send_sol(&sender, &from, &to_pub_key, lamports).await?;
 
         let after_balance = client.get_balance(&to_pub_key).await?;
 